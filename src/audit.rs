@@ -0,0 +1,122 @@
+//! Audit trail of locking, unlocking and failed attempts.
+//!
+//! Events are logged. With the `journald` feature, they are sent to the
+//! systemd journal instead, with structured fields to query them by:
+//!
+//! - `PINLOCK_EVENT`: `locked`, `unlocked`, `failed_attempt` or `panic_wipe`
+//! - `PINLOCK_USER`: the user whose session is locked
+//! - `PINLOCK_FAILED_ATTEMPTS`: wrong PINs since locking
+//! - `PINLOCK_UNLOCK_REASON`: `pin`, `grace`, `signal`, `signal_unlock` or
+//!   `requested`
+//!
+//! The journal timestamps each entry itself. Entered PINs are never part of
+//! an event, and unlocking with a duress PIN is recorded like unlocking with
+//! a regular one, so that the journal doesn't give it away.
+
+use log::info;
+
+use crate::state::UnlockReason;
+
+/// Something worth an entry in the audit trail.
+pub enum Event<'a> {
+    Locked,
+    Unlocked {
+        reason: &'a UnlockReason,
+        failed_attempts: u32,
+    },
+    FailedAttempt {
+        failed_attempts: u32,
+    },
+    /// `panic_wipe_command` was started.
+    PanicWipe {
+        failed_attempts: u32,
+    },
+}
+
+impl Event<'_> {
+    fn message(&self) -> String {
+        match self {
+            Self::Locked => "Screen locked".to_owned(),
+            Self::Unlocked {
+                failed_attempts, ..
+            } => format!(
+                "Screen unlocked ({}) after {failed_attempts} failed attempts",
+                self.unlock_reason().unwrap_or_default()
+            ),
+            Self::FailedAttempt { failed_attempts } => {
+                format!("Wrong PIN entered, {failed_attempts} failed attempts since locking")
+            }
+            Self::PanicWipe { failed_attempts } => {
+                format!("PANIC WIPE triggered after {failed_attempts} failed attempts")
+            }
+        }
+    }
+
+    #[cfg(feature = "journald")]
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        let (name, failed_attempts) = match *self {
+            Self::Locked => ("locked", None),
+            Self::Unlocked {
+                failed_attempts, ..
+            } => ("unlocked", Some(failed_attempts)),
+            Self::FailedAttempt { failed_attempts } => ("failed_attempt", Some(failed_attempts)),
+            Self::PanicWipe { failed_attempts } => ("panic_wipe", Some(failed_attempts)),
+        };
+
+        let mut fields = vec![("PINLOCK_EVENT", name.to_owned()), ("PINLOCK_USER", user())];
+        if let Some(failed_attempts) = failed_attempts {
+            fields.push(("PINLOCK_FAILED_ATTEMPTS", failed_attempts.to_string()));
+        }
+        if let Some(reason) = self.unlock_reason() {
+            fields.push(("PINLOCK_UNLOCK_REASON", reason.to_owned()));
+        }
+        fields
+    }
+
+    fn unlock_reason(&self) -> Option<&'static str> {
+        let Self::Unlocked { reason, .. } = self else {
+            return None;
+        };
+        Some(match reason {
+            UnlockReason::Authenticated | UnlockReason::Duress { .. } => "pin",
+            UnlockReason::Grace => "grace",
+            UnlockReason::Signal => "signal",
+            UnlockReason::SignalUnlock => "signal_unlock",
+            #[cfg(feature = "logind")]
+            UnlockReason::Requested => "requested",
+        })
+    }
+}
+
+/// Record an event. Sending it to the journal happens on a thread of its own
+/// and falls back to logging, so that the journal never holds up or breaks
+/// the lock.
+pub fn record(event: Event) {
+    let message = event.message();
+
+    #[cfg(feature = "journald")]
+    {
+        use libsystemd::logging::{self, Priority};
+
+        let mut fields = event.fields();
+        fields.push(("SYSLOG_IDENTIFIER", "pinlock".to_owned()));
+        std::thread::spawn(move || {
+            if let Err(err) = logging::journal_send(Priority::Notice, &message, fields.into_iter())
+            {
+                log::warn!("Failed to write to the journal: {err}");
+                info!("{message}");
+            }
+        });
+    }
+
+    #[cfg(not(feature = "journald"))]
+    info!("{message}");
+}
+
+#[cfg(feature = "journald")]
+fn user() -> String {
+    std::env::var("USER").unwrap_or_else(|_| {
+        // SAFETY: always safe to call
+        unsafe { libc::getuid() }.to_string()
+    })
+}
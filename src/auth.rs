@@ -0,0 +1,802 @@
+use std::{
+    fmt,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, TryRecvError},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use log::error;
+use serde::Deserialize;
+use subtle::ConstantTimeEq;
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::{config::Config, random, state::UnlockReason};
+
+/// Longest wait for `auth_command` or `otp_command` unless configured
+/// otherwise with `auth_timeout_secs`, after which the PIN counts as wrong
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A PIN that unlocks the screen, and what else happens when it is used.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Pin {
+    pub pin: ProtectedSecret,
+    /// Makes this a duress PIN: it unlocks like any other, but this command
+    /// is run in the background afterwards.
+    #[serde(default)]
+    pub duress_command: Option<String>,
+}
+
+impl Pin {
+    pub fn new(pin: String) -> Self {
+        Self {
+            pin: pin.into(),
+            duress_command: None,
+        }
+    }
+
+    /// Compare the input in constant time with respect to its content, so
+    /// that a matching prefix fails no slower than a wrong first digit.
+    pub fn verify(&self, input: &str) -> bool {
+        self.pin.matches(input)
+    }
+
+    fn unlock_reason(&self) -> UnlockReason {
+        match &self.duress_command {
+            Some(command) => UnlockReason::Duress {
+                command: command.clone(),
+            },
+            None => UnlockReason::Authenticated,
+        }
+    }
+}
+
+/// A secret stored XORed with a random pad of its length, so that it isn't
+/// found as is in a core dump or by scanning memory. Both halves are wiped
+/// when dropped.
+#[derive(Clone, Deserialize)]
+#[serde(from = "String")]
+pub struct ProtectedSecret {
+    masked: Zeroizing<Vec<u8>>,
+    pad: Zeroizing<Vec<u8>>,
+}
+
+impl ProtectedSecret {
+    /// Call `reveal` with the secret, restored into a scratch buffer that is
+    /// wiped right after.
+    pub fn with_plaintext<T>(&self, reveal: impl FnOnce(&[u8]) -> T) -> T {
+        let plaintext: Zeroizing<Vec<u8>> = Zeroizing::new(
+            self.masked
+                .iter()
+                .zip(self.pad.iter())
+                .map(|(masked, pad)| masked ^ pad)
+                .collect(),
+        );
+        reveal(&plaintext)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.masked.is_empty()
+    }
+
+    /// Compare `input` with the secret in constant time with respect to
+    /// its content.
+    pub fn matches(&self, input: &str) -> bool {
+        self.with_plaintext(|secret| secret.ct_eq(input.as_bytes()).into())
+    }
+}
+
+/// Takes over the secret, wiping the string it came in.
+impl From<String> for ProtectedSecret {
+    fn from(mut secret: String) -> Self {
+        let mut pad = Zeroizing::new(vec![0; secret.len()]);
+        if let Err(err) = random::os_fill(&mut pad) {
+            error!("Failed to draw a pad for a secret, storing it as is: {err}");
+        }
+        let masked = Zeroizing::new(
+            secret
+                .bytes()
+                .zip(pad.iter())
+                .map(|(byte, pad)| byte ^ pad)
+                .collect(),
+        );
+        secret.zeroize();
+        Self { masked, pad }
+    }
+}
+
+impl fmt::Debug for ProtectedSecret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProtectedSecret(..)")
+    }
+}
+
+/// Find the PIN matching the input. Every PIN is compared, so the time taken
+/// does not reveal which one matched.
+pub fn verify(pins: &[Pin], input: &str) -> Option<UnlockReason> {
+    let mut matched = None;
+    for pin in pins {
+        if pin.verify(input) && matched.is_none() {
+            matched = Some(pin);
+        }
+    }
+    matched.map(Pin::unlock_reason)
+}
+
+/// The PIN printed by `otp_command`, which changes every `window` counted
+/// from the Unix epoch, like a TOTP code. Fetched when locking, and again
+/// when a PIN doesn't match it and a window has started since.
+pub struct OneTimePin {
+    command: String,
+    window: Duration,
+    /// Longest wait for the command
+    timeout: Duration,
+    /// The last PIN printed, and the window it was printed in
+    fetched: Mutex<Option<(u64, ProtectedSecret)>>,
+}
+
+impl OneTimePin {
+    pub fn new(command: &str, window: Duration, timeout: Duration) -> Self {
+        let pin = Self {
+            command: command.to_owned(),
+            window,
+            timeout,
+            fetched: Mutex::new(None),
+        };
+        pin.fetch();
+        pin
+    }
+
+    /// Whether `input` is the current PIN.
+    pub fn verify(&self, input: &str) -> bool {
+        let matches = |fetched: &Option<(u64, ProtectedSecret)>| {
+            fetched.as_ref().is_some_and(|(_, pin)| pin.matches(input))
+        };
+        if matches(&self.fetched()) {
+            return true;
+        }
+        let stale = self
+            .fetched()
+            .as_ref()
+            .is_none_or(|&(window, _)| window != self.current_window());
+        stale && {
+            self.fetch();
+            matches(&self.fetched())
+        }
+    }
+
+    /// The last PIN printed. Still usable after a verification on another
+    /// thread panicked, it is only ever replaced whole.
+    fn fetched(&self) -> MutexGuard<'_, Option<(u64, ProtectedSecret)>> {
+        self.fetched.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn fetch(&self) {
+        let window = self.current_window();
+        let fetched = match fetch_one_time_pin(&self.command, self.timeout) {
+            Ok(pin) => Some((window, pin)),
+            Err(err) => {
+                error!("Failed to fetch the PIN with otp_command: {err:#}");
+                None
+            }
+        };
+        *self.fetched() = fetched;
+    }
+
+    fn current_window(&self) -> u64 {
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        window_of(since_epoch, self.window)
+    }
+}
+
+/// The number of the `window` that `since_epoch` falls into.
+fn window_of(since_epoch: Duration, window: Duration) -> u64 {
+    (since_epoch.as_millis() / window.as_millis().max(1)) as u64
+}
+
+/// One of the factors a [`Policy`] asks for.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Factor {
+    /// `"pin"`: the entered PIN or the unlock pattern, checked like without
+    /// a policy
+    Pin,
+    /// `"usb-key"`: `usb_key_file` exists, as on a USB stick being plugged
+    /// in
+    UsbKey,
+    /// `"command:..."`: a shell command exits with 0, such as one asking a
+    /// YubiKey for a challenge-response. It doesn't get the PIN, and is
+    /// given up on like `auth_command` after `auth_timeout_secs`.
+    Command(String),
+}
+
+impl TryFrom<String> for Factor {
+    type Error = String;
+
+    fn try_from(factor: String) -> Result<Self, Self::Error> {
+        match factor.as_str() {
+            "pin" => Ok(Self::Pin),
+            "usb-key" => Ok(Self::UsbKey),
+            _ => match factor.strip_prefix("command:") {
+                Some(command) => Ok(Self::Command(command.to_owned())),
+                None => Err(format!(
+                    "Unknown factor `{factor}`, expected `pin`, `usb-key` or `command:...`"
+                )),
+            },
+        }
+    }
+}
+
+/// Which factors unlock: `{ all = [...] }` needs each of them, `{ any =
+/// [...] }` one of them. Either lists factors or policies of their own, as
+/// in `{ all = ["pin", { any = ["usb-key", "command:..."] }] }`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase", deny_unknown_fields)]
+pub enum Policy {
+    All(Vec<Requirement>),
+    Any(Vec<Requirement>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum Requirement {
+    Factor(Factor),
+    Policy(Policy),
+}
+
+impl Policy {
+    fn requirements(&self) -> &[Requirement] {
+        match self {
+            Self::All(requirements) | Self::Any(requirements) => requirements,
+        }
+    }
+
+    /// Every factor the policy asks for, nested ones included.
+    pub fn factors(&self) -> Vec<&Factor> {
+        self.requirements()
+            .iter()
+            .flat_map(|requirement| match requirement {
+                Requirement::Factor(factor) => vec![factor],
+                Requirement::Policy(policy) => policy.factors(),
+            })
+            .collect()
+    }
+
+    /// Refuse empty lists, which `all` would take as met.
+    pub fn validate(&self) -> Result<()> {
+        if self.requirements().is_empty() {
+            bail!("`all` and `any` need at least one factor");
+        }
+        for requirement in self.requirements() {
+            if let Requirement::Policy(policy) = requirement {
+                policy.validate()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the factors `met` make up the policy. Every factor is
+    /// checked, also once the outcome is known, so that how long it takes
+    /// doesn't tell which one failed.
+    fn holds(&self, met: &mut impl FnMut(&Factor) -> bool) -> bool {
+        let results: Vec<bool> = self
+            .requirements()
+            .iter()
+            .map(|requirement| match requirement {
+                Requirement::Factor(factor) => met(factor),
+                Requirement::Policy(policy) => policy.holds(met),
+            })
+            .collect();
+        match self {
+            Self::All(_) => results.iter().all(|&result| result),
+            Self::Any(_) => results.iter().any(|&result| result),
+        }
+    }
+}
+
+/// Decides whether an entered PIN unlocks the screen. Holds copies of what
+/// it checks against, so that a copy can check on a thread of its own, see
+/// [`Self::spawn_verify`].
+#[derive(Clone)]
+pub struct Authenticator {
+    pins: Vec<Pin>,
+    /// Asked when none of the PINs match
+    command: Option<String>,
+    /// Compared when none of the PINs match, before asking the command
+    one_time_pin: Option<Arc<OneTimePin>>,
+    /// The dots of the unlock pattern, checked on their own
+    pattern: Option<ProtectedSecret>,
+    /// The factors needed besides, or instead of, the PIN
+    require: Option<Policy>,
+    usb_key_file: Option<PathBuf>,
+    /// Longest wait for a command, after which it counts as a no
+    timeout: Duration,
+    /// Least time a wrong PIN takes on a thread of its own, see
+    /// [`pad_response`]
+    response: Option<Duration>,
+}
+
+impl Authenticator {
+    pub fn new(pins: &[Pin], command: Option<&str>) -> Self {
+        Self {
+            pins: pins.to_vec(),
+            command: command.map(str::to_owned),
+            one_time_pin: None,
+            pattern: None,
+            require: None,
+            usb_key_file: None,
+            timeout: COMMAND_TIMEOUT,
+            response: None,
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(&config.pins, config.auth_command.as_deref())
+            .with_pattern(config.pattern.as_ref())
+            .with_policy(config.require.as_ref(), config.usb_key_file.as_deref())
+            .with_timeout(config.auth_timeout())
+            .with_response(config.constant_time_response())
+    }
+
+    /// Give up on commands after `timeout`, taking that as a wrong PIN or a
+    /// factor that isn't met, never as an unlock.
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self { timeout, ..self }
+    }
+
+    /// Have wrong PINs checked with [`Self::spawn_verify`] take `response`
+    /// at least, with `constant_time_response_ms`.
+    pub fn with_response(self, response: Option<Duration>) -> Self {
+        Self { response, ..self }
+    }
+
+    /// Unlock only as `require` allows, a correct PIN being the `pin`
+    /// factor. Without one, a correct PIN is all it takes.
+    pub fn with_policy(self, require: Option<&Policy>, usb_key_file: Option<&Path>) -> Self {
+        Self {
+            require: require.cloned(),
+            usb_key_file: usb_key_file.map(Path::to_owned),
+            ..self
+        }
+    }
+
+    /// Also accept the unlock pattern of `pattern`, with
+    /// [`Self::verify_pattern`].
+    pub fn with_pattern(self, pattern: Option<&ProtectedSecret>) -> Self {
+        Self {
+            pattern: pattern.cloned(),
+            ..self
+        }
+    }
+
+    /// Also accept the PIN of `otp_command`.
+    pub fn with_one_time_pin(self, one_time_pin: Option<OneTimePin>) -> Self {
+        Self {
+            one_time_pin: one_time_pin.map(Arc::new),
+            ..self
+        }
+    }
+
+    /// Check `input` like [`Self::verify`], on a thread of its own so that
+    /// the lock screen keeps drawing and taking input meanwhile.
+    pub fn spawn_verify(&self, input: Zeroizing<String>) -> Verification {
+        self.spawn(move |auth| auth.verify(&input))
+    }
+
+    /// Check the dots of the unlock pattern like [`Self::verify_pattern`],
+    /// on a thread of its own.
+    pub fn spawn_verify_pattern(&self, dots: Zeroizing<String>) -> Verification {
+        self.spawn(move |auth| auth.verify_pattern(&dots))
+    }
+
+    fn spawn(
+        &self,
+        check: impl FnOnce(&Self) -> Option<UnlockReason> + Send + 'static,
+    ) -> Verification {
+        let auth = self.clone();
+        let started = Instant::now();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let reason = check(&auth);
+            if let (None, Some(response)) = (&reason, auth.response) {
+                pad_response(started, response);
+            }
+            // Given up on by then if it took too long
+            let _ = sender.send(reason);
+        });
+        // The padding is up to a quarter longer than the response
+        let padding = self
+            .response
+            .map_or(Duration::ZERO, |response| response + response / 4);
+        Verification {
+            result: receiver,
+            started,
+            deadline: started + self.timeout + padding,
+        }
+    }
+
+    /// Check the input against the PINs and the one-time PIN, then with the
+    /// command, and the other factors of the policy.
+    pub fn verify(&self, input: &str) -> Option<UnlockReason> {
+        let reason = self.verify_pin(input);
+        self.required(reason)
+    }
+
+    fn verify_pin(&self, input: &str) -> Option<UnlockReason> {
+        let reason = verify(&self.pins, input).or_else(|| {
+            self.one_time_pin
+                .as_ref()
+                .filter(|pin| pin.verify(input))
+                .map(|_| UnlockReason::Authenticated)
+        });
+        let Some(command) = self.command.as_deref().filter(|_| reason.is_none()) else {
+            return reason;
+        };
+        match verify_with_command(command, input, self.timeout) {
+            Ok(true) => Some(UnlockReason::Authenticated),
+            Ok(false) => None,
+            Err(err) => {
+                error!("Failed to verify the PIN with auth_command: {err:#}");
+                None
+            }
+        }
+    }
+
+    /// Check the tapped dots of the unlock pattern, in constant time with
+    /// respect to their order. A pattern never counts as a PIN or the other
+    /// way around.
+    pub fn verify_pattern(&self, dots: &str) -> Option<UnlockReason> {
+        let reason = self
+            .pattern
+            .as_ref()
+            .filter(|pattern| pattern.matches(dots))
+            .map(|_| UnlockReason::Authenticated);
+        self.required(reason)
+    }
+
+    /// Whether the policy holds with the outcome of the PIN, `reason`, and
+    /// what unlocking is then for. All it says otherwise is no, whichever
+    /// factor failed.
+    fn required(&self, reason: Option<UnlockReason>) -> Option<UnlockReason> {
+        let Some(policy) = &self.require else {
+            return reason;
+        };
+        let holds = policy.holds(&mut |factor| match factor {
+            Factor::Pin => reason.is_some(),
+            Factor::UsbKey => self.usb_key_file.as_deref().is_some_and(Path::exists),
+            Factor::Command(command) => {
+                check_with_command(command, self.timeout).unwrap_or_else(|err| {
+                    error!("Failed to check a factor of require: {err:#}");
+                    false
+                })
+            }
+        });
+        holds.then(|| reason.unwrap_or(UnlockReason::Authenticated))
+    }
+}
+
+/// A PIN or pattern being checked on a thread of its own, by
+/// [`Authenticator::spawn_verify`]. Given up on once the checking took
+/// longer than the command timeout allows, a hung command that wasn't
+/// killed for good being left to itself.
+pub struct Verification {
+    result: Receiver<Option<UnlockReason>>,
+    started: Instant,
+    deadline: Instant,
+}
+
+impl Verification {
+    /// The outcome once the check is over, the reason to unlock or `None`
+    /// for a wrong PIN, which is also what a check that was given up on or
+    /// failed counts as. `None` while it is still going.
+    pub fn poll(&self) -> Option<Option<UnlockReason>> {
+        match self.result.try_recv() {
+            Ok(reason) => Some(reason),
+            Err(TryRecvError::Empty) if Instant::now() < self.deadline => None,
+            Err(TryRecvError::Empty) => {
+                self.give_up();
+                Some(None)
+            }
+            Err(TryRecvError::Disconnected) => {
+                error!("Checking the PIN failed, counting it as wrong");
+                Some(None)
+            }
+        }
+    }
+
+    /// Wait for the outcome of [`Self::poll`].
+    pub fn wait(&self) -> Option<UnlockReason> {
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        match self.result.recv_timeout(remaining) {
+            Ok(reason) => reason,
+            Err(RecvTimeoutError::Timeout) => {
+                self.give_up();
+                None
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                error!("Checking the PIN failed, counting it as wrong");
+                None
+            }
+        }
+    }
+
+    /// Time since the check started.
+    #[cfg(feature = "metrics")]
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    fn give_up(&self) {
+        error!(
+            "Checking the PIN took longer than {:?}, counting it as wrong",
+            self.deadline - self.started
+        );
+    }
+}
+
+/// Run `command` for a factor of the policy, which is met by exiting with 0.
+fn check_with_command(command: &str, timeout: Duration) -> Result<bool> {
+    let mut child = Command::new("sh")
+        .args(["-c", command])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to run `{command}`"))?;
+    Ok(wait_for(&mut child, command, timeout)?.success())
+}
+
+/// Ask `command` whether `input` unlocks, by writing it to the command's
+/// stdin followed by a newline. Never passed as an argument, where other
+/// users could see it. Exiting with 0 means yes.
+fn verify_with_command(command: &str, input: &str, timeout: Duration) -> Result<bool> {
+    let mut child = Command::new("sh")
+        .args(["-c", command])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to run `{command}`"))?;
+
+    // Dropped right after, so that the command sees the end of its input
+    let mut stdin = child.stdin.take().context("The input isn't piped")?;
+    let written = stdin
+        .write_all(input.as_bytes())
+        .and_then(|()| stdin.write_all(b"\n"));
+    drop(stdin);
+    if let Err(err) = written {
+        let _ = child.kill();
+        let _ = child.wait();
+        return Err(err).context("Failed to pass the PIN");
+    }
+
+    Ok(wait_for(&mut child, command, timeout)?.success())
+}
+
+/// Run `command` for the current one-time PIN, the first line it prints.
+/// The output is read on a thread as it comes, so that a command printing
+/// more than the pipe holds isn't stuck writing until the timeout. What it
+/// printed is wiped once read.
+fn fetch_one_time_pin(command: &str, timeout: Duration) -> Result<ProtectedSecret> {
+    let mut child = Command::new("sh")
+        .args(["-c", command])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run `{command}`"))?;
+
+    let deadline = Instant::now() + timeout;
+    let mut stdout = child.stdout.take().context("The output isn't piped")?;
+    let (sender, receiver) = mpsc::channel();
+    // Left behind if the output never ends, such as when a process the
+    // command started keeps it open
+    thread::spawn(move || {
+        let mut output = Zeroizing::new(Vec::new());
+        let read = stdout.read_to_end(&mut output).map(|_| output);
+        let _ = sender.send(read);
+    });
+    let read = receiver.recv_timeout(timeout);
+    let Ok(Ok(mut output)) = read else {
+        let _ = child.kill();
+        let _ = child.wait();
+        return match read {
+            Ok(Err(err)) => Err(err).context(format!("Failed to read the output of `{command}`")),
+            _ => Err(anyhow!("`{command}` didn't finish within {timeout:?}")),
+        };
+    };
+
+    let status = wait_for(
+        &mut child,
+        command,
+        deadline.saturating_duration_since(Instant::now()),
+    )?;
+    if !status.success() {
+        bail!("`{command}` failed with {status}");
+    }
+    let mut pin = String::from_utf8(std::mem::take(&mut *output)).map_err(|err| {
+        let mut bytes = err.into_bytes();
+        bytes.zeroize();
+        anyhow!("`{command}` printed something other than UTF-8")
+    })?;
+    pin.truncate(pin.lines().next().unwrap_or_default().len());
+    if pin.is_empty() {
+        bail!("`{command}` printed no PIN");
+    }
+    Ok(pin.into())
+}
+
+/// Wait for `child`, running `command`, to exit, killing it once
+/// `timeout` has passed.
+fn wait_for(child: &mut Child, command: &str, timeout: Duration) -> Result<ExitStatus> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            bail!("`{command}` didn't finish within {timeout:?}");
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Sleep until `response` and up to a quarter more of it passed since
+/// `started`, drawn from the operating system's CSPRNG. A response that
+/// already took longer, such as one waiting for `auth_command`, isn't
+/// delayed further.
+pub fn pad_response(started: Instant, response: Duration) {
+    let spread = response.as_micros() as u64 / 4;
+    let jitter = match random::os_u64() {
+        Ok(random) if spread > 0 => random % spread,
+        _ => 0,
+    };
+    let deadline = started + response + Duration::from_micros(jitter);
+    if let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        thread::sleep(remaining);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+
+    #[test]
+    fn a_protected_secret_is_only_stored_masked() {
+        let secret = ProtectedSecret::from("correct horse battery staple".to_owned());
+        assert_ne!(secret.masked.as_slice(), b"correct horse battery staple");
+        assert_eq!(
+            secret.with_plaintext(<[u8]>::to_vec),
+            b"correct horse battery staple"
+        );
+    }
+
+    #[test]
+    fn a_matching_prefix_is_not_a_match() {
+        let pin = Pin::new("1234".into());
+        assert!(pin.verify("1234"));
+        assert!(!pin.verify("123"));
+        assert!(!pin.verify("12345"));
+        assert!(!pin.verify(""));
+    }
+
+    #[test]
+    fn the_one_time_pin_is_the_first_line_of_the_command() {
+        let pin = OneTimePin::new(
+            "printf '424242\\nunused'",
+            Duration::from_secs(30),
+            COMMAND_TIMEOUT,
+        );
+        assert!(pin.verify("424242"));
+        assert!(!pin.verify("424242\nunused"));
+        assert!(!pin.verify("123456"));
+
+        let pins = [Pin::new("1234".into())];
+        let auth = Authenticator::new(&pins, None).with_one_time_pin(Some(pin));
+        assert_eq!(auth.verify("424242"), Some(UnlockReason::Authenticated));
+        assert_eq!(auth.verify("1234"), Some(UnlockReason::Authenticated));
+        assert_eq!(auth.verify("0000"), None);
+    }
+
+    #[test]
+    fn the_one_time_pin_is_read_while_the_command_prints() {
+        // Far more than a pipe holds
+        let command = "echo 424242; head -c 1000000 /dev/zero";
+        let pin = fetch_one_time_pin(command, COMMAND_TIMEOUT).unwrap();
+        assert!(pin.matches("424242"));
+    }
+
+    #[test]
+    fn windows_are_counted_from_the_epoch() {
+        let window = Duration::from_secs(30);
+        assert_eq!(window_of(Duration::from_secs(59), window), 1);
+        assert_eq!(window_of(Duration::from_secs(60), window), 2);
+    }
+
+    #[test]
+    fn a_response_is_padded_to_the_configured_time() {
+        let started = Instant::now();
+        pad_response(started, Duration::from_millis(20));
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(20));
+        assert!(elapsed < Duration::from_millis(200));
+    }
+
+    #[test]
+    fn patterns_and_pins_are_apart() {
+        let pins = [Pin::new("1234".into())];
+        let pattern = ProtectedSecret::from("14789".to_owned());
+        let auth = Authenticator::new(&pins, None).with_pattern(Some(&pattern));
+        assert_eq!(
+            auth.verify_pattern("14789"),
+            Some(UnlockReason::Authenticated)
+        );
+        assert_eq!(auth.verify_pattern("98741"), None);
+        assert_eq!(auth.verify_pattern("1234"), None);
+        assert_eq!(auth.verify("14789"), None);
+    }
+
+    #[test]
+    fn the_command_reads_the_pin_from_stdin_when_no_pin_matches() {
+        let pins = [Pin::new("1234".into())];
+        let auth = Authenticator::new(&pins, Some(r#"read pin && [ "$pin" = 0000 ]"#));
+        assert_eq!(auth.verify("1234"), Some(UnlockReason::Authenticated));
+        assert_eq!(auth.verify("0000"), Some(UnlockReason::Authenticated));
+        assert_eq!(auth.verify("5678"), None);
+    }
+
+    #[test]
+    fn every_factor_of_all_is_needed() {
+        let key = env::temp_dir().join(format!("pinlock-usb-key-{}", process::id()));
+        let pins = [Pin::new("1234".into())];
+        let require: Config = toml::from_str(r#"require = { all = ["pin", "usb-key"] }"#).unwrap();
+        let require = require.require.unwrap();
+        let auth = Authenticator::new(&pins, None).with_policy(Some(&require), Some(&key));
+
+        assert_eq!(auth.verify("1234"), None);
+        fs::write(&key, "").unwrap();
+        assert_eq!(auth.verify("1234"), Some(UnlockReason::Authenticated));
+        assert_eq!(auth.verify("0000"), None);
+        fs::remove_file(&key).unwrap();
+    }
+
+    #[test]
+    fn policies_nest() {
+        let config: Config =
+            toml::from_str(r#"require = { all = ["pin", { any = ["usb-key", "command:true"] }] }"#)
+                .unwrap();
+        let require = config.require.unwrap();
+        assert_eq!(
+            require.factors(),
+            [
+                &Factor::Pin,
+                &Factor::UsbKey,
+                &Factor::Command("true".to_owned())
+            ]
+        );
+        let pins = [Pin::new("1234".into())];
+        let auth = Authenticator::new(&pins, None).with_policy(Some(&require), None);
+        assert_eq!(auth.verify("1234"), Some(UnlockReason::Authenticated));
+
+        assert!(Policy::All(Vec::new()).validate().is_err());
+        assert!(toml::from_str::<Config>(r#"require = { all = ["face"] }"#).is_err());
+    }
+
+    #[test]
+    fn the_command_reads_the_pin_as_utf8() {
+        let command = r#"read -r pin && [ "$pin" = "$(printf 'p\303\251\360\237\224\222')" ]"#;
+        let auth = Authenticator::new(&[], Some(command));
+        assert_eq!(auth.verify("pé🔒"), Some(UnlockReason::Authenticated));
+        assert_eq!(auth.verify("pe🔒"), None);
+    }
+}
@@ -0,0 +1,350 @@
+//! What the lock screen is drawn with: the back buffer of the lock window,
+//! or an image for `--print-layout`.
+//!
+//! Drawing requests take the color to draw in, rather than relying on the
+//! state of a GC, so that a backend without X can follow along.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    time::Duration,
+};
+
+use anyhow::Result;
+use x11rb::{
+    connection::Connection,
+    protocol::xproto::{
+        Arc, ChangeGCAux, ChangeWindowAttributesAux, Char2b, ConnectionExt as _, CreateGCAux, Font,
+        Gcontext, Pixmap, Point, Rectangle,
+    },
+    rust_connection::RustConnection,
+};
+
+use crate::{
+    buffer::BackBuffer,
+    config::Config,
+    image::{self, Encoded, PendingBackground},
+    theme::Color,
+    window::Window,
+};
+
+const FONT: &[u8] = b"fixed";
+/// Pixel size of `FONT`, the base for scaled variants of it.
+pub const FONT_SIZE: f64 = 13.0;
+
+/// Most text widths remembered at once.
+const TEXT_WIDTHS_KEPT: usize = 256;
+
+/// How often to check whether the background image has been prepared.
+const BACKGROUND_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Drawing requests of the lock screen, in window coordinates. Outlines
+/// and lines are `line_width` pixels wide, 0 being the thinnest.
+pub trait Backend {
+    /// Distance between the baselines of two lines of text
+    fn line_height(&self) -> i16;
+
+    /// Width of `text` in pixels.
+    fn text_width(&self, text: &str) -> Result<i32>;
+
+    /// Cover everything with the background image, or `color` without one.
+    fn clear(&self, color: Color) -> Result<()>;
+
+    fn fill_rectangles(&self, color: Color, rectangles: &[Rectangle]) -> Result<()>;
+
+    fn draw_rectangles(&self, color: Color, rectangles: &[Rectangle]) -> Result<()>;
+
+    fn fill_arcs(&self, color: Color, arcs: &[Arc]) -> Result<()>;
+
+    fn draw_arcs(&self, color: Color, line_width: u16, arcs: &[Arc]) -> Result<()>;
+
+    fn draw_lines(&self, color: Color, line_width: u16, points: &[Point]) -> Result<()>;
+
+    /// Draw a line of text starting at `x`, with its baseline at `y`.
+    fn draw_text(&self, color: Color, text: &str, x: i16, y: i16) -> Result<()>;
+
+    /// Restrict drawing to `areas`, until `unclip`.
+    fn clip(&self, areas: &[Rectangle]) -> Result<()>;
+
+    fn unclip(&self) -> Result<()>;
+
+    /// Show the finished frame.
+    fn present(&self) -> Result<()>;
+}
+
+/// Draws into the [`BackBuffer`] of the lock window with a single GC, and
+/// copies frames to the window.
+pub struct XBackend<'connection> {
+    conn: &'connection RustConnection,
+    window: u32,
+    buffer: BackBuffer<'connection>,
+    /// The background image, fitted to the monitors
+    background: Option<Pixmap>,
+    /// The background image while it's still being prepared
+    pending_background: Option<PendingBackground>,
+    /// What the background is made from, again after resizing
+    background_image: Option<Encoded>,
+    gc: Gcontext,
+    /// The foreground, background and line width `gc` is set to
+    gc_state: Cell<(u32, u32, u16)>,
+    /// What text is drawn on, as image text fills its box: the color the
+    /// frame was cleared with
+    text_background: Cell<Color>,
+    font: Font,
+    line_height: i16,
+    /// Widths of the texts measured so far, sparing a round trip to the
+    /// server for each text of each frame
+    text_widths: RefCell<HashMap<String, i32>>,
+}
+
+impl<'connection> XBackend<'connection> {
+    /// Draw on `window`, over `background_image` if there is one, with
+    /// text of a size matching `scale`.
+    pub fn new(
+        connection: &'connection RustConnection,
+        window: &Window,
+        config: &Config,
+        background_image: Option<Encoded>,
+        scale: f64,
+    ) -> Result<Self> {
+        let font = open_font(connection, scale)?;
+        let font_info = connection.query_font(font)?.reply()?;
+        let line_height = font_info.font_ascent + font_info.font_descent;
+
+        let buffer = BackBuffer::new(connection, window, config.max_buffer_size())?;
+        let (background, pending_background) = if let Some(delay) = config.background_delay() {
+            let pending = PendingBackground::start(
+                connection,
+                window,
+                config,
+                background_image.clone(),
+                delay,
+            )?;
+            (None, pending)
+        } else {
+            let background =
+                image::create_background(connection, window, config, background_image.as_ref())?;
+            (background, None)
+        };
+
+        let gc = connection.generate_id()?;
+        connection.create_gc(gc, buffer.drawable(), &CreateGCAux::default().font(font))?;
+
+        Ok(Self {
+            conn: connection,
+            window: window.id,
+            buffer,
+            background,
+            pending_background,
+            background_image,
+            gc,
+            gc_state: Cell::new((0, 1, 0)),
+            text_background: Cell::new(Color(0)),
+            font,
+            line_height,
+            text_widths: RefCell::new(HashMap::new()),
+        })
+    }
+
+    pub fn connection(&self) -> &'connection RustConnection {
+        self.conn
+    }
+
+    /// Make the background image the window's own background and show it,
+    /// ahead of the first frame. Nothing happens while it is still being
+    /// prepared.
+    pub fn show_background(&self) -> Result<()> {
+        if let Some(background) = self.background {
+            self.conn.change_window_attributes(
+                self.window,
+                &ChangeWindowAttributesAux::new().background_pixmap(background),
+            )?;
+            self.conn.clear_area(false, self.window, 0, 0, 0, 0)?;
+        }
+        Ok(())
+    }
+
+    /// Recreate the back buffer and background for a new size of the window.
+    pub fn resize(&mut self, window: &Window, config: &Config) -> Result<()> {
+        self.free_background();
+        self.buffer = BackBuffer::new(self.conn, window, config.max_buffer_size())?;
+        self.background =
+            image::create_background(self.conn, window, config, self.background_image.as_ref())?;
+        self.pending_background = None;
+        Ok(())
+    }
+
+    /// How long to wait before checking on the background image again,
+    /// while it is being prepared.
+    pub fn background_poll(&self) -> Option<Duration> {
+        self.pending_background
+            .as_ref()
+            .map(|_| BACKGROUND_POLL_INTERVAL)
+    }
+
+    /// Swap in the background image if it is ready. Returns whether it was.
+    pub fn update_background(&mut self) -> Result<bool> {
+        let Some(pending) = self.pending_background.take() else {
+            return Ok(false);
+        };
+        match pending.finish(self.conn)? {
+            Ok(background) => {
+                self.background = background;
+                Ok(true)
+            }
+            Err(pending) => {
+                self.pending_background = Some(pending);
+                Ok(false)
+            }
+        }
+    }
+
+    fn free_background(&self) {
+        if let Some(background) = self.background {
+            let _ = self.conn.free_pixmap(background);
+        }
+    }
+
+    /// Set up the GC to draw in `color`, with lines `line_width` wide.
+    fn set_gc(&self, color: Color, line_width: u16) -> Result<()> {
+        let state = (
+            color.pixel(),
+            self.text_background.get().pixel(),
+            line_width,
+        );
+        if state != self.gc_state.get() {
+            self.conn.change_gc(
+                self.gc,
+                &ChangeGCAux::new()
+                    .foreground(state.0)
+                    .background(state.1)
+                    .line_width(u32::from(line_width)),
+            )?;
+            self.gc_state.set(state);
+        }
+        Ok(())
+    }
+}
+
+impl Backend for XBackend<'_> {
+    fn line_height(&self) -> i16 {
+        self.line_height
+    }
+
+    fn text_width(&self, text: &str) -> Result<i32> {
+        if let Some(&width) = self.text_widths.borrow().get(text) {
+            return Ok(width);
+        }
+        let extents = self
+            .conn
+            .query_text_extents(self.font, &to_char2b(text))?
+            .reply()?;
+
+        let mut text_widths = self.text_widths.borrow_mut();
+        // Texts that change, like the clock's, would pile up otherwise
+        if text_widths.len() >= TEXT_WIDTHS_KEPT {
+            text_widths.clear();
+        }
+        text_widths.insert(text.to_owned(), extents.overall_width);
+        Ok(extents.overall_width)
+    }
+
+    fn clear(&self, color: Color) -> Result<()> {
+        self.text_background.set(color);
+        if let Some(background) = self.background {
+            return self.buffer.copy_from(background, self.gc);
+        }
+        let (width, height) = self.buffer.size();
+        self.fill_rectangles(
+            color,
+            &[Rectangle {
+                x: 0,
+                y: 0,
+                width,
+                height,
+            }],
+        )
+    }
+
+    fn fill_rectangles(&self, color: Color, rectangles: &[Rectangle]) -> Result<()> {
+        self.set_gc(color, 0)?;
+        self.buffer.fill_rectangles(self.gc, rectangles)
+    }
+
+    fn draw_rectangles(&self, color: Color, rectangles: &[Rectangle]) -> Result<()> {
+        self.set_gc(color, 0)?;
+        self.buffer.draw_rectangles(self.gc, rectangles)
+    }
+
+    fn fill_arcs(&self, color: Color, arcs: &[Arc]) -> Result<()> {
+        self.set_gc(color, 0)?;
+        self.buffer.fill_arcs(self.gc, arcs)
+    }
+
+    fn draw_arcs(&self, color: Color, line_width: u16, arcs: &[Arc]) -> Result<()> {
+        self.set_gc(color, line_width)?;
+        self.buffer.draw_arcs(self.gc, arcs)
+    }
+
+    fn draw_lines(&self, color: Color, line_width: u16, points: &[Point]) -> Result<()> {
+        self.set_gc(color, line_width)?;
+        self.buffer.draw_lines(self.gc, points)
+    }
+
+    fn draw_text(&self, color: Color, text: &str, x: i16, y: i16) -> Result<()> {
+        self.set_gc(color, 0)?;
+        self.buffer.draw_text(self.gc, x, y, &to_char2b(text))
+    }
+
+    fn clip(&self, areas: &[Rectangle]) -> Result<()> {
+        self.buffer.clip(&[self.gc], areas)
+    }
+
+    fn unclip(&self) -> Result<()> {
+        self.buffer.unclip(&[self.gc])
+    }
+
+    fn present(&self) -> Result<()> {
+        self.buffer.present(self.window, self.gc)?;
+        self.conn.flush()?;
+        Ok(())
+    }
+}
+
+impl Drop for XBackend<'_> {
+    fn drop(&mut self) {
+        let _ = self.conn.free_gc(self.gc);
+        self.free_background();
+        let _ = self.conn.close_font(self.font);
+    }
+}
+
+/// Open `FONT` at a size matching the scale, falling back to its regular
+/// size if the server has no such font.
+fn open_font(connection: &RustConnection, scale: f64) -> Result<Font> {
+    let font = connection.generate_id()?;
+
+    let size = (FONT_SIZE * scale).round();
+    if size != FONT_SIZE {
+        let name = format!("-misc-fixed-medium-r-normal--{size}-*-*-*-*-*-iso10646-1");
+        if connection.open_font(font, name.as_bytes())?.check().is_ok() {
+            return Ok(font);
+        }
+    }
+
+    connection.open_font(font, FONT)?;
+    Ok(font)
+}
+
+/// Encode text for the 16-bit core text requests, replacing characters
+/// outside of the basic multilingual plane. Core requests are limited to 255
+/// characters, anything above is cut off.
+fn to_char2b(text: &str) -> Vec<Char2b> {
+    text.chars()
+        .take(255)
+        .map(|c| {
+            let [byte1, byte2] = u16::try_from(u32::from(c)).unwrap_or(0xfffd).to_be_bytes();
+            Char2b { byte1, byte2 }
+        })
+        .collect()
+}
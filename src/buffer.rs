@@ -0,0 +1,384 @@
+//! The back buffer frames are composed in before they are copied to the
+//! lock window.
+//!
+//! Usually a single pixmap the size of the window. A window spanning many
+//! monitors can be larger than the X server can allocate a pixmap for, in
+//! which case the buffer is made of a pixmap per monitor instead, and every
+//! drawing request goes to each of them, moved to its origin.
+
+use std::cell::Cell;
+
+use anyhow::Result;
+use log::warn;
+use x11rb::{
+    connection::Connection,
+    errors::ReplyOrIdError,
+    protocol::{
+        xproto::{
+            Arc, ChangeGCAux, Char2b, ClipOrdering, ConnectionExt as _, CoordMode, Gcontext,
+            Pixmap, Point, Rectangle,
+        },
+        ErrorKind,
+    },
+    rust_connection::RustConnection,
+    NONE,
+};
+
+use crate::{image, window::Window};
+
+/// Largest pixmap side, as X coordinates are signed 16-bit numbers.
+pub const MAX_PIXMAP_SIDE: u16 = i16::MAX as u16;
+
+/// How the window is split into back buffer pixmaps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Plan {
+    Whole,
+    /// A pixmap for each of these areas of the window
+    PerMonitor(Vec<Rectangle>),
+}
+
+impl Plan {
+    /// A single pixmap if a `width` by `height` one fits within `max`, or
+    /// one per monitor, cut into pieces that fit.
+    pub fn new(width: u16, height: u16, monitors: &[Rectangle], max: (u16, u16)) -> Self {
+        if width <= max.0 && height <= max.1 {
+            Self::Whole
+        } else {
+            Self::PerMonitor(per_monitor(monitors, max))
+        }
+    }
+}
+
+/// The monitors, each cut into pieces of at most `max`.
+fn per_monitor(monitors: &[Rectangle], max: (u16, u16)) -> Vec<Rectangle> {
+    monitors
+        .iter()
+        .flat_map(|&monitor| split(monitor, max))
+        .collect()
+}
+
+/// Cut `area` into pieces of at most `max`, row by row.
+fn split(area: Rectangle, (max_width, max_height): (u16, u16)) -> Vec<Rectangle> {
+    let mut pieces = Vec::new();
+    for dy in (0..area.height).step_by(max_height.max(1).into()) {
+        for dx in (0..area.width).step_by(max_width.max(1).into()) {
+            pieces.push(Rectangle {
+                x: area.x.saturating_add_unsigned(dx),
+                y: area.y.saturating_add_unsigned(dy),
+                width: (area.width - dx).min(max_width),
+                height: (area.height - dy).min(max_height),
+            });
+        }
+    }
+    pieces
+}
+
+/// A pixmap holding `area` of the window.
+struct Tile {
+    pixmap: Pixmap,
+    area: Rectangle,
+}
+
+/// Offers the drawing requests of the lock screen, taking window
+/// coordinates whichever way the buffer is split.
+pub struct BackBuffer<'connection> {
+    conn: &'connection RustConnection,
+    width: u16,
+    height: u16,
+    tiles: Vec<Tile>,
+    /// Whether the GCs clip, so that their clip origin has to follow the
+    /// tile drawn to
+    clipped: Cell<bool>,
+}
+
+impl<'connection> BackBuffer<'connection> {
+    /// A buffer for `window` made of pixmaps of at most `max`. Falls back to
+    /// a pixmap per monitor should the server fail to allocate one for the
+    /// whole window.
+    pub fn new(
+        connection: &'connection RustConnection,
+        window: &Window,
+        max: (u16, u16),
+    ) -> Result<Self> {
+        let monitors = image::monitors(connection, window)?;
+        let whole = Rectangle {
+            x: 0,
+            y: 0,
+            width: window.width,
+            height: window.height,
+        };
+        let mut buffer = Self {
+            conn: connection,
+            width: window.width,
+            height: window.height,
+            tiles: Vec::new(),
+            clipped: Cell::new(false),
+        };
+
+        let areas = match Plan::new(window.width, window.height, &monitors, max) {
+            Plan::Whole => match buffer.add_tile(window, whole) {
+                Ok(()) => return Ok(buffer),
+                Err(ReplyOrIdError::X11Error(err)) if err.error_kind == ErrorKind::Alloc => {
+                    warn!(
+                        "The X server can't allocate a {}x{} back buffer, using one per monitor",
+                        window.width, window.height
+                    );
+                    per_monitor(&monitors, max)
+                }
+                Err(err) => return Err(err.into()),
+            },
+            Plan::PerMonitor(areas) => areas,
+        };
+        for area in areas {
+            buffer.add_tile(window, area)?;
+        }
+        Ok(buffer)
+    }
+
+    fn add_tile(&mut self, window: &Window, area: Rectangle) -> Result<(), ReplyOrIdError> {
+        let pixmap = self.conn.generate_id()?;
+        self.conn
+            .create_pixmap(
+                window.visual.depth,
+                pixmap,
+                window.id,
+                area.width,
+                area.height,
+            )?
+            .check()?;
+        self.tiles.push(Tile { pixmap, area });
+        Ok(())
+    }
+
+    /// The size of the window the buffer is for.
+    pub fn size(&self) -> (u16, u16) {
+        (self.width, self.height)
+    }
+
+    /// A pixmap of the buffer, to create GCs for it with.
+    pub fn drawable(&self) -> Pixmap {
+        self.tiles[0].pixmap
+    }
+
+    /// Run `draw` for every tile, with the clip origin of `gc` moved to it.
+    fn each(&self, gc: Gcontext, mut draw: impl FnMut(&Tile) -> Result<()>) -> Result<()> {
+        for tile in &self.tiles {
+            if self.clipped.get() && self.tiles.len() > 1 {
+                self.conn.change_gc(
+                    gc,
+                    &ChangeGCAux::new()
+                        .clip_x_origin(-i32::from(tile.area.x))
+                        .clip_y_origin(-i32::from(tile.area.y)),
+                )?;
+            }
+            draw(tile)?;
+        }
+        Ok(())
+    }
+
+    /// Restrict drawing with `gcs` to `areas` of the window.
+    pub fn clip(&self, gcs: &[Gcontext], areas: &[Rectangle]) -> Result<()> {
+        for &gc in gcs {
+            self.conn
+                .set_clip_rectangles(ClipOrdering::UNSORTED, gc, 0, 0, areas)?;
+        }
+        self.clipped.set(true);
+        Ok(())
+    }
+
+    pub fn unclip(&self, gcs: &[Gcontext]) -> Result<()> {
+        for &gc in gcs {
+            self.conn
+                .change_gc(gc, &ChangeGCAux::new().clip_mask(NONE))?;
+        }
+        self.clipped.set(false);
+        Ok(())
+    }
+
+    pub fn fill_rectangles(&self, gc: Gcontext, rectangles: &[Rectangle]) -> Result<()> {
+        self.each(gc, |tile| {
+            let moved: Vec<Rectangle> = rectangles.iter().map(|r| moved(*r, tile.area)).collect();
+            self.conn.poly_fill_rectangle(tile.pixmap, gc, &moved)?;
+            Ok(())
+        })
+    }
+
+    pub fn draw_rectangles(&self, gc: Gcontext, rectangles: &[Rectangle]) -> Result<()> {
+        self.each(gc, |tile| {
+            let moved: Vec<Rectangle> = rectangles.iter().map(|r| moved(*r, tile.area)).collect();
+            self.conn.poly_rectangle(tile.pixmap, gc, &moved)?;
+            Ok(())
+        })
+    }
+
+    pub fn fill_arcs(&self, gc: Gcontext, arcs: &[Arc]) -> Result<()> {
+        self.each(gc, |tile| {
+            let moved: Vec<Arc> = arcs.iter().map(|arc| moved_arc(*arc, tile.area)).collect();
+            self.conn.poly_fill_arc(tile.pixmap, gc, &moved)?;
+            Ok(())
+        })
+    }
+
+    pub fn draw_arcs(&self, gc: Gcontext, arcs: &[Arc]) -> Result<()> {
+        self.each(gc, |tile| {
+            let moved: Vec<Arc> = arcs.iter().map(|arc| moved_arc(*arc, tile.area)).collect();
+            self.conn.poly_arc(tile.pixmap, gc, &moved)?;
+            Ok(())
+        })
+    }
+
+    pub fn draw_lines(&self, gc: Gcontext, points: &[Point]) -> Result<()> {
+        self.each(gc, |tile| {
+            let moved: Vec<Point> = points
+                .iter()
+                .map(|point| Point {
+                    x: point.x.saturating_sub(tile.area.x),
+                    y: point.y.saturating_sub(tile.area.y),
+                })
+                .collect();
+            self.conn
+                .poly_line(CoordMode::ORIGIN, tile.pixmap, gc, &moved)?;
+            Ok(())
+        })
+    }
+
+    /// Draw `text` starting at `x`, with its baseline at `y`.
+    pub fn draw_text(&self, gc: Gcontext, x: i16, y: i16, text: &[Char2b]) -> Result<()> {
+        self.each(gc, |tile| {
+            self.conn.image_text16(
+                tile.pixmap,
+                gc,
+                x.saturating_sub(tile.area.x),
+                y.saturating_sub(tile.area.y),
+                text,
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Cover the buffer with `source`, a pixmap of the window's size.
+    pub fn copy_from(&self, source: Pixmap, gc: Gcontext) -> Result<()> {
+        self.each(gc, |tile| {
+            let Rectangle {
+                x,
+                y,
+                width,
+                height,
+            } = tile.area;
+            self.conn
+                .copy_area(source, tile.pixmap, gc, x, y, 0, 0, width, height)?;
+            Ok(())
+        })
+    }
+
+    /// Copy the buffer to `window`.
+    pub fn present(&self, window: u32, gc: Gcontext) -> Result<()> {
+        // The window is drawn in window coordinates again
+        if self.clipped.get() && self.tiles.len() > 1 {
+            self.conn
+                .change_gc(gc, &ChangeGCAux::new().clip_x_origin(0).clip_y_origin(0))?;
+        }
+        for tile in &self.tiles {
+            let Rectangle {
+                x,
+                y,
+                width,
+                height,
+            } = tile.area;
+            self.conn
+                .copy_area(tile.pixmap, window, gc, 0, 0, x, y, width, height)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for BackBuffer<'_> {
+    fn drop(&mut self) {
+        for tile in &self.tiles {
+            let _ = self.conn.free_pixmap(tile.pixmap);
+        }
+    }
+}
+
+/// `rectangle` in the coordinates of a tile holding `area`.
+fn moved(rectangle: Rectangle, area: Rectangle) -> Rectangle {
+    Rectangle {
+        x: rectangle.x.saturating_sub(area.x),
+        y: rectangle.y.saturating_sub(area.y),
+        ..rectangle
+    }
+}
+
+fn moved_arc(arc: Arc, area: Rectangle) -> Arc {
+    Arc {
+        x: arc.x.saturating_sub(area.x),
+        y: arc.y.saturating_sub(area.y),
+        ..arc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MONITOR: Rectangle = Rectangle {
+        x: 0,
+        y: 0,
+        width: 3840,
+        height: 2160,
+    };
+
+    #[test]
+    fn a_window_within_the_limits_gets_one_pixmap() {
+        let plan = Plan::new(7680, 2160, &[MONITOR], (MAX_PIXMAP_SIDE, MAX_PIXMAP_SIDE));
+        assert_eq!(plan, Plan::Whole);
+    }
+
+    #[test]
+    fn an_oversized_window_gets_a_pixmap_per_monitor() {
+        // A video wall of 4 by 2 monitors, larger than the clamp
+        let monitors: Vec<Rectangle> = (0..8)
+            .map(|i| Rectangle {
+                x: i % 4 * 3840,
+                y: i / 4 * 2160,
+                ..MONITOR
+            })
+            .collect();
+        let plan = Plan::new(15360, 4320, &monitors, (8192, 8192));
+        assert_eq!(plan, Plan::PerMonitor(monitors));
+    }
+
+    #[test]
+    fn monitors_larger_than_the_limit_are_cut() {
+        let plan = Plan::new(3840, 2160, &[MONITOR], (2048, 2048));
+        assert_eq!(
+            plan,
+            Plan::PerMonitor(vec![
+                Rectangle {
+                    x: 0,
+                    y: 0,
+                    width: 2048,
+                    height: 2048,
+                },
+                Rectangle {
+                    x: 2048,
+                    y: 0,
+                    width: 1792,
+                    height: 2048,
+                },
+                Rectangle {
+                    x: 0,
+                    y: 2048,
+                    width: 2048,
+                    height: 112,
+                },
+                Rectangle {
+                    x: 2048,
+                    y: 2048,
+                    width: 1792,
+                    height: 112,
+                },
+            ])
+        );
+    }
+}
@@ -0,0 +1,76 @@
+//! Photos of whoever fails to unlock, for the `camera` feature.
+//!
+//! Frames are requested as MJPEG, which nearly every webcam supports, so
+//! that they can be saved as JPEG files as they are.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use chrono::Local;
+use log::{error, info};
+use v4l::{
+    buffer::Type,
+    io::{mmap::Stream, traits::CaptureStream},
+    video::Capture,
+    Device, FourCC,
+};
+
+use crate::{config::Config, persist};
+
+/// Frames dropped before the photo, while the camera adjusts its exposure
+const WARMUP_FRAMES: usize = 5;
+
+/// Longest wait for a frame
+const FRAME_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Take a photo with the configured camera on a thread of its own, as opening
+/// the device and waiting for frames can take a while. The outcome is only
+/// logged.
+pub fn capture(config: &Config) {
+    let device = config.camera_device.clone();
+    let dir = config.capture_dir.clone();
+
+    thread::spawn(move || {
+        let result = match dir {
+            Some(dir) => Ok(dir),
+            None => persist::state_dir().map(|dir| dir.join("captures")),
+        }
+        .and_then(|dir| take_photo(&device, &dir));
+
+        match result {
+            Ok(path) => info!("Saved a photo to {}", path.display()),
+            Err(err) => error!("Failed to take a photo: {err:#}"),
+        }
+    });
+}
+
+fn take_photo(device: &Path, dir: &Path) -> Result<PathBuf> {
+    let camera = Device::with_path(device)
+        .with_context(|| format!("Failed to open {}", device.display()))?;
+
+    let mjpeg = FourCC::new(b"MJPG");
+    let mut format = camera.format()?;
+    format.fourcc = mjpeg;
+    if camera.set_format(&format)?.fourcc != mjpeg {
+        bail!("{} doesn't support MJPEG", device.display());
+    }
+
+    let mut stream = Stream::with_buffers(&camera, Type::VideoCapture, 4)?;
+    stream.set_timeout(FRAME_TIMEOUT);
+    for _ in 0..WARMUP_FRAMES {
+        stream.next()?;
+    }
+    let (frame, metadata) = stream.next()?;
+    let jpeg = frame.get(..metadata.bytesused as usize).unwrap_or(frame);
+
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let path = dir.join(Local::now().format("%Y-%m-%d_%H-%M-%S.jpg").to_string());
+    fs::write(&path, jpeg).with_context(|| format!("Failed to write {}", path.display()))?;
+
+    Ok(path)
+}
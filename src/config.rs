@@ -0,0 +1,1218 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use chrono::format::{Item, StrftimeItems};
+use log::{debug, warn};
+use serde::Deserialize;
+use toml::{Table, Value};
+use zeroize::Zeroize;
+
+#[cfg(feature = "dbus")]
+use crate::dbus;
+use crate::{
+    auth::{Factor, Pin, Policy, ProtectedSecret},
+    buffer,
+    cursor::CursorConfig,
+    image::{BackgroundImage, BackgroundMode, ScreenRegion},
+    indicator::{BarPosition, Orientation},
+    input::{DeadKeys, KeyCombo, KeyRepeat},
+    led::Led,
+    locker,
+    random::RandomnessSource,
+    screensaver::ScreensaverKind,
+    sound::Sound,
+    state::InputFilter,
+    sysinfo::InfoLine,
+    theme::{Color, Theme, ThemeConfig},
+    ui::{BannerPosition, IndicatorStyle, UiAnchor, UiOn},
+    widget::{Region, WidgetConfig},
+    window::{GrabTarget, KeyboardGrabMode},
+};
+
+/// User configuration, read from `$XDG_CONFIG_HOME/pinlock/config.toml`.
+///
+/// A file can build on others with `include = ["/etc/pinlock/common.toml"]`,
+/// paths being relative to its directory. The included files are read in
+/// order, each overriding the ones before, and the including file overrides
+/// them all. Tables are merged key by key, anything else is replaced.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The PIN required to unlock the screen, shorthand for a single entry
+    /// in `pins`.
+    pub pin: String,
+    /// All PINs that unlock the screen, including duress PINs.
+    pub pins: Vec<Pin>,
+    /// Program deciding whether a PIN unlocks when none of `pins` match,
+    /// such as one asking a hardware token or a server. It is run with the
+    /// shell, gets the entered PIN and a newline on stdin and unlocks by
+    /// exiting with 0. The lock screen waits for it, up to
+    /// `auth_timeout_secs`.
+    pub auth_command: Option<String>,
+    /// Seconds to wait for `auth_command`, `otp_command` and the commands
+    /// of `require`, after which a command is killed and the PIN counts as
+    /// wrong, so that a hung command can't wedge the lock screen. Never
+    /// unlocks.
+    pub auth_timeout_secs: u64,
+    /// Program printing a one-time PIN, valid for the current window of
+    /// `otp_window_secs`, such as `"oathtool --totp -b @secret.txt"`. It is
+    /// run with the shell when locking, and again if an entered PIN doesn't
+    /// match and a window has started since. Only the first line counts.
+    pub otp_command: Option<String>,
+    /// Length of the windows of `otp_command`, counted from the Unix epoch.
+    pub otp_window_secs: u64,
+    /// Where to read one more PIN from when the configuration is loaded,
+    /// rather than keeping it in this file. `"secret-service:NAME"` reads
+    /// the Secret Service item with the attribute `application` set to
+    /// NAME, as stored by `secret-tool store --label=pinlock application
+    /// NAME`. The keyring must be unlocked by then, as it can't be unlocked
+    /// from the lock screen. Needs the `dbus` feature.
+    pub pin_source: Option<PinSource>,
+    /// An unlock pattern for touchscreens without a keyboard: the dots of a
+    /// three by three grid below the PIN field to tap in order, numbered 1
+    /// to 9 like a phone keypad, such as `"14789"` for an L. Each dot counts
+    /// once, and tapping the last one again submits. Takes the place of
+    /// `buttons` and `keypad`.
+    pub pattern: Option<ProtectedSecret>,
+    /// Factors that all have to, or one of which has to, check out to
+    /// unlock, such as `{ all = ["pin", "usb-key"] }` for a PIN and a USB
+    /// key. They are `"pin"`, `"usb-key"` for `usb_key_file` and
+    /// `"command:..."` for a shell command exiting with 0. A failure looks
+    /// the same whichever factor failed. Unset, the PIN is all it takes.
+    pub require: Option<Policy>,
+    /// A file whose presence makes up the `usb-key` factor, such as one on
+    /// a USB stick mounted at a fixed place.
+    pub usb_key_file: Option<PathBuf>,
+    /// Milliseconds a rejected PIN takes to show as wrong, plus up to a
+    /// quarter more at random, however quickly it was rejected. Hides from
+    /// onlookers with a stopwatch how the PIN or `auth_command` failed.
+    /// Unset shows it right away.
+    pub constant_time_response_ms: Option<u64>,
+    /// The X display to lock, such as `":1"`, instead of `$DISPLAY`.
+    /// `--display` takes precedence. Only read at startup.
+    pub display: Option<String>,
+    /// Another locker to run in place of pinlock should it fail to lock the
+    /// screen, such as `"i3lock -n"`, so that the screen gets locked all the
+    /// same. Run with the shell, replacing pinlock. Only for failing to grab
+    /// or set up, never once the screen was locked.
+    pub fallback_command: Option<String>,
+    /// Length of the PINs, further input is ignored once it is reached.
+    pub max_pin_length: Option<usize>,
+    /// Which characters are accepted, `"any_char"` or `"digits_only"` to
+    /// ignore everything but `0` to `9`, including the keypad digits. With
+    /// `"digits_only"`, the number row types its digits whatever the layout
+    /// and modifiers, so a PIN set on one layout works on any other.
+    pub input_filter: InputFilter,
+    /// Failed attempts after which PIN entry is refused for `lockout_secs`.
+    /// The lockout survives restarting pinlock.
+    pub max_attempts: Option<u32>,
+    pub lockout_secs: u64,
+    /// After a wrong PIN, tell how many attempts are left before the
+    /// lockout, with the `remaining_attempts` string. Only with
+    /// `max_attempts`.
+    pub show_remaining_attempts: bool,
+    /// Milliseconds the wrong PIN message stays, so that it doesn't linger
+    /// once nobody is around. 0 keeps it until the next key press.
+    pub incorrect_pin_timeout_ms: u64,
+    /// After a wrong PIN, ignore submitting until every key was released
+    /// once, which slows down tools that retry by holding keys or sending
+    /// presses only. The entered characters are kept meanwhile.
+    pub release_between_attempts: bool,
+    /// Milliseconds Enter has to be held to submit, a bar filling up
+    /// meanwhile, so that brushing it doesn't submit half a PIN. Releasing
+    /// it early submits nothing. 0 submits on the press.
+    pub hold_to_submit_ms: u64,
+    /// Draw the PIN dots in the theme's `ready` color once `max_pin_length`
+    /// characters were entered, hinting that the PIN can be submitted.
+    pub ready_indicator: bool,
+    /// How the entered characters are shown: `"dots"`, `"count"` for their
+    /// number as text, `"bar"` for a bar filling up towards
+    /// `max_pin_length`, or `"none"`.
+    pub indicator_style: IndicatorStyle,
+    /// Height in pixels of `indicator_style = "bar"`, scaled like the rest.
+    /// Unset makes it as high as a dot, the filled part is the theme's `bar`
+    /// color.
+    pub progress_bar_height: Option<u16>,
+    /// Shake the PIN indicator sideways after a wrong PIN.
+    pub shake_on_failure: bool,
+    /// Allow motion on the lock screen: the dots growing in as typing
+    /// starts, and the shake of `shake_on_failure`. Off for reduced motion.
+    pub animations: bool,
+    /// Show Submit and Clear buttons below the PIN field, for touchscreens
+    /// or trying out the lock screen with a mouse.
+    pub buttons: bool,
+    /// Show an on-screen keypad below the PIN field, with Clear and Submit
+    /// in its bottom row, for touchscreens.
+    pub keypad: bool,
+    /// Place the digits of the keypad randomly for each lock, so that
+    /// onlookers can't tell the PIN from where it was touched.
+    pub keypad_randomize: bool,
+    /// With `keypad_randomize`, place the digits anew after every wrong PIN
+    /// as well.
+    pub keypad_reshuffle: bool,
+    /// Where the keypad layout of `keypad_randomize` is drawn from: `"os"`
+    /// for the operating system's CSPRNG, or a device such as
+    /// `"/dev/hwrng"`. Should the device fail, the keypad stays ordered
+    /// rather than falling back to another source.
+    pub randomness_source: RandomnessSource,
+    /// Seconds after locking during which any keypress or pointer motion
+    /// dismisses the lock without asking for the PIN.
+    pub grace_secs: u64,
+    /// Milliseconds after locking during which keypresses are dropped, so
+    /// that keys of the hotkey starting the lock don't end up in the PIN.
+    pub input_ignore_ms: u64,
+    /// Milliseconds over which typed keys are gathered into a single redraw
+    /// of the PIN field, so that fast typing doesn't queue up redraws. 0
+    /// redraws on every key.
+    pub input_redraw_ms: u64,
+    /// Milliseconds the first frame waits for the server to be done
+    /// exposing the newly mapped lock window, so that it is drawn once for
+    /// the whole batch of Expose events. Drawn anyway after that, should
+    /// the last of them never come. 0 draws it right away.
+    pub first_frame_wait_ms: u64,
+    /// Most times a second the lock screen wakes up to draw by itself, for
+    /// animations, the clock or the screensaver. Keys and clicks are
+    /// handled as they come regardless, and with nothing to animate, the
+    /// lock screen sleeps until they do.
+    pub max_fps: u32,
+    /// Seconds without input after which `pinlock daemon` locks the screen.
+    pub idle_secs: u64,
+    /// Seconds after unlocking at which `pinlock daemon` locks the screen
+    /// again even if it is in use, for kiosks. Unset never does.
+    pub max_session_secs: Option<u64>,
+    /// Animation shown while locked and nobody is typing, `"clock"` or
+    /// `"starfield"`. Unset keeps the PIN field on screen.
+    pub screensaver: Option<ScreensaverKind>,
+    /// Seconds without input after which the lock screen is dimmed, its
+    /// text and dots fading `dim_level` of the way into the background
+    /// until the next input. Unset never dims.
+    pub dim_secs: Option<u64>,
+    pub dim_level: f64,
+    /// Seconds without a keypress after which the screensaver starts.
+    pub screensaver_secs: u64,
+    /// Seconds without a keypress after which the lock screen collapses to
+    /// just the time, centered and dimmed, like a bedside clock, until the
+    /// next keypress brings back the PIN field. Unlike display power
+    /// management, the clock stays visible. Only without a `screensaver`.
+    pub idle_clock_after_secs: Option<u64>,
+    /// How bright that clock is, from 0 for the background color to 1 for
+    /// the foreground color.
+    pub idle_clock_brightness: f64,
+    /// strftime-like format of the screensaver clock, e.g. `"%I:%M %p"`
+    /// for the 12-hour clock or `"%a %d %b %H:%M"` to include the date.
+    pub clock_format: String,
+    /// What the `sysinfo` widget shows, a line each: `"hostname"`,
+    /// `"uptime"` and `"load"`.
+    pub info_lines: Vec<InfoLine>,
+    /// Let a cooperative window manager place the lock window, by requesting
+    /// fullscreen through `_NET_WM_STATE` rather than bypassing the window
+    /// manager with override-redirect. An escape hatch for compositors whose
+    /// input routing conflicts with override-redirect windows.
+    pub use_fullscreen_hint: bool,
+    /// Ask a compositing window manager to stop compositing while locked,
+    /// through `_NET_WM_BYPASS_COMPOSITOR`, so that it can't keep showing
+    /// its cached image of the desktop through or around the lock window.
+    /// The lock screen is opaque either way, turning this off only keeps
+    /// the compositor's effects, such as blur or fading the window in.
+    pub bypass_compositor: bool,
+    /// Grab the pointer while locked. Disabling this helps on some remote
+    /// desktop and VNC setups where the grab fails or breaks the session, at
+    /// the cost of security: clicks still hit the lock window, but the pointer
+    /// is no longer exclusively ours and other clients can grab it.
+    pub grab_pointer: bool,
+    /// Keep the pointer confined to the screen area while locked, so it can't
+    /// wander off to other screens or slip out of the grab.
+    pub confine_pointer: bool,
+    /// Which window the grabs are taken on. `"window"`, the lock window, is
+    /// the cleaner choice, as the grabs go away together with it. `"root"`
+    /// can be more reliable under window managers that reparent, unmap or
+    /// restack the lock window, at the risk of keeping input grabbed on the
+    /// root should the lock window disappear.
+    pub grab_on: GrabTarget,
+    /// How the keyboard is grabbed. With `"sync"`, the server holds back
+    /// further key events until pinlock processed the last one, so keys are
+    /// never reported while a previous one is still being handled. Slower
+    /// typing is the price, as every key waits for a round trip.
+    pub keyboard_grab_mode: KeyboardGrabMode,
+    /// What a held key does: `"on"` repeats it as usual, `"off"` turns
+    /// auto-repeat off while locked and `"filter"` ignores the repeats.
+    pub key_repeat: KeyRepeat,
+    /// What dead keys do: `"compose"` combines them with the next key, as
+    /// anywhere else, with the `compose` feature, `"ignore"` drops them.
+    pub dead_keys: DeadKeys,
+    /// Whether pressing a modifier like Shift or Ctrl on its own counts as
+    /// activity, which wakes the screen and ends the grace period. Off, it
+    /// only changes what the next key types.
+    pub modifier_activity: bool,
+    /// Key combos like `"Super_L"` or `"Mod4+d"` to also grab on their own
+    /// on the root window, so that the window manager can't act on them.
+    /// The keyboard grab already keeps every key from it while locked,
+    /// these only cover the moments before it is taken and after it is lost.
+    /// Combos the window manager grabbed itself can't be taken from it.
+    pub block_keys: Vec<KeyCombo>,
+    /// Only take the PIN from this keyboard, named or numbered as in
+    /// `xinput list`, such as a separate USB numpad. Other keyboards still
+    /// wake the screen but can't type. Needs the `xinput2` feature, and
+    /// every keyboard is used if the device isn't found.
+    pub pin_device: Option<String>,
+    /// Check every this many milliseconds that the lock is still in
+    /// effect, raising the lock window and taking the grabs again. A grab
+    /// another client holds is tried again on the next check. Unset
+    /// disables the check.
+    pub grab_check_ms: Option<u64>,
+    /// Clear the PIN typed so far when another client takes the input
+    /// focus from the lock window. Keys typed until the grabs are taken
+    /// again may have gone to that client, so the rest of the PIN would be
+    /// missing them, or a PIN typed from scratch be appended to its start.
+    /// Clearing costs only retyping.
+    pub clear_on_focus_change: bool,
+    /// Give the input focus back to the window that had it before locking,
+    /// so that typing after unlocking goes where it went before.
+    pub restore_focus: bool,
+    /// Pointer shown while locked: `"default"`, `"hidden"` or the path of an
+    /// image, which needs the RENDER extension.
+    pub cursor: CursorConfig,
+    /// Set the `_PINLOCK_STATE` property of the root window while locked,
+    /// such as `state=locked failed_attempts=1 input_length=3`, for scripts
+    /// to read with `xprop`. Any client on the display can read it, so it
+    /// tells them when typing happens and how long the PIN is.
+    pub publish_state: bool,
+    /// Briefly show a check mark in the theme's `ready` color after
+    /// unlocking with a PIN, before the lock screen goes away.
+    pub unlock_flash: bool,
+    /// Shell command to run after unlocking with a PIN, unlike when the
+    /// lock ends otherwise, such as on SIGTERM.
+    pub unlock_command: Option<String>,
+    /// Keep Ctrl+Alt+F1 and the like from switching to another virtual
+    /// terminal while locked. Needs the `vtlock` feature, and write access to
+    /// `/dev/tty0` with the `CAP_SYS_TTY_CONFIG` capability, which usually
+    /// means running as root. Locks without it otherwise.
+    pub lock_vt_switch: bool,
+    /// Lock keys to show a warning for while they are on, out of
+    /// `caps_lock`, `num_lock` and `scroll_lock`, in this order.
+    pub lock_indicators: Vec<Led>,
+    /// Where the lock key warnings are shown: `"above_pin"`, `"top"`,
+    /// `"bottom"`, or a corner such as `"top_right"`, which they share with
+    /// the widgets there.
+    pub lock_indicators_position: BarPosition,
+    /// Whether the lock key warnings are lined up `"horizontal"` or
+    /// `"vertical"`.
+    pub lock_indicators_orientation: Orientation,
+    /// Keyboard LED to briefly toggle after a wrong PIN, as a silent signal.
+    /// One of `caps_lock`, `num_lock` or `scroll_lock`.
+    pub led_on_failure: Option<Led>,
+    /// Sound on every key typed into the PIN, for kiosks: `"bell"` rings
+    /// the X bell, `"bell:PERCENT"` at a volume from -100 to 100 relative
+    /// to its usual one, anything else is the path of a WAV file, which
+    /// needs the `audio` feature.
+    pub key_sound: Option<Sound>,
+    /// Sound on submitting a PIN, like `key_sound`.
+    pub submit_sound: Option<Sound>,
+    /// Sound after a wrong PIN, like `key_sound`.
+    pub failure_sound: Option<Sound>,
+    /// Unlock without a PIN when logind says so, as `loginctl
+    /// unlock-session` does, e.g. after authenticating elsewhere. Only in
+    /// daemon mode and with the `logind` feature.
+    pub logind_unlock: bool,
+    /// Unlock without a PIN on SIGUSR2, for scripts. Anything that can send
+    /// it can also end pinlock with SIGTERM, but this keeps it running in
+    /// daemon mode.
+    pub signal_unlock: bool,
+    /// Lock as soon as logind reports the laptop lid closed, unless another
+    /// monitor is on, as when docked. Only in daemon mode and with the
+    /// `logind` feature.
+    pub lock_on_lid_close: bool,
+    /// Pause the media players that are playing when locking, through
+    /// MPRIS. Needs the `dbus` feature.
+    pub pause_media_on_lock: bool,
+    /// Resume the players paused by `pause_media_on_lock` after unlocking.
+    pub resume_media_on_unlock: bool,
+    /// Shell commands to run when the failed attempts since locking reach a
+    /// count, e.g. `on_failure = { 3 = "notify-send ...", 10 = "..." }`.
+    /// They run in the background, the lock doesn't wait for them.
+    pub on_failure: HashMap<FailureCount, String>,
+    /// Arm the panic wipe: once the failed attempts since locking reach
+    /// `panic_wipe_after`, `panic_wipe_command` runs, such as a script
+    /// calling `cryptsetup luksErase`. Whatever it destroys is gone for
+    /// good, also when the wrong PINs were typed by a child, a cat on the
+    /// keyboard or the owner on a bad morning. Nothing is run without both
+    /// this and the command set.
+    pub enable_panic_wipe: bool,
+    /// Shell command of the panic wipe, run in a process group of its own
+    /// that outlives pinlock. The lock goes on meanwhile.
+    pub panic_wipe_command: Option<String>,
+    pub panic_wipe_after: Option<u32>,
+    /// Take a photo with the webcam on this many failed attempts since
+    /// locking, once per lock. Needs the `camera` feature.
+    pub capture_after: Option<u32>,
+    /// Video4Linux device the photo is taken with.
+    pub camera_device: PathBuf,
+    /// Directory the photos are saved to, named after the time they were
+    /// taken. Unset means `$XDG_STATE_HOME/pinlock/captures`.
+    pub capture_dir: Option<PathBuf>,
+    /// Static text such as an ownership notice, shown on the lock screen.
+    /// Split into lines at `\n`.
+    pub banner_text: String,
+    pub banner_position: BannerPosition,
+    /// Group the banner, when at the center, the PIN field and the buttons
+    /// on a panel with rounded corners.
+    pub card: bool,
+    /// Color of the card, `foreground` if unset.
+    pub card_color: Option<Color>,
+    /// How much the card color shows over the theme's background color,
+    /// from 0 to 1. The card is blended with the color rather than the
+    /// background image, so it covers the image.
+    pub card_opacity: f64,
+    /// Radius of the card's corners, in pixels before scaling.
+    pub card_radius: u16,
+    /// Space between the card's edges and its contents, in pixels before
+    /// scaling.
+    pub card_padding: u16,
+    /// Widgets in the corners of the screen, each a `[[widgets]]` table
+    /// with a `kind`, `"clock"` or `"sysinfo"`, and a `region` like
+    /// `"top_right"`.
+    pub widgets: Vec<WidgetConfig>,
+    /// Show the machine's hostname in a corner, to tell lock screens apart
+    /// behind a KVM switch. Left out if the hostname can't be read.
+    pub show_hostname: bool,
+    /// The corner of the hostname, like `"top_right"`, where it goes after
+    /// the widgets there. Its color is the theme's `hostname`.
+    pub hostname_position: Region,
+    /// Image shown behind the PIN field, on every monitor. Either a path,
+    /// `"screenshot"` for what the screen showed before locking, or
+    /// `"command:"` and a shell command printing the image, run before
+    /// locking, such as `"command:convert ... png:-"`.
+    pub background_image: Option<BackgroundImage>,
+    /// Longest wait in milliseconds for the screenshot or the background
+    /// command, which delay locking. The plain background color is shown if
+    /// they take longer.
+    pub background_timeout_ms: u64,
+    /// How the background image is fitted to each monitor: `"fill"`, `"fit"`,
+    /// `"center"`, `"stretch"` or `"tile"`.
+    pub background_mode: BackgroundMode,
+    /// Lock right away on the plain background color, and swap in the
+    /// background image once it is decoded and scaled, instead of waiting
+    /// for it.
+    pub pre_blank: bool,
+    /// Like `pre_blank`, but only start preparing the background image this
+    /// many milliseconds after locking, so that it doesn't slow down the
+    /// first frames. Unlocking before then skips the work altogether.
+    pub background_delay_ms: Option<u64>,
+    /// Areas of the screen blacked out of the `"screenshot"` background,
+    /// stronger than any blur for what is known to be sensitive, such as
+    /// `["0,0,1920,100"]` for a top bar. Each is `"X,Y,WIDTH,HEIGHT"` in
+    /// pixels of the whole screen.
+    pub redact_regions: Vec<ScreenRegion>,
+    /// Areas of the screen where clicks go through the lock window to the
+    /// windows below, such as `["1800,0,120,40"]` for an emergency call
+    /// button of a kiosk. Each is `"X,Y,WIDTH,HEIGHT"` in pixels of the whole
+    /// screen, and still shows the lock screen. Anything clickable below is
+    /// usable without the PIN, so only leave holes over windows made to be:
+    /// a window that can open others, a terminal or a file manager, hands
+    /// over the session. Keys never get through. Needs `grab_pointer =
+    /// false`, as the pointer grab takes every click, and the X server's
+    /// SHAPE extension, without which nothing goes through.
+    pub passthrough_regions: Vec<ScreenRegion>,
+    /// Grab the X server from taking the screenshot until the lock window
+    /// shows it and holds the input, so that nothing other clients draw
+    /// in between can show. Every other client freezes for that moment. A
+    /// background command runs before the grab, as it may need the server.
+    /// Ignored with `use_fullscreen_hint`, as the window manager has to map
+    /// the window then.
+    pub grab_server: bool,
+    /// Factor for the size of dots, text and margins, for high DPI screens.
+    /// Unset derives it from the DPI of the primary monitor. Clamped to
+    /// between 0.5 and 4.
+    pub scale: Option<f64>,
+    /// Largest back buffer pixmap to allocate, as `[width, height]`. A lock
+    /// window larger than that, such as one spanning a video wall, is drawn
+    /// through a pixmap per monitor instead. Unset allows up to 32767 on
+    /// each side, the most X coordinates reach.
+    pub max_buffer_size: Option<(u16, u16)>,
+    /// Where the PIN field is placed: `"center"` of the screen, or near
+    /// where the `"pointer"` is when locking, for very wide screens.
+    pub ui_anchor: UiAnchor,
+    /// Which monitors show the UI: the `"primary"` one, `"all"` of them or
+    /// the `"focused"` one the pointer is on when locking.
+    pub ui_on: UiOn,
+    /// Colors of the lock screen: the name of a theme like `"nord"`, or a
+    /// `[theme]` section with colors and optionally a `preset` they apply
+    /// to. The built-in themes are `dark`, the default, `light`, `nord`,
+    /// `dracula` and `high_contrast`.
+    #[serde(rename = "theme")]
+    pub theme_config: ThemeConfig,
+    /// Custom themes to select by name, each a `[themes.<name>]` section
+    /// like `[theme]`, whose `preset` can only name a built-in theme.
+    pub themes: HashMap<String, ThemeConfig>,
+    /// The resulting colors, `theme_config` resolved.
+    #[serde(skip)]
+    pub theme: Theme,
+    /// High contrast and large text. Changes these values:
+    ///
+    /// - The theme becomes white on black, with yellow for `ready`. Colors
+    ///   set in `[theme]` still apply.
+    /// - Text, dots and margins are drawn 1.5 times larger than the DPI
+    ///   calls for, unless `scale` is set.
+    ///
+    /// A `max_attempts` set still locks out PIN entry.
+    pub accessibility: bool,
+    /// Texts shown on the lock screen.
+    pub strings: Strings,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            pin: String::new(),
+            pins: Vec::new(),
+            auth_command: None,
+            otp_command: None,
+            otp_window_secs: 30,
+            auth_timeout_secs: 5,
+            pattern: None,
+            require: None,
+            usb_key_file: None,
+            pin_source: None,
+            constant_time_response_ms: None,
+            display: None,
+            fallback_command: None,
+            max_pin_length: None,
+            input_filter: InputFilter::default(),
+            max_attempts: None,
+            lockout_secs: 60,
+            show_remaining_attempts: false,
+            incorrect_pin_timeout_ms: 0,
+            release_between_attempts: false,
+            hold_to_submit_ms: 0,
+            ready_indicator: true,
+            indicator_style: IndicatorStyle::default(),
+            progress_bar_height: None,
+            shake_on_failure: false,
+            animations: true,
+            buttons: false,
+            keypad: false,
+            keypad_randomize: false,
+            keypad_reshuffle: false,
+            randomness_source: RandomnessSource::default(),
+            grace_secs: 0,
+            input_ignore_ms: 0,
+            // One frame at 60 Hz
+            input_redraw_ms: 16,
+            first_frame_wait_ms: 100,
+            max_fps: 60,
+            idle_secs: 300,
+            max_session_secs: None,
+            dim_secs: None,
+            dim_level: 0.6,
+            screensaver: None,
+            screensaver_secs: 60,
+            idle_clock_after_secs: None,
+            idle_clock_brightness: 0.4,
+            clock_format: "%H:%M".into(),
+            info_lines: vec![InfoLine::Hostname, InfoLine::Uptime, InfoLine::Load],
+            use_fullscreen_hint: false,
+            bypass_compositor: true,
+            grab_pointer: true,
+            confine_pointer: true,
+            grab_on: GrabTarget::default(),
+            keyboard_grab_mode: KeyboardGrabMode::default(),
+            key_repeat: KeyRepeat::default(),
+            modifier_activity: true,
+            dead_keys: DeadKeys::default(),
+            block_keys: Vec::new(),
+            pin_device: None,
+            grab_check_ms: None,
+            clear_on_focus_change: true,
+            restore_focus: true,
+            cursor: CursorConfig::default(),
+            publish_state: false,
+            unlock_flash: false,
+            unlock_command: None,
+            lock_vt_switch: false,
+            lock_indicators: vec![Led::Caps],
+            lock_indicators_position: BarPosition::default(),
+            lock_indicators_orientation: Orientation::default(),
+            led_on_failure: None,
+            key_sound: None,
+            submit_sound: None,
+            failure_sound: None,
+            logind_unlock: false,
+            signal_unlock: false,
+            lock_on_lid_close: false,
+            pause_media_on_lock: false,
+            resume_media_on_unlock: false,
+            on_failure: HashMap::new(),
+            enable_panic_wipe: false,
+            panic_wipe_command: None,
+            panic_wipe_after: None,
+            capture_after: None,
+            camera_device: "/dev/video0".into(),
+            capture_dir: None,
+            banner_text: String::new(),
+            banner_position: BannerPosition::default(),
+            card: false,
+            card_color: None,
+            card_opacity: 0.1,
+            card_radius: 12,
+            card_padding: 24,
+            widgets: Vec::new(),
+            show_hostname: false,
+            hostname_position: Region::default(),
+            background_image: None,
+            background_timeout_ms: 5000,
+            background_mode: BackgroundMode::default(),
+            pre_blank: false,
+            background_delay_ms: None,
+            redact_regions: Vec::new(),
+            passthrough_regions: Vec::new(),
+            grab_server: false,
+            scale: None,
+            max_buffer_size: None,
+            ui_anchor: UiAnchor::default(),
+            ui_on: UiOn::default(),
+            theme_config: ThemeConfig::default(),
+            themes: HashMap::new(),
+            theme: Theme::default(),
+            accessibility: false,
+            strings: Strings::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Read the configuration from `path`, `-` meaning stdin, or from the
+    /// default location. Only a missing file at the default location is
+    /// fine, the defaults are used then.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let (mut config, origin) = match path {
+            Some(path) if path == Path::new("-") => (Self::read_stdin()?, "stdin".to_owned()),
+            Some(path) => (Self::read_file(path)?, path.display().to_string()),
+            None => {
+                let path = default_path()?;
+                let config = match Self::read_file(&path) {
+                    Err(e) if is_not_found(&e) => Config::default(),
+                    result => result?,
+                };
+                (config, path.display().to_string())
+            }
+        };
+
+        if !config.pin.is_empty() {
+            let pin = std::mem::take(&mut config.pin);
+            config.pins.insert(0, Pin::new(pin));
+        }
+        #[cfg(feature = "dbus")]
+        if let Some(PinSource::SecretService(application)) = &config.pin_source {
+            let pin = dbus::secrets::lookup(application).with_context(|| {
+                format!("Failed to read the PIN of pin_source, configured in {origin}")
+            })?;
+            config.pins.push(Pin::new(pin));
+        }
+        if cfg!(not(feature = "dbus")) && config.pin_source.is_some() {
+            warn!("Ignoring pin_source, pinlock was built without the dbus feature");
+        }
+        if config.pins.is_empty()
+            && config.auth_command.is_none()
+            && config.otp_command.is_none()
+            && config.pattern.is_none()
+        {
+            bail!(
+                "No PIN configured, set `pin`, `auth_command`, `otp_command` or `pattern` in {origin}"
+            );
+        }
+        if let Some(require) = &config.require {
+            require
+                .validate()
+                .with_context(|| format!("Invalid require in {origin}"))?;
+            let factors = require.factors();
+            if factors.contains(&&Factor::UsbKey) && config.usb_key_file.is_none() {
+                bail!("The usb-key factor of require needs a usb_key_file in {origin}");
+            }
+            if !factors.contains(&&Factor::UsbKey) && config.usb_key_file.is_some() {
+                warn!("Ignoring usb_key_file, require doesn't list usb-key");
+            }
+        } else if config.usb_key_file.is_some() {
+            warn!("Ignoring usb_key_file, it only counts as a factor of require");
+        }
+        if let Some(pattern) = &config.pattern {
+            if !pattern.with_plaintext(is_pattern) {
+                bail!("pattern must be at least two of the dots 1 to 9, each once, in {origin}");
+            }
+            if config.buttons || config.keypad {
+                warn!("Ignoring buttons and keypad, the pattern grid takes their place");
+                config.buttons = false;
+                config.keypad = false;
+            }
+        }
+        if config.max_fps == 0 {
+            bail!("max_fps must be at least 1 in {origin}");
+        }
+        if config.auth_timeout_secs == 0 {
+            bail!("auth_timeout_secs must be at least 1 in {origin}");
+        }
+        if config.otp_window_secs == 0 {
+            bail!("otp_window_secs must be at least 1 in {origin}");
+        }
+        if config.pins.iter().any(|pin| pin.pin.is_empty()) {
+            bail!("Empty PIN configured in {origin}");
+        }
+        if StrftimeItems::new(&config.clock_format).any(|item| item == Item::Error) {
+            bail!("Invalid clock_format in {origin}");
+        }
+        if config.indicator_style == IndicatorStyle::Bar && config.max_pin_length.is_none() {
+            bail!("indicator_style = \"bar\" needs max_pin_length in {origin}");
+        }
+        if config.progress_bar_height == Some(0) {
+            bail!("progress_bar_height must be at least 1 in {origin}");
+        }
+        if config.max_attempts == Some(0) {
+            bail!("max_attempts must be at least 1 in {origin}");
+        }
+        if let Some(display) = &config.display {
+            if let Err(err) = locker::parse_display(display) {
+                bail!("{err} in {origin}");
+            }
+        }
+        if let Some((0, _) | (_, 0)) = config.max_buffer_size {
+            bail!("max_buffer_size must be at least 1 on each side in {origin}");
+        }
+        if !(0.0..=1.0).contains(&config.dim_level) {
+            bail!("dim_level must be between 0 and 1 in {origin}");
+        }
+        if !(0.0..=1.0).contains(&config.idle_clock_brightness) {
+            bail!("idle_clock_brightness must be between 0 and 1 in {origin}");
+        }
+        if config.screensaver.is_some() && config.idle_clock_after_secs.is_some() {
+            warn!("Ignoring idle_clock_after_secs, the screensaver takes its place");
+        }
+        if !(0.0..=1.0).contains(&config.card_opacity) {
+            bail!("card_opacity must be between 0 and 1 in {origin}");
+        }
+        if config.capture_after == Some(0) {
+            bail!("capture_after must be at least 1 in {origin}");
+        }
+        if config.enable_panic_wipe {
+            if config.panic_wipe_command.is_none() {
+                bail!("enable_panic_wipe needs a panic_wipe_command in {origin}");
+            }
+            match config.panic_wipe_after {
+                None | Some(0) => {
+                    bail!("enable_panic_wipe needs panic_wipe_after of at least 1 in {origin}")
+                }
+                Some(after) => {
+                    warn!("The panic wipe is armed, {after} failed attempts run panic_wipe_command")
+                }
+            }
+        } else if config.panic_wipe_command.is_some() {
+            warn!("Ignoring panic_wipe_command, enable_panic_wipe is off");
+        }
+        if cfg!(not(feature = "audio"))
+            && [
+                &config.key_sound,
+                &config.submit_sound,
+                &config.failure_sound,
+            ]
+            .into_iter()
+            .any(|sound| matches!(sound, Some(Sound::Wav(_))))
+        {
+            warn!("Ignoring WAV sounds, pinlock was built without the audio feature");
+        }
+        if cfg!(not(feature = "camera")) && config.capture_after.is_some() {
+            warn!("Ignoring capture_after, pinlock was built without the camera feature");
+        }
+        if cfg!(not(feature = "vtlock")) && config.lock_vt_switch {
+            warn!("Ignoring lock_vt_switch, pinlock was built without the vtlock feature");
+        }
+        if cfg!(not(feature = "logind")) && config.logind_unlock {
+            warn!("Ignoring logind_unlock, pinlock was built without the logind feature");
+        }
+        if cfg!(not(feature = "logind")) && config.lock_on_lid_close {
+            warn!("Ignoring lock_on_lid_close, pinlock was built without the logind feature");
+        }
+        if cfg!(not(feature = "xinput2")) && config.pin_device.is_some() {
+            warn!("Ignoring pin_device, pinlock was built without the xinput2 feature");
+        }
+        if cfg!(not(feature = "dbus")) && config.pause_media_on_lock {
+            warn!("Ignoring pause_media_on_lock, pinlock was built without the dbus feature");
+        }
+        if !config.redact_regions.is_empty()
+            && config.background_image != Some(BackgroundImage::Screenshot)
+        {
+            warn!("Ignoring redact_regions, only screenshots are redacted");
+        }
+        if config.grab_pointer && !config.passthrough_regions.is_empty() {
+            warn!("Ignoring passthrough_regions, the pointer grab takes every click");
+            config.passthrough_regions.clear();
+        }
+        if config.randomness_source != RandomnessSource::Os && !config.keypad_randomize {
+            warn!("Ignoring randomness_source, only keypad_randomize draws from it");
+        }
+        if config.grab_server && config.use_fullscreen_hint {
+            warn!("Ignoring grab_server, use_fullscreen_hint needs the window manager to map");
+            config.grab_server = false;
+        }
+        if let Some(max) = config.max_pin_length {
+            let too_long =
+                |pin: &[u8]| std::str::from_utf8(pin).is_ok_and(|pin| pin.chars().count() > max);
+            if config
+                .pins
+                .iter()
+                .any(|pin| pin.pin.with_plaintext(too_long))
+            {
+                bail!("A PIN is longer than max_pin_length in {origin}");
+            }
+        }
+
+        let base_theme = if config.accessibility {
+            Theme::high_contrast()
+        } else {
+            Theme::default()
+        };
+        config.theme = config
+            .theme_config
+            .resolve(&config.themes, base_theme)
+            .with_context(|| format!("Invalid theme in {origin}"))?;
+
+        Ok(config)
+    }
+
+    /// Parse a configuration file and the files it includes, wiping the
+    /// PINs it contains from the buffer it was read into afterwards.
+    fn read_file(path: &Path) -> Result<Self> {
+        let mut contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let result = Self::parse(&contents, Some(path))
+            .with_context(|| format!("Failed to parse {}", path.display()));
+        contents.zeroize();
+        result
+    }
+
+    /// Parse a configuration piped in, wiping the PINs it contains from the
+    /// input buffer afterwards. Includes are relative to the working
+    /// directory.
+    fn read_stdin() -> Result<Self> {
+        let mut contents = String::new();
+        let result = io::stdin()
+            .read_to_string(&mut contents)
+            .context("Failed to read the configuration from stdin")
+            .and_then(|_| {
+                Self::parse(&contents, None).context("Failed to parse the configuration from stdin")
+            });
+        contents.zeroize();
+        result
+    }
+
+    /// Parse `contents`, read from `path`, merging in the files it
+    /// includes. Without includes, it is parsed directly, so that errors
+    /// point at the line they are on.
+    fn parse(contents: &str, path: Option<&Path>) -> Result<Self> {
+        let table: Table = toml::from_str(contents)?;
+        if !table.contains_key("include") {
+            wipe(Value::Table(table));
+            return Ok(toml::from_str(contents)?);
+        }
+
+        let mut merged = Table::new();
+        let mut chain = Vec::new();
+        merge_included(&mut merged, table, path, &mut chain)?;
+        Ok(Value::Table(merged).try_into()?)
+    }
+
+    pub fn grace(&self) -> Duration {
+        Duration::from_secs(self.grace_secs)
+    }
+
+    pub fn input_ignore(&self) -> Duration {
+        Duration::from_millis(self.input_ignore_ms)
+    }
+
+    pub fn input_redraw(&self) -> Duration {
+        Duration::from_millis(self.input_redraw_ms)
+    }
+
+    pub fn incorrect_pin_timeout(&self) -> Option<Duration> {
+        (self.incorrect_pin_timeout_ms > 0)
+            .then(|| Duration::from_millis(self.incorrect_pin_timeout_ms))
+    }
+
+    pub fn hold_to_submit(&self) -> Duration {
+        Duration::from_millis(self.hold_to_submit_ms)
+    }
+
+    pub fn first_frame_wait(&self) -> Duration {
+        Duration::from_millis(self.first_frame_wait_ms)
+    }
+
+    pub fn grab_check(&self) -> Option<Duration> {
+        self.grab_check_ms.map(Duration::from_millis)
+    }
+
+    /// The failed attempts that trigger the panic wipe and its command,
+    /// only if it is armed.
+    pub fn panic_wipe(&self) -> Option<(u32, &str)> {
+        if !self.enable_panic_wipe {
+            return None;
+        }
+        Some((self.panic_wipe_after?, self.panic_wipe_command.as_deref()?))
+    }
+
+    pub fn auth_timeout(&self) -> Duration {
+        Duration::from_secs(self.auth_timeout_secs)
+    }
+
+    pub fn otp_window(&self) -> Duration {
+        Duration::from_secs(self.otp_window_secs)
+    }
+
+    pub fn lockout(&self) -> Duration {
+        Duration::from_secs(self.lockout_secs)
+    }
+
+    pub fn idle(&self) -> Duration {
+        Duration::from_secs(self.idle_secs)
+    }
+
+    pub fn background_timeout(&self) -> Duration {
+        Duration::from_millis(self.background_timeout_ms)
+    }
+
+    /// How long after locking to start preparing the background image, if
+    /// the plain background color is shown meanwhile.
+    pub fn background_delay(&self) -> Option<Duration> {
+        match self.background_delay_ms {
+            Some(delay) => Some(Duration::from_millis(delay)),
+            None => self.pre_blank.then_some(Duration::ZERO),
+        }
+    }
+
+    pub fn constant_time_response(&self) -> Option<Duration> {
+        self.constant_time_response_ms.map(Duration::from_millis)
+    }
+
+    pub fn max_buffer_size(&self) -> (u16, u16) {
+        self.max_buffer_size
+            .unwrap_or((buffer::MAX_PIXMAP_SIDE, buffer::MAX_PIXMAP_SIDE))
+    }
+
+    pub fn max_session(&self) -> Option<Duration> {
+        self.max_session_secs.map(Duration::from_secs)
+    }
+
+    pub fn dim(&self) -> Option<Duration> {
+        self.dim_secs.map(Duration::from_secs)
+    }
+
+    pub fn screensaver_delay(&self) -> Duration {
+        Duration::from_secs(self.screensaver_secs)
+    }
+
+    pub fn idle_clock_after(&self) -> Option<Duration> {
+        self.idle_clock_after_secs.map(Duration::from_secs)
+    }
+}
+
+/// Every piece of text the lock screen displays, overridable from the
+/// `[strings]` section to localize or customize the wording.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Strings {
+    /// Shown while the grace period allows dismissing the lock.
+    pub press_any_key: String,
+    /// Shown by `pinlock blank`, which doesn't lock.
+    pub blanked: String,
+    /// Shown while a submitted PIN is being checked.
+    pub verifying: String,
+    /// Shown after a wrong PIN was submitted.
+    pub incorrect_pin: String,
+    /// Shown instead of `incorrect_pin` with `show_remaining_attempts`,
+    /// `{remaining}` is replaced by the attempts left before the lockout.
+    pub remaining_attempts: String,
+    /// Shown above the PIN while Caps Lock, Num Lock or Scroll Lock is
+    /// engaged, if in `lock_indicators`.
+    pub caps_lock: String,
+    pub num_lock: String,
+    pub scroll_lock: String,
+    /// Shown instead of the PIN during a lockout, `{seconds}` is replaced by
+    /// the time left.
+    pub locked_out: String,
+    /// Labels of the on-screen buttons.
+    pub submit: String,
+    pub clear: String,
+    /// Asks for the PIN when locking the terminal, with `--allow-console`.
+    pub console_prompt: String,
+    /// Lines of the `access` widget, `{time}` is replaced by the time, or
+    /// by `never`.
+    pub last_unlocked: String,
+    pub last_failed: String,
+    pub never: String,
+}
+
+impl Default for Strings {
+    fn default() -> Self {
+        Self {
+            press_any_key: "Press any key".into(),
+            blanked: "Not locked, press any key to show the screen".into(),
+            verifying: "Verifying PIN".into(),
+            incorrect_pin: "Incorrect PIN".into(),
+            remaining_attempts: "Incorrect PIN, {remaining} attempts remaining before lockout"
+                .into(),
+            caps_lock: "CAPS LOCK".into(),
+            num_lock: "NUM LOCK".into(),
+            scroll_lock: "SCROLL LOCK".into(),
+            locked_out: "Locked out, try again in {seconds}s".into(),
+            submit: "Submit".into(),
+            clear: "Clear".into(),
+            console_prompt: "PIN: ".into(),
+            last_unlocked: "Last unlocked: {time}".into(),
+            last_failed: "Last failed attempt: {time}".into(),
+            never: "never".into(),
+        }
+    }
+}
+
+/// Failed attempts an `on_failure` command is run on. Parsed from a string,
+/// as TOML table keys are always strings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(try_from = "String")]
+pub struct FailureCount(pub u32);
+
+impl TryFrom<String> for FailureCount {
+    type Error = String;
+
+    fn try_from(count: String) -> Result<Self, Self::Error> {
+        match count.parse() {
+            Ok(count) if count > 0 => Ok(Self(count)),
+            _ => Err(format!("`{count}` is not a count of failed attempts")),
+        }
+    }
+}
+
+/// Where a PIN is read from besides the configuration, for `pin_source`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum PinSource {
+    /// The `application` attribute of a Secret Service item
+    SecretService(String),
+}
+
+impl TryFrom<String> for PinSource {
+    type Error = String;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        match source.split_once(':') {
+            Some(("secret-service", application)) if !application.is_empty() => {
+                Ok(Self::SecretService(application.to_owned()))
+            }
+            _ => Err(format!(
+                "`{source}` is not a PIN source, expected `secret-service:NAME`"
+            )),
+        }
+    }
+}
+
+/// Whether `pattern` taps two or more different dots of the grid.
+fn is_pattern(pattern: &[u8]) -> bool {
+    pattern.len() >= 2
+        && pattern.iter().all(|dot| (b'1'..=b'9').contains(dot))
+        && pattern
+            .iter()
+            .enumerate()
+            .all(|(i, dot)| !pattern[..i].contains(dot))
+}
+
+/// Merge the files that `table`, read from `path`, includes into `merged`,
+/// and then `table` itself. `chain` holds the files being merged, the
+/// including ones first, to catch files including themselves.
+fn merge_included(
+    merged: &mut Table,
+    mut table: Table,
+    path: Option<&Path>,
+    chain: &mut Vec<PathBuf>,
+) -> Result<()> {
+    let includes = match table.remove("include") {
+        None => Vec::new(),
+        Some(Value::Array(includes)) => includes
+            .into_iter()
+            .map(|include| match include {
+                Value::String(include) => Ok(PathBuf::from(include)),
+                _ => bail!("include must be a list of paths"),
+            })
+            .collect::<Result<_>>()?,
+        Some(_) => bail!("include must be a list of paths"),
+    };
+
+    let dir = path.and_then(Path::parent).unwrap_or(Path::new(""));
+    if let Some(path) = path {
+        chain.push(path.canonicalize()?);
+    }
+    for include in includes {
+        let include = dir.join(include);
+        let canonical = include
+            .canonicalize()
+            .with_context(|| format!("Failed to read {}", include.display()))?;
+        if chain.contains(&canonical) {
+            bail!("{} includes itself", include.display());
+        }
+
+        let mut contents = fs::read_to_string(&include)
+            .with_context(|| format!("Failed to read {}", include.display()))?;
+        let parsed = toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse {}", include.display()));
+        contents.zeroize();
+        merge_included(merged, parsed?, Some(&include), chain)?;
+        debug!("Merged {} into the configuration", include.display());
+    }
+    if path.is_some() {
+        chain.pop();
+    }
+
+    merge(merged, table);
+    Ok(())
+}
+
+/// Override the keys of `into` with those of `from`, merging tables.
+fn merge(into: &mut Table, from: Table) {
+    for (key, value) in from {
+        match (into.get_mut(&key), value) {
+            (Some(Value::Table(into)), Value::Table(from)) => merge(into, from),
+            (_, value) => {
+                if let Some(overridden) = into.insert(key, value) {
+                    wipe(overridden);
+                }
+            }
+        }
+    }
+}
+
+/// Drop `value`, wiping the strings in it, as they may be PINs.
+fn wipe(value: Value) {
+    match value {
+        Value::String(mut string) => string.zeroize(),
+        Value::Array(values) => values.into_iter().for_each(wipe),
+        Value::Table(table) => table.into_iter().for_each(|(_, value)| wipe(value)),
+        _ => {}
+    }
+}
+
+fn is_not_found(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<io::Error>()
+        .is_some_and(|e| e.kind() == io::ErrorKind::NotFound)
+}
+
+fn default_path() -> Result<PathBuf> {
+    let config_dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => {
+            let home = env::var_os("HOME").context("Neither XDG_CONFIG_HOME nor HOME is set")?;
+            PathBuf::from(home).join(".config")
+        }
+    };
+
+    Ok(config_dir.join("pinlock").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+
+    #[test]
+    fn overriding_a_string_keeps_the_other_defaults() {
+        let config: Config = toml::from_str(
+            r#"
+            pin = "1234"
+
+            [strings]
+            incorrect_pin = "Falsche PIN"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.strings.incorrect_pin, "Falsche PIN");
+        assert_eq!(
+            config.strings.press_any_key,
+            Strings::default().press_any_key
+        );
+    }
+
+    #[test]
+    fn on_failure_is_keyed_by_the_count() {
+        let config: Config = toml::from_str(
+            r#"
+            pin = "1234"
+            on_failure = { 3 = "true", 10 = "poweroff" }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.on_failure[&FailureCount(3)], "true");
+        assert_eq!(config.on_failure[&FailureCount(10)], "poweroff");
+    }
+
+    #[test]
+    fn the_panic_wipe_needs_both_opt_ins() {
+        let config: Config = toml::from_str(
+            r#"
+            panic_wipe_command = "wipe"
+            panic_wipe_after = 5
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.panic_wipe(), None);
+
+        let config = Config {
+            enable_panic_wipe: true,
+            ..config
+        };
+        assert_eq!(config.panic_wipe(), Some((5, "wipe")));
+    }
+
+    #[test]
+    fn on_failure_counts_start_at_one() {
+        assert!(toml::from_str::<Config>(r#"on_failure = { 0 = "true" }"#).is_err());
+    }
+
+    #[test]
+    fn pin_source_names_the_secret_service_item() {
+        let config: Config = toml::from_str(r#"pin_source = "secret-service:pinlock""#).unwrap();
+        assert_eq!(
+            config.pin_source,
+            Some(PinSource::SecretService("pinlock".into()))
+        );
+        assert!(toml::from_str::<Config>(r#"pin_source = "secret-service:""#).is_err());
+        assert!(toml::from_str::<Config>(r#"pin_source = "keyring:pinlock""#).is_err());
+    }
+
+    #[test]
+    fn included_files_are_overridden_by_the_including_one() {
+        let dir = env::temp_dir().join(format!("pinlock-include-{}", process::id()));
+        fs::create_dir_all(dir.join("common")).unwrap();
+        fs::write(
+            dir.join("common/base.toml"),
+            "pin = \"1234\"\nidle_secs = 60\ngrace_secs = 5\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("local.toml"),
+            "include = [\"common/base.toml\"]\nidle_secs = 120\n",
+        )
+        .unwrap();
+        fs::write(dir.join("loop.toml"), "include = [\"loop.toml\"]\n").unwrap();
+
+        let config = Config::read_file(&dir.join("local.toml")).unwrap();
+        let looped = Config::read_file(&dir.join("loop.toml"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(config.pin, "1234");
+        assert_eq!(config.idle_secs, 120);
+        assert_eq!(config.grace_secs, 5);
+        assert!(looped.is_err());
+    }
+}
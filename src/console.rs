@@ -0,0 +1,176 @@
+//! A `vlock`-like lock of the terminal, for `--allow-console` when there is
+//! no X server to lock. The PIN is read from the controlling terminal with
+//! echo off and checked like on the lock screen, attempts and lockouts
+//! included.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Write},
+    mem::MaybeUninit,
+    os::fd::AsRawFd,
+    thread,
+};
+
+use anyhow::{bail, Context, Result};
+use log::{info, warn};
+use zeroize::Zeroizing;
+
+#[cfg(feature = "metrics")]
+use crate::metrics::{self, Histogram};
+#[cfg(feature = "vtlock")]
+use crate::vt::VtLock;
+use crate::{
+    audit,
+    auth::{Authenticator, OneTimePin},
+    config::Config,
+    locker,
+    persist::Persisted,
+    signals::Signals,
+    state::{LockState, UnlockReason},
+};
+
+const TTY: &str = "/dev/tty";
+
+/// Longest line read as a PIN, anything after it counts as the next one.
+const MAX_LINE: usize = 256;
+
+/// Lock the controlling terminal until the PIN is entered.
+pub fn lock(config: &Config, signals: &Signals) -> Result<UnlockReason> {
+    if config.pins.is_empty() && config.auth_command.is_none() && config.otp_command.is_none() {
+        bail!("Only the unlock pattern is configured, which can't be entered on a terminal");
+    }
+    let tty = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(TTY)
+        .with_context(|| format!("Failed to open {TTY}"))?;
+    let _mode = TerminalMode::set(&tty)?;
+    let mut terminal = &tty;
+    #[cfg(feature = "vtlock")]
+    let _vt_lock = config
+        .lock_vt_switch
+        .then(|| VtLock::acquire().map_err(|err| warn!("{err:#}")).ok())
+        .flatten();
+
+    let one_time_pin = config
+        .otp_command
+        .as_deref()
+        .map(|command| OneTimePin::new(command, config.otp_window(), config.auth_timeout()));
+    let auth = Authenticator::from_config(config).with_one_time_pin(one_time_pin);
+    let mut state = LockState::new(config, &Persisted::load());
+    audit::record(audit::Event::Locked);
+
+    let reason = loop {
+        if signals.terminate() {
+            break UnlockReason::Signal;
+        }
+        if signals.take_unlock_signal() {
+            if config.signal_unlock {
+                break UnlockReason::SignalUnlock;
+            }
+            info!("Ignoring SIGUSR2, signal_unlock is off");
+        }
+        if let Some(remaining) = state.lockout_remaining() {
+            let seconds = remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0);
+            let text = config
+                .strings
+                .locked_out
+                .replace("{seconds}", &seconds.to_string());
+            writeln!(terminal, "{text}")?;
+            thread::sleep(remaining);
+            // Typed meanwhile, which doesn't count
+            // SAFETY: the descriptor is valid for as long as `tty` lives
+            unsafe { libc::tcflush(tty.as_raw_fd(), libc::TCIFLUSH) };
+            continue;
+        }
+
+        write!(terminal, "{}", config.strings.console_prompt)?;
+        terminal.flush()?;
+        let mut line = Zeroizing::new([0; MAX_LINE]);
+        let read = match terminal.read(&mut line[..]) {
+            Ok(0) => bail!("{TTY} was closed"),
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err).context(format!("Failed to read from {TTY}")),
+        };
+        // Echo is off, the newline too
+        writeln!(terminal)?;
+
+        let entered = std::str::from_utf8(&line[..read]).unwrap_or_default();
+        for c in entered.trim_end_matches(['\r', '\n']).chars() {
+            state.on_char(c);
+        }
+        // Padded to constant_time_response_ms on the checking thread
+        state.on_submit(&auth);
+        let reason = state.wait_verified().flatten();
+        state.persisted().save();
+        if let Some(reason) = reason {
+            break reason;
+        }
+
+        let failed_attempts = state.failed_attempts();
+        audit::record(audit::Event::FailedAttempt { failed_attempts });
+        locker::panic_wipe(config, failed_attempts);
+        let text = match state
+            .attempts_remaining()
+            .filter(|_| config.show_remaining_attempts)
+        {
+            Some(remaining) => config
+                .strings
+                .remaining_attempts
+                .replace("{remaining}", &remaining.to_string()),
+            None => config.strings.incorrect_pin.clone(),
+        };
+        writeln!(terminal, "{text}")?;
+    };
+
+    audit::record(audit::Event::Unlocked {
+        reason: &reason,
+        failed_attempts: state.failed_attempts(),
+    });
+    #[cfg(feature = "metrics")]
+    metrics::log_summary(&Histogram::default(), state.auth_times());
+    Ok(reason)
+}
+
+/// The terminal without echo, and without the keys that send signals or end
+/// the input, so that neither Ctrl+C nor Ctrl+D gets past the lock. Set back
+/// once dropped.
+struct TerminalMode<'tty> {
+    tty: &'tty File,
+    saved: libc::termios,
+}
+
+impl<'tty> TerminalMode<'tty> {
+    fn set(tty: &'tty File) -> Result<Self> {
+        let fd = tty.as_raw_fd();
+        let mut termios = MaybeUninit::uninit();
+        // SAFETY: the descriptor is valid for as long as `tty` lives, and
+        // `termios` is only read once filled in
+        let saved = unsafe {
+            if libc::tcgetattr(fd, termios.as_mut_ptr()) != 0 {
+                return Err(io::Error::last_os_error()).context(format!("{TTY} isn't a terminal"));
+            }
+            termios.assume_init()
+        };
+
+        let mut locked = saved;
+        locked.c_lflag &= !(libc::ECHO | libc::ISIG);
+        locked.c_lflag |= libc::ICANON;
+        locked.c_cc[libc::VEOF] = 0;
+        // SAFETY: as above
+        if unsafe { libc::tcsetattr(fd, libc::TCSAFLUSH, &locked) } != 0 {
+            return Err(io::Error::last_os_error()).context(format!("Failed to set up {TTY}"));
+        }
+        Ok(Self { tty, saved })
+    }
+}
+
+impl Drop for TerminalMode<'_> {
+    fn drop(&mut self) {
+        // SAFETY: the descriptor is valid for as long as `tty` lives
+        if unsafe { libc::tcsetattr(self.tty.as_raw_fd(), libc::TCSANOW, &self.saved) } != 0 {
+            warn!("Failed to restore {TTY}: {}", io::Error::last_os_error());
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use log::warn;
+use serde::Deserialize;
+use x11rb::{
+    connection::Connection,
+    protocol::{
+        render::{ConnectionExt as _, CreatePictureAux, PictType},
+        xproto::{ConnectionExt, Cursor, ImageFormat, ImageOrder, Pixmap, Window},
+    },
+    rust_connection::RustConnection,
+    NONE,
+};
+
+/// Glyph of the arrow pointer in the standard cursor font.
+const XC_LEFT_PTR: u16 = 68;
+
+/// The pointer shown while locked: `"default"`, `"hidden"` or a path to an
+/// image file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(from = "String")]
+pub enum CursorConfig {
+    /// Whatever cursor the root window has.
+    #[default]
+    Default,
+    Hidden,
+    Image(PathBuf),
+}
+
+impl From<String> for CursorConfig {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "default" => Self::Default,
+            "hidden" => Self::Hidden,
+            _ => Self::Image(value.into()),
+        }
+    }
+}
+
+/// Create the configured cursor, `NONE` meaning the parent's cursor. The
+/// caller owns the returned cursor and frees it once it's attached.
+pub fn create(
+    connection: &RustConnection,
+    window: Window,
+    config: &CursorConfig,
+) -> Result<Cursor> {
+    match config {
+        CursorConfig::Default => Ok(NONE),
+        CursorConfig::Hidden => create_hidden(connection, window),
+        CursorConfig::Image(path) => match create_from_image(connection, window, path) {
+            Ok(cursor) => Ok(cursor),
+            Err(err) => {
+                warn!("Falling back to the standard cursor: {err:#}");
+                create_glyph(connection)
+            }
+        },
+    }
+}
+
+/// An empty 1x1 cursor.
+fn create_hidden(connection: &RustConnection, window: Window) -> Result<Cursor> {
+    let pixmap: Pixmap = connection.generate_id()?;
+    connection.create_pixmap(1, pixmap, window, 1, 1)?;
+
+    let cursor = connection.generate_id()?;
+    connection.create_cursor(cursor, pixmap, pixmap, 0, 0, 0, 0, 0, 0, 0, 0)?;
+    connection.free_pixmap(pixmap)?;
+
+    Ok(cursor)
+}
+
+/// The arrow from the core cursor font, available on every server.
+fn create_glyph(connection: &RustConnection) -> Result<Cursor> {
+    let font = connection.generate_id()?;
+    connection.open_font(font, b"cursor")?;
+
+    let cursor = connection.generate_id()?;
+    connection.create_glyph_cursor(
+        cursor,
+        font,
+        font,
+        XC_LEFT_PTR,
+        XC_LEFT_PTR + 1,
+        0,
+        0,
+        0,
+        0xffff,
+        0xffff,
+        0xffff,
+    )?;
+    connection.close_font(font)?;
+
+    Ok(cursor)
+}
+
+/// An ARGB cursor built from an image through the RENDER extension, with
+/// its hotspot in the top left corner.
+fn create_from_image(connection: &RustConnection, window: Window, path: &Path) -> Result<Cursor> {
+    let image = image::open(path)
+        .with_context(|| format!("Failed to load the cursor image {}", path.display()))?
+        .into_rgba8();
+    let (width, height) = (
+        u16::try_from(image.width())?,
+        u16::try_from(image.height())?,
+    );
+
+    let formats = connection
+        .render_query_pict_formats()
+        .context("The RENDER extension is required for image cursors")?
+        .reply()?;
+    let format = formats
+        .formats
+        .iter()
+        .find(|format| {
+            format.type_ == PictType::DIRECT
+                && format.depth == 32
+                && format.direct.alpha_mask == 0xff
+                && format.direct.alpha_shift == 24
+                && format.direct.red_shift == 16
+                && format.direct.green_shift == 8
+                && format.direct.blue_shift == 0
+        })
+        .context("The server has no ARGB32 picture format")?;
+
+    // Premultiplied ARGB pixels, in the byte order the server expects
+    let msb_first = connection.setup().image_byte_order == ImageOrder::MSB_FIRST;
+    let data: Vec<u8> = image
+        .pixels()
+        .flat_map(|pixel| {
+            let [r, g, b, a] = pixel.0;
+            let premultiply = |c: u8| (u16::from(c) * u16::from(a) / 255) as u8;
+            let argb = u32::from_be_bytes([a, premultiply(r), premultiply(g), premultiply(b)]);
+            if msb_first {
+                argb.to_be_bytes()
+            } else {
+                argb.to_le_bytes()
+            }
+        })
+        .collect();
+
+    let pixmap = connection.generate_id()?;
+    connection.create_pixmap(32, pixmap, window, width, height)?;
+
+    let gc = connection.generate_id()?;
+    connection.create_gc(gc, pixmap, &Default::default())?;
+    connection.put_image(
+        ImageFormat::Z_PIXMAP,
+        pixmap,
+        gc,
+        width,
+        height,
+        0,
+        0,
+        0,
+        32,
+        &data,
+    )?;
+    connection.free_gc(gc)?;
+
+    let picture = connection.generate_id()?;
+    connection.render_create_picture(picture, pixmap, format.id, &CreatePictureAux::new())?;
+
+    let cursor = connection.generate_id()?;
+    connection.render_create_cursor(cursor, picture, 0, 0)?;
+
+    connection.render_free_picture(picture)?;
+    connection.free_pixmap(pixmap)?;
+
+    Ok(cursor)
+}
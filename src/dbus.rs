@@ -0,0 +1,346 @@
+//! Lock requests from the desktop, for daemon mode, and pausing media players.
+//!
+//! Claims `org.freedesktop.ScreenSaver` on the session bus, serving its
+//! `Lock` method at `/org/freedesktop/ScreenSaver` and `/ScreenSaver`. With
+//! the `logind` feature, the `Lock` and `Unlock` signals of the session's
+//! `org.freedesktop.login1.Session` object on the system bus are followed as
+//! well, so that `loginctl lock-session` and `unlock-session` reach pinlock.
+//! The latter only unlocks with `logind_unlock`. With `lock_on_lid_close`,
+//! logind's `LidClosed` property is watched as well.
+//!
+//! Players are paused through MPRIS, see [`mpris`], and `pin_source` is
+//! read from the Secret Service, see [`secrets`].
+
+use anyhow::{Context, Result};
+use zbus::{blocking::connection, interface};
+
+use crate::signals::Requests;
+
+const SCREENSAVER_NAME: &str = "org.freedesktop.ScreenSaver";
+
+struct ScreenSaver {
+    requests: Requests,
+}
+
+#[interface(name = "org.freedesktop.ScreenSaver")]
+impl ScreenSaver {
+    fn lock(&self) {
+        self.requests.lock();
+    }
+}
+
+/// Keeps serving lock requests while alive.
+pub struct Bus {
+    _session: zbus::blocking::Connection,
+}
+
+/// Connect to the buses and start forwarding lock requests, and lid closes
+/// if `watch_lid`.
+pub fn serve(
+    requests: &Requests,
+    #[cfg_attr(not(feature = "logind"), allow(unused_variables))] watch_lid: bool,
+) -> Result<Bus> {
+    let session = connection::Builder::session()?
+        .name(SCREENSAVER_NAME)?
+        .serve_at(
+            "/org/freedesktop/ScreenSaver",
+            ScreenSaver {
+                requests: requests.clone(),
+            },
+        )?
+        .serve_at(
+            "/ScreenSaver",
+            ScreenSaver {
+                requests: requests.clone(),
+            },
+        )?
+        .build()
+        .with_context(|| format!("Failed to claim {SCREENSAVER_NAME} on the session bus"))?;
+
+    #[cfg(feature = "logind")]
+    logind::follow(requests.clone()).context("Failed to subscribe to logind")?;
+    #[cfg(feature = "logind")]
+    if watch_lid {
+        logind::watch_lid(requests.clone()).context("Failed to watch the lid")?;
+    }
+
+    Ok(Bus { _session: session })
+}
+
+/// Pausing the media players of the session while locked.
+pub mod mpris {
+    use std::thread::{self, JoinHandle};
+
+    use anyhow::Result;
+    use log::{error, info};
+    use zbus::{
+        blocking::{fdo::DBusProxy, Connection, Proxy},
+        names::OwnedBusName,
+    };
+
+    const PLAYER_PREFIX: &str = "org.mpris.MediaPlayer2.";
+    const PLAYER_PATH: &str = "/org/mpris/MediaPlayer2";
+    const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+
+    /// The players that were playing before [`pause`].
+    pub struct Paused {
+        players: JoinHandle<Vec<OwnedBusName>>,
+    }
+
+    /// Pause every player that is playing. Runs on a thread of its own, so
+    /// that an unresponsive player can't hold up locking.
+    pub fn pause() -> Paused {
+        let players = thread::spawn(|| match pause_playing() {
+            Ok(players) => players,
+            Err(err) => {
+                error!("Failed to pause the media players: {err:#}");
+                Vec::new()
+            }
+        });
+        Paused { players }
+    }
+
+    impl Paused {
+        /// Resume the players paused before, leaving those that were paused
+        /// already alone.
+        pub fn resume(self) {
+            thread::spawn(move || {
+                let players = self.players.join().unwrap_or_default();
+                if players.is_empty() {
+                    return;
+                }
+                if let Err(err) = call_all(&players, "Play") {
+                    error!("Failed to resume the media players: {err:#}");
+                }
+            });
+        }
+    }
+
+    fn pause_playing() -> Result<Vec<OwnedBusName>> {
+        let session = Connection::session()?;
+        let mut playing = Vec::new();
+        for name in DBusProxy::new(&session)?.list_names()? {
+            if !name.starts_with(PLAYER_PREFIX) {
+                continue;
+            }
+            let player = player(&session, &name)?;
+            if player.get_property::<String>("PlaybackStatus")? == "Playing" {
+                player.call_method("Pause", &())?;
+                info!("Paused {}", name.as_str());
+                playing.push(name);
+            }
+        }
+        Ok(playing)
+    }
+
+    fn call_all(players: &[OwnedBusName], method: &str) -> Result<()> {
+        let session = Connection::session()?;
+        for name in players {
+            player(&session, name)?.call_method(method, &())?;
+        }
+        Ok(())
+    }
+
+    fn player<'a>(session: &'a Connection, name: &OwnedBusName) -> Result<Proxy<'a>> {
+        Ok(Proxy::new(
+            session,
+            name.clone(),
+            PLAYER_PATH,
+            PLAYER_INTERFACE,
+        )?)
+    }
+}
+
+/// Reading a PIN from the Secret Service, such as GNOME Keyring or KWallet,
+/// through `org.freedesktop.secrets`.
+pub mod secrets {
+    use std::collections::HashMap;
+
+    use anyhow::{bail, Context, Result};
+    use zbus::{
+        blocking::{Connection, Proxy},
+        zvariant::{OwnedObjectPath, OwnedValue, Value},
+    };
+    use zeroize::Zeroizing;
+
+    const SERVICE: &str = "org.freedesktop.secrets";
+
+    /// The secret of the first unlocked item whose `application` attribute
+    /// is `application`. The buffer it arrives in is wiped once copied.
+    pub fn lookup(application: &str) -> Result<String> {
+        let session = Connection::session()?;
+        let service = Proxy::new(
+            &session,
+            SERVICE,
+            "/org/freedesktop/secrets",
+            "org.freedesktop.Secret.Service",
+        )?;
+
+        let attributes = HashMap::from([("application", application)]);
+        let (unlocked, locked): (Vec<OwnedObjectPath>, Vec<OwnedObjectPath>) = service
+            .call("SearchItems", &(attributes,))
+            .context("Failed to search the Secret Service")?;
+        let Some(item) = unlocked.first() else {
+            if locked.is_empty() {
+                bail!("No secret has the attribute application={application}");
+            }
+            bail!("The keyring holding application={application} is locked, unlock it first");
+        };
+
+        // Not encrypted, the secret only passes through the session bus
+        let (_, transfer): (OwnedValue, OwnedObjectPath) = service
+            .call("OpenSession", &("plain", Value::from("")))
+            .context("Failed to open a Secret Service session")?;
+        let secret = Proxy::new(&session, SERVICE, item, "org.freedesktop.Secret.Item")?
+            .call::<_, _, (OwnedObjectPath, Vec<u8>, Vec<u8>, String)>("GetSecret", &(&transfer,));
+        let _ = Proxy::new(
+            &session,
+            SERVICE,
+            &transfer,
+            "org.freedesktop.Secret.Session",
+        )
+        .and_then(|proxy| proxy.call::<_, _, ()>("Close", &()));
+
+        let (_, _, value, _) = secret.context("Failed to read the secret")?;
+        let value = Zeroizing::new(value);
+        let pin = std::str::from_utf8(&value).context("The secret isn't UTF-8")?;
+        Ok(pin.trim_end_matches('\n').to_owned())
+    }
+}
+
+#[cfg(feature = "logind")]
+pub mod logind {
+    use std::{thread, time::Duration};
+
+    use anyhow::{bail, Result};
+    use log::{error, info};
+    use zbus::{
+        blocking::{Connection, MessageIterator, Proxy},
+        message::Type,
+        zvariant::{OwnedObjectPath, OwnedValue},
+        MatchRule,
+    };
+
+    use crate::signals::Requests;
+
+    /// How often the lid is checked. logind doesn't signal changes of
+    /// `LidClosed`.
+    const LID_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+    /// Listen for the `Lock` and `Unlock` signals of our session on a
+    /// thread of its own.
+    pub fn follow(requests: Requests) -> Result<()> {
+        let system = Connection::system()?;
+
+        // Signals are sent from the session's real path, `auto` is only an alias
+        let manager = Proxy::new(
+            &system,
+            "org.freedesktop.login1",
+            "/org/freedesktop/login1",
+            "org.freedesktop.login1.Manager",
+        )?;
+        let session: OwnedObjectPath = manager.call("GetSession", &("auto",))?;
+        info!("Following logind session {}", session.as_str());
+
+        let rule = MatchRule::builder()
+            .msg_type(Type::Signal)
+            .sender("org.freedesktop.login1")?
+            .interface("org.freedesktop.login1.Session")?
+            .path(session)?
+            .build();
+        let messages = MessageIterator::for_match_rule(rule, &system, None)?;
+
+        thread::spawn(move || {
+            for message in messages {
+                let message = match message {
+                    Ok(message) => message,
+                    Err(err) => {
+                        error!("Lost the connection to logind: {err}");
+                        return;
+                    }
+                };
+                match message.header().member().map(|member| member.as_str()) {
+                    Some("Lock") => requests.lock(),
+                    Some("Unlock") => requests.unlock(),
+                    _ => {}
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// The X display of the user's graphical session, for a daemon started
+    /// outside of it, such as by a systemd user unit.
+    pub fn session_display() -> Result<String> {
+        let system = Connection::system()?;
+        let manager = Proxy::new(
+            &system,
+            "org.freedesktop.login1",
+            "/org/freedesktop/login1",
+            "org.freedesktop.login1.Manager",
+        )?;
+        // SAFETY: always safe to call
+        let uid = unsafe { libc::getuid() };
+        let user: OwnedObjectPath = manager.call("GetUser", &(uid,))?;
+        let user = Proxy::new(
+            &system,
+            "org.freedesktop.login1",
+            user,
+            "org.freedesktop.login1.User",
+        )?;
+        let (_, session): (String, OwnedObjectPath) = user.get_property("Display")?;
+        if session.as_str() == "/" {
+            bail!("The user has no graphical session");
+        }
+
+        let session = Proxy::new(
+            &system,
+            "org.freedesktop.login1",
+            session,
+            "org.freedesktop.login1.Session",
+        )?;
+        let display: String = session.get_property("Display")?;
+        if display.is_empty() {
+            bail!("The graphical session has no X display");
+        }
+        Ok(display)
+    }
+
+    /// Poll logind's `LidClosed` on a thread of its own, reporting each
+    /// time the lid goes from open to closed.
+    pub fn watch_lid(requests: Requests) -> Result<()> {
+        let system = Connection::system()?;
+        let properties = Proxy::new_owned(
+            system,
+            "org.freedesktop.login1",
+            "/org/freedesktop/login1",
+            "org.freedesktop.DBus.Properties",
+        )?;
+        let lid_closed = move || -> Result<bool> {
+            let value: OwnedValue =
+                properties.call("Get", &("org.freedesktop.login1.Manager", "LidClosed"))?;
+            Ok(bool::try_from(value)?)
+        };
+        // Fails right away without a lid
+        let mut was_closed = lid_closed()?;
+
+        thread::spawn(move || loop {
+            thread::sleep(LID_POLL_INTERVAL);
+            match lid_closed() {
+                Ok(closed) => {
+                    if closed && !was_closed {
+                        requests.lid_closed();
+                    }
+                    was_closed = closed;
+                }
+                Err(err) => {
+                    error!("Stopped watching the lid: {err:#}");
+                    return;
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
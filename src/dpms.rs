@@ -0,0 +1,23 @@
+use anyhow::Result;
+use x11rb::{
+    connection::RequestConnection,
+    protocol::dpms::{self, ConnectionExt as _, DPMSMode},
+    rust_connection::RustConnection,
+};
+
+/// Turn the display back on if DPMS put it into a power saving mode.
+/// Servers without DPMS are left alone.
+pub fn force_on(connection: &RustConnection) -> Result<()> {
+    if connection
+        .extension_information(dpms::X11_EXTENSION_NAME)?
+        .is_none()
+    {
+        return Ok(());
+    }
+
+    let info = connection.dpms_info()?.reply()?;
+    if info.state && info.power_level != DPMSMode::ON {
+        connection.dpms_force_level(DPMSMode::ON)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,71 @@
+use std::fmt;
+
+use x11rb::protocol::xproto::GrabStatus;
+
+/// The configuration could not be loaded, attached as context to the cause.
+#[derive(Debug)]
+pub struct ConfigError;
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Invalid configuration")
+    }
+}
+
+/// The screen could not be locked, and was left as it was. Attached as
+/// context to the cause, which failing after locking never is.
+#[derive(Debug)]
+pub struct SetupError;
+
+impl fmt::Display for SetupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Failed to lock the screen")
+    }
+}
+
+/// The X server refused to give us exclusive access to an input device.
+#[derive(Debug)]
+pub struct GrabError {
+    pub device: &'static str,
+    pub status: GrabStatus,
+}
+
+impl fmt::Display for GrabError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Failed to grab the {}: {:?}", self.device, self.status)
+    }
+}
+
+impl std::error::Error for GrabError {}
+
+/// The lock window was set up, but a check that it covers the screen and
+/// holds the input failed.
+#[derive(Debug)]
+pub struct NotLocked {
+    pub check: &'static str,
+}
+
+impl fmt::Display for NotLocked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The screen isn't locked, {}", self.check)
+    }
+}
+
+impl std::error::Error for NotLocked {}
+
+/// Another pinlock holds the instance lock for this display.
+#[derive(Debug)]
+pub struct AlreadyRunning {
+    pub pid: Option<i32>,
+}
+
+impl fmt::Display for AlreadyRunning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.pid {
+            Some(pid) => write!(f, "pinlock is already running as pid {pid}"),
+            None => f.write_str("pinlock is already running"),
+        }
+    }
+}
+
+impl std::error::Error for AlreadyRunning {}
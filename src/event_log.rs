@@ -0,0 +1,183 @@
+//! A record of the X events the lock screen receives, for `--debug-events`,
+//! to tell whether input reaches pinlock at all when it can't be unlocked.
+//!
+//! Keys are recorded by keycode and modifier state only. What they type
+//! depends on the keymap and is never resolved here, but with the keymap at
+//! hand the keycodes still give away the PIN, so only the user may read the
+//! file.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    os::unix::fs::{OpenOptionsExt, PermissionsExt},
+    path::Path,
+};
+
+use anyhow::{bail, Context, Result};
+use chrono::Local;
+use log::warn;
+use x11rb::protocol::Event;
+
+/// The file events are appended to.
+pub struct EventLog {
+    file: File,
+}
+
+impl EventLog {
+    /// Open `path` for appending, creating it readable by the user only. An
+    /// existing file that others may read is refused rather than having
+    /// keycodes added to it.
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .mode(0o600)
+            .open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        let mode = file
+            .metadata()
+            .with_context(|| format!("Failed to read the permissions of {}", path.display()))?
+            .permissions()
+            .mode();
+        if mode & 0o077 != 0 {
+            bail!(
+                "{} can be accessed by other users (mode {:o}), make it private with chmod 600",
+                path.display(),
+                mode & 0o777
+            );
+        }
+        Ok(Self { file })
+    }
+
+    /// Append a timestamped line for `event`. Failing to is only logged, as
+    /// debugging must not get in the way of unlocking.
+    pub fn record(&mut self, event: &Event) {
+        let time = Local::now().format("%Y-%m-%d %H:%M:%S%.3f");
+        if let Err(err) = writeln!(self.file, "{time} {}", describe(event)) {
+            warn!("Failed to write the event log: {err}");
+        }
+    }
+}
+
+/// [`describe`] without the keycodes and modifiers of key events, for logs
+/// that aren't kept private like the event log.
+pub fn describe_without_keys(event: &Event) -> String {
+    match event {
+        Event::KeyPress(event) => format!("KeyPress window={}", event.event),
+        Event::KeyRelease(event) => format!("KeyRelease window={}", event.event),
+        _ => describe(event),
+    }
+}
+
+/// A line about `event`, with its type and the fields that tell where the
+/// input went.
+pub fn describe(event: &Event) -> String {
+    match event {
+        Event::KeyPress(event) => format!(
+            "KeyPress keycode={} state={:#06x} window={}",
+            event.detail,
+            u16::from(event.state),
+            event.event
+        ),
+        Event::KeyRelease(event) => format!(
+            "KeyRelease keycode={} state={:#06x} window={}",
+            event.detail,
+            u16::from(event.state),
+            event.event
+        ),
+        Event::ButtonPress(event) => format!(
+            "ButtonPress button={} state={:#06x} window={} at ({},{})",
+            event.detail,
+            u16::from(event.state),
+            event.event,
+            event.event_x,
+            event.event_y
+        ),
+        Event::ButtonRelease(event) => format!(
+            "ButtonRelease button={} state={:#06x} window={} at ({},{})",
+            event.detail,
+            u16::from(event.state),
+            event.event,
+            event.event_x,
+            event.event_y
+        ),
+        Event::Expose(event) => format!(
+            "Expose window={} area={}x{}+{}+{} count={}",
+            event.window, event.width, event.height, event.x, event.y, event.count
+        ),
+        Event::FocusIn(focus) | Event::FocusOut(focus) => format!(
+            "{} window={} mode={:?} detail={:?}",
+            type_name(event),
+            focus.event,
+            focus.mode,
+            focus.detail
+        ),
+        // Only the type, as the fields of some carry keymap contents
+        _ => type_name(event),
+    }
+}
+
+/// The event type, which starts its debug formatting.
+fn type_name(event: &Event) -> String {
+    format!("{event:?}")
+        .split(|c: char| !c.is_alphanumeric())
+        .next()
+        .unwrap_or_default()
+        .to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use x11rb::protocol::xproto::{KeyButMask, KeyPressEvent};
+
+    use super::*;
+
+    #[test]
+    fn keys_are_described_by_keycode_and_state() {
+        let event = Event::KeyPress(KeyPressEvent {
+            detail: 38,
+            state: KeyButMask::SHIFT | KeyButMask::MOD2,
+            event: 7,
+            ..Default::default()
+        });
+        assert_eq!(
+            describe(&event),
+            "KeyPress keycode=38 state=0x0011 window=7"
+        );
+    }
+
+    #[test]
+    fn keys_can_be_described_without_their_keycode() {
+        let event = Event::KeyRelease(KeyPressEvent {
+            detail: 38,
+            state: KeyButMask::SHIFT,
+            event: 7,
+            ..Default::default()
+        });
+        assert_eq!(describe_without_keys(&event), "KeyRelease window=7");
+        let event = Event::MapNotify(Default::default());
+        assert_eq!(describe_without_keys(&event), "MapNotify");
+    }
+
+    #[test]
+    fn the_log_is_private() {
+        let path = env::temp_dir().join(format!("pinlock-events-{}", process::id()));
+        let _ = fs::remove_file(&path);
+        EventLog::open(&path).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        let shared = EventLog::open(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(mode & 0o777, 0o600);
+        assert!(shared.is_err());
+    }
+
+    #[test]
+    fn other_events_are_described_by_their_type() {
+        let event = Event::MapNotify(Default::default());
+        assert_eq!(describe(&event), "MapNotify");
+    }
+}
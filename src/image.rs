@@ -0,0 +1,844 @@
+use std::{
+    io::Read,
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use ::image::{imageops, Rgba, RgbaImage};
+use anyhow::{anyhow, bail, Context, Result};
+use log::{debug, warn};
+use serde::Deserialize;
+use x11rb::{
+    connection::{Connection, RequestConnection},
+    protocol::{
+        randr::{ConnectionExt as _, MonitorInfo},
+        xproto::{
+            ConnectionExt, CreateGCAux, Gcontext, ImageFormat, ImageOrder, Pixmap, Rectangle,
+            Screen,
+        },
+    },
+    rust_connection::RustConnection,
+};
+
+use crate::{config::Config, theme::Color, window::Window};
+
+/// How often RandR is asked for the monitors while it reports none, and
+/// how long to wait in between.
+const MONITOR_ATTEMPTS: u32 = 3;
+const MONITOR_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// How the background image is fitted to each monitor.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackgroundMode {
+    /// Scale to cover the whole monitor, cutting off what doesn't fit.
+    #[default]
+    Fill,
+    /// Scale to fit within the monitor, bordered by the background color.
+    Fit,
+    /// Keep the original size, centered on the monitor.
+    Center,
+    /// Scale to the monitor's size, ignoring the aspect ratio.
+    Stretch,
+    /// Repeat the image from the top left corner of the monitor.
+    Tile,
+}
+
+/// Where the background image comes from, configured as a path,
+/// `"screenshot"`, or `"command:"` followed by a shell command printing an
+/// image.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(from = "String")]
+pub enum BackgroundImage {
+    File(PathBuf),
+    Screenshot,
+    Command(String),
+}
+
+impl From<String> for BackgroundImage {
+    fn from(value: String) -> Self {
+        if value == "screenshot" {
+            return Self::Screenshot;
+        }
+        match value.strip_prefix("command:") {
+            Some(command) => Self::Command(command.trim().to_owned()),
+            None => Self::File(value.into()),
+        }
+    }
+}
+
+/// An area of the screen, configured as `"X,Y,WIDTH,HEIGHT"` in pixels of
+/// the root window: blacked out of screenshots or, with
+/// `passthrough_regions`, left out of the lock window's input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct ScreenRegion(pub Rectangle);
+
+impl TryFrom<String> for ScreenRegion {
+    type Error = String;
+
+    fn try_from(region: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("`{region}` is not a region, expected `X,Y,WIDTH,HEIGHT`");
+        let numbers: Vec<u16> = region
+            .split(',')
+            .map(|number| number.trim().parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| invalid())?;
+        let &[x, y, width, height] = numbers.as_slice() else {
+            return Err(invalid());
+        };
+        let (Ok(x), Ok(y)) = (i16::try_from(x), i16::try_from(y)) else {
+            return Err(invalid());
+        };
+        if width == 0 || height == 0 {
+            return Err(format!("`{region}` covers nothing"));
+        }
+        Ok(Self(Rectangle {
+            x,
+            y,
+            width,
+            height,
+        }))
+    }
+}
+
+/// A background image yet to be decoded.
+#[derive(Clone)]
+pub enum Encoded {
+    File(PathBuf),
+    /// The output of a command, which is read ahead of time
+    Bytes(Arc<[u8]>),
+    /// The screen as it was, decoded already. Cut to each monitor rather
+    /// than fitted, so that the desktop stays in place.
+    Screenshot(Arc<RgbaImage>),
+}
+
+impl Encoded {
+    /// The configured background image. A command is run and a screenshot
+    /// taken right away, so call this before the lock window covers the
+    /// screen. If they fail or take longer than `background_timeout_ms`, the
+    /// plain background color is shown.
+    pub fn from_config(
+        connection: &RustConnection,
+        screen: &Screen,
+        config: &Config,
+    ) -> Option<Self> {
+        let timeout = config.background_timeout();
+        let started = Instant::now();
+        let (what, result) = match config.background_image.as_ref()? {
+            BackgroundImage::File(path) => return Some(Self::File(path.clone())),
+            BackgroundImage::Screenshot => (
+                "Took the screenshot".to_owned(),
+                screenshot(connection, screen, timeout).map(|mut image| {
+                    redact(&mut image, &config.redact_regions);
+                    Self::Screenshot(image.into())
+                }),
+            ),
+            BackgroundImage::Command(command) => (
+                format!("Ran `{command}`"),
+                run(command, timeout).map(|bytes| Self::Bytes(bytes.into())),
+            ),
+        };
+        match result {
+            Ok(image) => {
+                debug!("{what} in {:?}", started.elapsed());
+                Some(image)
+            }
+            Err(err) => {
+                warn!("Falling back to the background color: {err:#}");
+                None
+            }
+        }
+    }
+
+    /// The configured background image as far as it can be had without a
+    /// display, which a screenshot can't, for `--print-layout`.
+    pub fn without_display(config: &Config) -> Result<Option<Self>> {
+        match config.background_image.as_ref() {
+            None => Ok(None),
+            Some(BackgroundImage::File(path)) => Ok(Some(Self::File(path.clone()))),
+            Some(BackgroundImage::Screenshot) => {
+                bail!("Screenshots can't be taken without a display")
+            }
+            Some(BackgroundImage::Command(command)) => {
+                let bytes = run(command, config.background_timeout())?;
+                Ok(Some(Self::Bytes(bytes.into())))
+            }
+        }
+    }
+
+    pub fn decode(&self) -> Result<RgbaImage> {
+        let image = match self {
+            Self::File(path) => ::image::open(path).with_context(|| {
+                format!("Failed to load the background image {}", path.display())
+            })?,
+            Self::Bytes(bytes) => ::image::load_from_memory(bytes)
+                .context("Failed to decode the background image printed by the command")?,
+            Self::Screenshot(image) => return Ok(RgbaImage::clone(image)),
+        };
+        Ok(image.into_rgba8())
+    }
+}
+
+/// Capture the root window, in strips that each fit the server's request
+/// size limit, giving up once `timeout` has passed. Only 24 bit TrueColor
+/// roots with 32 bits per pixel, which nearly every server has, are
+/// supported.
+fn screenshot(
+    connection: &RustConnection,
+    screen: &Screen,
+    timeout: Duration,
+) -> Result<RgbaImage> {
+    let deadline = Instant::now() + timeout;
+    let setup = connection.setup();
+    let depth = screen.root_depth;
+    let bits_per_pixel = setup
+        .pixmap_formats
+        .iter()
+        .find(|format| format.depth == depth)
+        .map(|format| format.bits_per_pixel);
+    let visual = screen
+        .allowed_depths
+        .iter()
+        .flat_map(|allowed| &allowed.visuals)
+        .find(|visual| visual.visual_id == screen.root_visual);
+    let rgb = visual.is_some_and(|visual| {
+        (visual.red_mask, visual.green_mask, visual.blue_mask) == (0xff0000, 0xff00, 0xff)
+    });
+    if bits_per_pixel != Some(32) || !rgb {
+        bail!("Screenshots need a 24 bit RGB root visual with 32 bits per pixel");
+    }
+    let msb_first = setup.image_byte_order == ImageOrder::MSB_FIRST;
+
+    let (width, height) = (screen.width_in_pixels, screen.height_in_pixels);
+    let row_bytes = usize::from(width) * 4;
+    let max_rows =
+        ((connection.maximum_request_bytes() - 64) / row_bytes.max(1)).clamp(1, u16::MAX.into());
+
+    let mut image = RgbaImage::new(width.into(), height.into());
+    let mut y = 0;
+    while y < height {
+        if Instant::now() >= deadline {
+            bail!("The screenshot didn't finish within {timeout:?}");
+        }
+        let strip = (height - y).min(max_rows as u16);
+        let reply = connection
+            .get_image(
+                ImageFormat::Z_PIXMAP,
+                screen.root,
+                0,
+                y as i16,
+                width,
+                strip,
+                !0,
+            )?
+            .reply()
+            .context("Failed to capture the screen")?;
+        let start = usize::from(y) * row_bytes;
+        let pixels = &mut image.as_mut()[start..start + usize::from(strip) * row_bytes];
+        if reply.data.len() < pixels.len() {
+            bail!("The server sent a short screenshot");
+        }
+        for (rgba, pixel) in pixels.chunks_exact_mut(4).zip(reply.data.chunks_exact(4)) {
+            let pixel = [pixel[0], pixel[1], pixel[2], pixel[3]];
+            let [_, r, g, b] = if msb_first {
+                u32::from_be_bytes(pixel)
+            } else {
+                u32::from_le_bytes(pixel)
+            }
+            .to_be_bytes();
+            rgba.copy_from_slice(&[r, g, b, 0xff]);
+        }
+        y += strip;
+    }
+    Ok(image)
+}
+
+/// Black out `regions` of a screenshot, cut to the screen. Regions reaching
+/// past it are only warned about, as monitors come and go.
+fn redact(screenshot: &mut RgbaImage, regions: &[ScreenRegion]) {
+    let (width, height) = screenshot.dimensions();
+    for &ScreenRegion(region) in regions {
+        let (x, y) = (region.x as u32, region.y as u32);
+        let right = x + u32::from(region.width);
+        let bottom = y + u32::from(region.height);
+        if right > width || bottom > height {
+            warn!(
+                "Redacting only part of {},{},{},{}, it reaches past the {width}x{height} screen",
+                region.x, region.y, region.width, region.height
+            );
+        }
+        for y in y..bottom.min(height) {
+            for x in x..right.min(width) {
+                screenshot.put_pixel(x, y, Rgba([0, 0, 0, 0xff]));
+            }
+        }
+    }
+}
+
+/// Run `command` with the shell and collect what it prints, giving up after
+/// `timeout`.
+fn run(command: &str, timeout: Duration) -> Result<Vec<u8>> {
+    let mut child = Command::new("sh")
+        .args(["-c", command])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run `{command}`"))?;
+
+    // Read meanwhile, so that the command doesn't block on a full pipe
+    let mut stdout = child.stdout.take().context("The output isn't piped")?;
+    let output = thread::spawn(move || {
+        let mut bytes = Vec::new();
+        stdout.read_to_end(&mut bytes).map(|_| bytes)
+    });
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            bail!("`{command}` didn't finish within {timeout:?}");
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+    if !status.success() {
+        bail!("`{command}` failed with {status}");
+    }
+
+    let bytes = output
+        .join()
+        .map_err(|_| anyhow!("Reading the output of `{command}` failed"))?
+        .with_context(|| format!("Failed to read the output of `{command}`"))?;
+    if bytes.is_empty() {
+        bail!("`{command}` printed nothing");
+    }
+    Ok(bytes)
+}
+
+/// Monitor areas with the image fitted to each.
+type Fitted = Vec<(Rectangle, RgbaImage)>;
+
+/// The drawable a background is made for.
+struct Target {
+    window: u32,
+    depth: u8,
+    width: u16,
+    height: u16,
+    fill: Color,
+}
+
+impl Target {
+    fn new(window: &Window, config: &Config) -> Self {
+        Self {
+            window: window.id,
+            depth: window.visual.depth,
+            width: window.width,
+            height: window.height,
+            fill: config.theme.background,
+        }
+    }
+}
+
+/// Render the background image onto a pixmap covering the lock window,
+/// fitted to every monitor on its own. Returns `None` if there is no image
+/// or it can't be used, in which case the plain background color is shown.
+pub fn create_background(
+    connection: &RustConnection,
+    window: &Window,
+    config: &Config,
+    image: Option<&Encoded>,
+) -> Result<Option<Pixmap>> {
+    let Some(image) = image else {
+        return Ok(None);
+    };
+
+    let monitors = monitors(connection, window)?;
+    let started = Instant::now();
+    let fitted = prepare(
+        image,
+        config.background_mode,
+        &monitors,
+        config.theme.background,
+    );
+    debug!("Prepared the background in {:?}", started.elapsed());
+
+    upload(connection, &Target::new(window, config), fitted)
+}
+
+/// A background image being prepared on another thread, so that locking
+/// doesn't wait for large images to be decoded and scaled.
+pub struct PendingBackground {
+    target: Target,
+    receiver: Receiver<Result<Fitted>>,
+    /// Dropped along with the pending background, which cancels it if its
+    /// delay isn't up yet
+    _cancel: Sender<()>,
+}
+
+impl PendingBackground {
+    /// Start preparing the background image, if there is one, once `delay`
+    /// has passed.
+    pub fn start(
+        connection: &RustConnection,
+        window: &Window,
+        config: &Config,
+        image: Option<Encoded>,
+        delay: Duration,
+    ) -> Result<Option<Self>> {
+        let Some(image) = image else {
+            return Ok(None);
+        };
+
+        let monitors = monitors(connection, window)?;
+        let mode = config.background_mode;
+        let fill = config.theme.background;
+        let (sender, receiver) = mpsc::channel();
+        let (cancel, cancelled) = mpsc::channel();
+        thread::spawn(move || {
+            if !waited_out(&cancelled, delay) {
+                return;
+            }
+            let started = Instant::now();
+            let fitted = prepare(&image, mode, &monitors, fill);
+            debug!("Prepared the background in {:?}", started.elapsed());
+            // The lock may be over already
+            let _ = sender.send(fitted);
+        });
+
+        Ok(Some(Self {
+            target: Target::new(window, config),
+            receiver,
+            _cancel: cancel,
+        }))
+    }
+
+    /// Upload the background once it's ready. Returns `Err(self)` while it
+    /// is still being prepared.
+    pub fn finish(self, connection: &RustConnection) -> Result<Result<Option<Pixmap>, Self>> {
+        let fitted = match self.receiver.try_recv() {
+            Ok(fitted) => fitted,
+            Err(TryRecvError::Empty) => return Ok(Err(self)),
+            Err(TryRecvError::Disconnected) => Err(anyhow!("Preparing the background failed")),
+        };
+        upload(connection, &self.target, fitted).map(Ok)
+    }
+}
+
+/// Wait for `delay`, unless the sender of `cancelled` goes away before.
+/// Returns whether the delay was waited out.
+fn waited_out(cancelled: &Receiver<()>, delay: Duration) -> bool {
+    matches!(
+        cancelled.recv_timeout(delay),
+        Err(RecvTimeoutError::Timeout)
+    )
+}
+
+/// Decode the image and fit it to every monitor, or cut a screenshot to
+/// them.
+fn prepare(
+    image: &Encoded,
+    mode: BackgroundMode,
+    monitors: &[Rectangle],
+    fill: Color,
+) -> Result<Fitted> {
+    if let Encoded::Screenshot(screenshot) = image {
+        return Ok(monitors
+            .iter()
+            .map(|&monitor| {
+                let cut = imageops::crop_imm(
+                    screenshot.as_ref(),
+                    monitor.x.max(0) as u32,
+                    monitor.y.max(0) as u32,
+                    monitor.width.into(),
+                    monitor.height.into(),
+                );
+                (monitor, cut.to_image())
+            })
+            .collect());
+    }
+
+    let image = image.decode()?;
+    Ok(monitors
+        .iter()
+        .map(|&monitor| {
+            let fitted = fit(
+                &image,
+                mode,
+                monitor.width.into(),
+                monitor.height.into(),
+                fill,
+            );
+            (monitor, fitted)
+        })
+        .collect())
+}
+
+/// Compose the fitted images on a pixmap, falling back to the background
+/// color on failure.
+fn upload(
+    connection: &RustConnection,
+    target: &Target,
+    fitted: Result<Fitted>,
+) -> Result<Option<Pixmap>> {
+    let fitted = match fitted {
+        Ok(fitted) => fitted,
+        Err(err) => {
+            warn!("Falling back to the background color: {err:#}");
+            return Ok(None);
+        }
+    };
+
+    // Checked, a window spanning many monitors can be too large for one
+    let pixmap = connection.generate_id()?;
+    if let Err(err) = connection
+        .create_pixmap(
+            target.depth,
+            pixmap,
+            target.window,
+            target.width,
+            target.height,
+        )?
+        .check()
+    {
+        warn!("Falling back to the background color, no pixmap for the image: {err}");
+        return Ok(None);
+    }
+    let gc = connection.generate_id()?;
+    connection.create_gc(
+        gc,
+        pixmap,
+        &CreateGCAux::new().foreground(target.fill.pixel()),
+    )?;
+    connection.poly_fill_rectangle(
+        pixmap,
+        gc,
+        &[Rectangle {
+            x: 0,
+            y: 0,
+            width: target.width,
+            height: target.height,
+        }],
+    )?;
+
+    let result = fitted.iter().try_for_each(|(monitor, image)| {
+        put_image(connection, pixmap, gc, target.depth, image, *monitor)
+    });
+    connection.free_gc(gc)?;
+
+    if let Err(err) = result {
+        warn!("Falling back to the background color: {err:#}");
+        connection.free_pixmap(pixmap)?;
+        return Ok(None);
+    }
+    Ok(Some(pixmap))
+}
+
+/// Areas of the active monitors, or the whole window if RandR can't tell.
+pub fn monitors(connection: &RustConnection, window: &Window) -> Result<Vec<Rectangle>> {
+    Ok(monitor_areas(
+        &active_monitors(connection, window),
+        window.width,
+        window.height,
+    ))
+}
+
+/// The active monitors as RandR reports them. In the middle of a
+/// reconfiguration it may briefly report none, so it is asked again a few
+/// times before giving up. Empty if RandR can't tell.
+pub fn active_monitors(connection: &RustConnection, window: &Window) -> Vec<MonitorInfo> {
+    for attempt in 1..=MONITOR_ATTEMPTS {
+        let Ok(reply) = connection
+            .randr_get_monitors(window.id, true)
+            .map_err(Into::into)
+            .and_then(|cookie| cookie.reply())
+        else {
+            return Vec::new();
+        };
+        if !reply.monitors.is_empty() {
+            return reply.monitors;
+        }
+        if attempt < MONITOR_ATTEMPTS {
+            thread::sleep(MONITOR_RETRY_DELAY);
+        }
+    }
+    warn!("RandR reports no active monitors, covering the whole screen");
+    Vec::new()
+}
+
+/// Areas of `monitors`, or the whole window of `width` by `height` without
+/// any, so that it is always covered.
+fn monitor_areas(monitors: &[MonitorInfo], width: u16, height: u16) -> Vec<Rectangle> {
+    if monitors.is_empty() {
+        return vec![Rectangle {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        }];
+    }
+    monitors
+        .iter()
+        .map(|monitor| Rectangle {
+            x: monitor.x,
+            y: monitor.y,
+            width: monitor.width,
+            height: monitor.height,
+        })
+        .collect()
+}
+
+/// Produce a `width` by `height` image from `image` according to `mode`,
+/// filling uncovered areas with `background`.
+pub fn fit(
+    image: &RgbaImage,
+    mode: BackgroundMode,
+    width: u32,
+    height: u32,
+    background: Color,
+) -> RgbaImage {
+    let [_, r, g, b] = background.0.to_be_bytes();
+    let mut canvas = RgbaImage::from_pixel(width, height, Rgba([r, g, b, 0xff]));
+
+    match mode {
+        BackgroundMode::Stretch => {
+            return imageops::resize(image, width, height, imageops::FilterType::Triangle);
+        }
+        BackgroundMode::Tile => {
+            imageops::tile(&mut canvas, image);
+            return canvas;
+        }
+        BackgroundMode::Fill | BackgroundMode::Fit | BackgroundMode::Center => {}
+    }
+
+    let (scaled_width, scaled_height) = scaled_size(image.dimensions(), (width, height), mode);
+    let scaled = if (scaled_width, scaled_height) == image.dimensions() {
+        image.clone()
+    } else {
+        imageops::resize(
+            image,
+            scaled_width,
+            scaled_height,
+            imageops::FilterType::Triangle,
+        )
+    };
+
+    // Centered, possibly with negative offsets when the image is cut off
+    let x = (i64::from(width) - i64::from(scaled_width)) / 2;
+    let y = (i64::from(height) - i64::from(scaled_height)) / 2;
+    imageops::overlay(&mut canvas, &scaled, x, y);
+    canvas
+}
+
+/// Size of the image once scaled for `Fill`, `Fit` or `Center`, preserving
+/// its aspect ratio.
+fn scaled_size(
+    (image_width, image_height): (u32, u32),
+    (width, height): (u32, u32),
+    mode: BackgroundMode,
+) -> (u32, u32) {
+    if image_width == 0 || image_height == 0 {
+        return (image_width, image_height);
+    }
+
+    let horizontal = f64::from(width) / f64::from(image_width);
+    let vertical = f64::from(height) / f64::from(image_height);
+    let scale = match mode {
+        BackgroundMode::Fill => horizontal.max(vertical),
+        BackgroundMode::Fit => horizontal.min(vertical),
+        _ => return (image_width, image_height),
+    };
+
+    (
+        (f64::from(image_width) * scale).round().max(1.0) as u32,
+        (f64::from(image_height) * scale).round().max(1.0) as u32,
+    )
+}
+
+/// Upload `image` to `area` of a pixmap, split into as many requests as the
+/// server's request size limit demands.
+fn put_image(
+    connection: &RustConnection,
+    pixmap: Pixmap,
+    gc: Gcontext,
+    depth: u8,
+    image: &RgbaImage,
+    area: Rectangle,
+) -> Result<()> {
+    let setup = connection.setup();
+    let bits_per_pixel = setup
+        .pixmap_formats
+        .iter()
+        .find(|format| format.depth == depth)
+        .map(|format| format.bits_per_pixel);
+    if bits_per_pixel != Some(32) {
+        bail!("Background images need a 32 bits per pixel visual, not depth {depth}");
+    }
+    let msb_first = setup.image_byte_order == ImageOrder::MSB_FIRST;
+
+    let row_bytes = image.width() as usize * 4;
+    // Leave room for the request header
+    let max_rows = ((connection.maximum_request_bytes() - 64) / row_bytes).max(1);
+
+    let rows_per_request = max_rows * row_bytes;
+    for (chunk_index, chunk) in image.as_raw().chunks(rows_per_request).enumerate() {
+        let data: Vec<u8> = chunk
+            .chunks_exact(4)
+            .flat_map(|rgba| {
+                let pixel = u32::from_be_bytes([0xff, rgba[0], rgba[1], rgba[2]]);
+                if msb_first {
+                    pixel.to_be_bytes()
+                } else {
+                    pixel.to_le_bytes()
+                }
+            })
+            .collect();
+
+        let y = i32::from(area.y) + (chunk_index * max_rows) as i32;
+        connection.put_image(
+            ImageFormat::Z_PIXMAP,
+            pixmap,
+            gc,
+            image.width() as u16,
+            (chunk.len() / row_bytes) as u16,
+            area.x,
+            y as i16,
+            0,
+            depth,
+            &data,
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use x11rb::protocol::randr::GetMonitorsReply;
+
+    use super::*;
+
+    const MONITOR: (u32, u32) = (1920, 1080);
+
+    #[test]
+    fn a_delay_is_cut_short_by_cancelling() {
+        let (cancel, cancelled) = mpsc::channel();
+        assert!(waited_out(&cancelled, Duration::ZERO));
+        drop(cancel);
+        assert!(!waited_out(&cancelled, Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn without_monitors_the_whole_window_is_covered() {
+        let reply = GetMonitorsReply::default();
+        assert_eq!(
+            monitor_areas(&reply.monitors, 1920, 1080),
+            [Rectangle {
+                x: 0,
+                y: 0,
+                width: 1920,
+                height: 1080,
+            }]
+        );
+    }
+
+    #[test]
+    fn background_images_from_commands_are_prefixed() {
+        assert_eq!(
+            BackgroundImage::from("command: scrot -o -".to_owned()),
+            BackgroundImage::Command("scrot -o -".to_owned())
+        );
+        assert_eq!(
+            BackgroundImage::from("screenshot".to_owned()),
+            BackgroundImage::Screenshot
+        );
+        assert_eq!(
+            BackgroundImage::from("~/wall.png".to_owned()),
+            BackgroundImage::File("~/wall.png".into())
+        );
+    }
+
+    #[test]
+    fn failing_background_commands_are_errors() {
+        let timeout = Duration::from_secs(5);
+        assert_eq!(run("printf png", timeout).unwrap(), b"png");
+        assert!(run("exit 1", timeout).is_err());
+        assert!(run("true", timeout).is_err());
+        assert!(run("sleep 1", Duration::from_millis(50)).is_err());
+    }
+
+    #[test]
+    fn redacted_regions_are_black_within_the_screen() {
+        let region = |region: &str| ScreenRegion::try_from(region.to_owned());
+        assert!(region("1,2,3").is_err());
+        assert!(region("0,0,0,10").is_err());
+        assert!(region("-1,0,10,10").is_err());
+
+        let mut screenshot = RgbaImage::from_pixel(4, 4, Rgba([0xff; 4]));
+        redact(
+            &mut screenshot,
+            &[region("1, 1, 2, 1").unwrap(), region("3,3,10,10").unwrap()],
+        );
+        let black: Vec<(u32, u32)> = screenshot
+            .enumerate_pixels()
+            .filter(|(_, _, pixel)| pixel.0 == [0, 0, 0, 0xff])
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        assert_eq!(black, [(1, 1), (2, 1), (3, 3)]);
+    }
+
+    #[test]
+    fn fit_keeps_the_aspect_ratio_within_the_monitor() {
+        assert_eq!(
+            scaled_size((1000, 1000), MONITOR, BackgroundMode::Fit),
+            (1080, 1080)
+        );
+        assert_eq!(
+            scaled_size((4000, 1000), MONITOR, BackgroundMode::Fit),
+            (1920, 480)
+        );
+    }
+
+    #[test]
+    fn fill_keeps_the_aspect_ratio_covering_the_monitor() {
+        assert_eq!(
+            scaled_size((1000, 1000), MONITOR, BackgroundMode::Fill),
+            (1920, 1920)
+        );
+        assert_eq!(
+            scaled_size((4000, 1000), MONITOR, BackgroundMode::Fill),
+            (4320, 1080)
+        );
+    }
+
+    #[test]
+    fn center_and_fit_pad_with_the_background_color() {
+        let red = RgbaImage::from_pixel(2, 2, Rgba([0xff, 0, 0, 0xff]));
+        let background = Color(0x0000ff);
+
+        for mode in [BackgroundMode::Center, BackgroundMode::Fit] {
+            let fitted = fit(&red, mode, 6, 2, background);
+            assert_eq!(fitted.dimensions(), (6, 2));
+            assert_eq!(fitted.get_pixel(0, 0), &Rgba([0, 0, 0xff, 0xff]));
+            assert_eq!(fitted.get_pixel(3, 1), &Rgba([0xff, 0, 0, 0xff]));
+        }
+    }
+
+    #[test]
+    fn tile_repeats_the_image() {
+        let mut image = RgbaImage::from_pixel(2, 1, Rgba([0, 0, 0, 0xff]));
+        image.put_pixel(1, 0, Rgba([0xff, 0xff, 0xff, 0xff]));
+
+        let tiled = fit(&image, BackgroundMode::Tile, 5, 1, Color(0));
+        let row: Vec<u8> = tiled.pixels().map(|pixel| pixel.0[0]).collect();
+        assert_eq!(row, [0, 0xff, 0, 0xff, 0]);
+    }
+}
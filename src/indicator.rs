@@ -0,0 +1,167 @@
+//! Status indicators such as the lock key warnings, laid out in a row or a
+//! column at a configurable place of the lock screen.
+
+use serde::Deserialize;
+
+use crate::{state::LockState, theme::Color};
+
+/// Text and color of an indicator for the current state, `None` while it
+/// is hidden.
+pub type Indicator = Box<dyn Fn(&LockState) -> Option<(String, Color)>>;
+
+/// Where the indicators are drawn.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BarPosition {
+    /// Right above the PIN field.
+    #[default]
+    AbovePin,
+    Top,
+    Bottom,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// How the indicators are lined up.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Orientation {
+    #[default]
+    Horizontal,
+    Vertical,
+}
+
+/// The area the indicators are laid out in, in pixels of the lock window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    pub width: i32,
+    pub height: i32,
+    pub line_height: i32,
+    /// Center of the PIN field
+    pub pin_x: i32,
+    /// Baseline of the row right above the PIN field
+    pub above_pin: i32,
+}
+
+/// A list of indicators, placed one after the other with a line of text
+/// between them.
+pub struct IndicatorBar {
+    indicators: Vec<Indicator>,
+    position: BarPosition,
+    orientation: Orientation,
+}
+
+impl IndicatorBar {
+    pub fn new(
+        position: BarPosition,
+        orientation: Orientation,
+        indicators: Vec<Indicator>,
+    ) -> Self {
+        Self {
+            indicators,
+            position,
+            orientation,
+        }
+    }
+
+    /// The indicators shown in `state`, in order.
+    pub fn shown(&self, state: &LockState) -> Vec<(String, Color)> {
+        self.indicators
+            .iter()
+            .filter_map(|indicator| indicator(state))
+            .collect()
+    }
+
+    /// The most indicators that can be shown at once.
+    pub fn most_shown(&self) -> usize {
+        self.indicators.len()
+    }
+
+    /// Starting point and baseline of each of the indicators `widths` wide.
+    /// A row is spaced by a line height, a column stacks lines. Both are
+    /// kept a line height away from the edges, and grow away from them or,
+    /// above the PIN field, upwards.
+    pub fn layout(&self, widths: &[i32], frame: Frame) -> Vec<(i32, i32)> {
+        let gap = frame.line_height;
+        let margin = frame.line_height;
+        let lines = match self.orientation {
+            Orientation::Horizontal => 1,
+            Orientation::Vertical => widths.len() as i32,
+        };
+
+        // Baseline of the first line
+        let top = match self.position {
+            BarPosition::Top | BarPosition::TopLeft | BarPosition::TopRight => {
+                margin + frame.line_height
+            }
+            BarPosition::Bottom | BarPosition::BottomLeft | BarPosition::BottomRight => {
+                frame.height - margin - (lines - 1) * frame.line_height
+            }
+            BarPosition::AbovePin => frame.above_pin - (lines - 1) * frame.line_height,
+        };
+        // Where a run of `width` pixels starts
+        let x = |width: i32| match self.position {
+            BarPosition::TopLeft | BarPosition::BottomLeft => margin,
+            BarPosition::TopRight | BarPosition::BottomRight => frame.width - margin - width,
+            BarPosition::AbovePin => frame.pin_x - width / 2,
+            BarPosition::Top | BarPosition::Bottom => (frame.width - width) / 2,
+        };
+
+        match self.orientation {
+            Orientation::Horizontal => {
+                let total = widths.iter().sum::<i32>() + gap * (widths.len() as i32 - 1).max(0);
+                let mut next = x(total);
+                widths
+                    .iter()
+                    .map(|width| {
+                        let start = next;
+                        next += width + gap;
+                        (start, top)
+                    })
+                    .collect()
+            }
+            Orientation::Vertical => widths
+                .iter()
+                .enumerate()
+                .map(|(i, &width)| (x(width), top + i as i32 * frame.line_height))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Frame = Frame {
+        width: 1000,
+        height: 800,
+        line_height: 10,
+        pin_x: 500,
+        above_pin: 376,
+    };
+
+    fn bar(position: BarPosition, orientation: Orientation) -> IndicatorBar {
+        IndicatorBar::new(position, orientation, Vec::new())
+    }
+
+    #[test]
+    fn a_row_is_centered_with_gaps() {
+        let bar = bar(BarPosition::AbovePin, Orientation::Horizontal);
+        assert_eq!(
+            bar.layout(&[40, 60], FRAME),
+            [(445, FRAME.above_pin), (495, FRAME.above_pin)]
+        );
+    }
+
+    #[test]
+    fn a_column_grows_away_from_its_corner() {
+        let top_right = bar(BarPosition::TopRight, Orientation::Vertical);
+        assert_eq!(top_right.layout(&[40, 60], FRAME), [(950, 20), (930, 30)]);
+
+        let bottom_left = bar(BarPosition::BottomLeft, Orientation::Vertical);
+        assert_eq!(bottom_left.layout(&[40, 60], FRAME), [(10, 780), (10, 790)]);
+    }
+}
@@ -0,0 +1,825 @@
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use x11rb::{
+    connection::{Connection, RequestConnection},
+    protocol::{
+        xkb::{BoolCtrl, ConnectionExt as _, PerClientFlag, ID, X11_EXTENSION_NAME},
+        xproto::{
+            AutoRepeatMode, ChangeKeyboardControlAux, ConnectionExt, KeyButMask, Keycode, Keysym,
+            ModMask, Timestamp,
+        },
+        Event,
+    },
+    rust_connection::RustConnection,
+};
+#[cfg(feature = "compose")]
+use xkbcommon::xkb;
+
+const XK_BACKSPACE: Keysym = 0xff08;
+const XK_RETURN: Keysym = 0xff0d;
+const XK_KP_ENTER: Keysym = 0xff8d;
+const XK_ESCAPE: Keysym = 0xff1b;
+const XK_SHIFT_L: Keysym = 0xffe1;
+const XK_HYPER_R: Keysym = 0xffee;
+const XK_MODE_SWITCH: Keysym = 0xff7e;
+const XK_NUM_LOCK: Keysym = 0xff7f;
+/// `ISO_Lock` to `ISO_Level5_Lock`, the level shifts, latches and locks
+const XK_ISO_MODIFIERS: std::ops::RangeInclusive<Keysym> = 0xfe01..=0xfe13;
+
+/// What a single keypress means for the PIN entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputAction {
+    Char(char),
+    Backspace,
+    Clear,
+    Submit,
+    /// A modifier pressed on its own, like Shift or Ctrl, which only
+    /// changes what the next key types.
+    Modifier,
+    /// A key meaning nothing for the PIN entry.
+    Ignore,
+}
+
+impl InputAction {
+    pub fn from_keysym(keysym: Keysym) -> Self {
+        match keysym {
+            // The numpad's Enter is a key of its own, not a Return
+            XK_RETURN | XK_KP_ENTER => Self::Submit,
+            XK_BACKSPACE => Self::Backspace,
+            XK_ESCAPE => Self::Clear,
+            _ if is_modifier(keysym) => Self::Modifier,
+            _ => keysym_to_char(keysym).map_or(Self::Ignore, Self::Char),
+        }
+    }
+}
+
+/// Whether `keysym` is of a modifier key: Shift, Ctrl, Alt, Super and the
+/// like, the lock keys and the level shifts of ISO layouts.
+pub fn is_modifier(keysym: Keysym) -> bool {
+    matches!(
+        keysym,
+        XK_SHIFT_L..=XK_HYPER_R | XK_MODE_SWITCH | XK_NUM_LOCK
+    ) || XK_ISO_MODIFIERS.contains(&keysym)
+}
+
+/// What happens to the key presses of X's auto-repeat while locked.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyRepeat {
+    /// They type like any other key press.
+    #[default]
+    On,
+    /// Auto-repeat is turned off for the whole display while locked.
+    Off,
+    /// They are told apart from real key presses and ignored.
+    Filter,
+}
+
+/// What dead keys, like the `^` of many European layouts, do in the PIN.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeadKeys {
+    /// They combine with the next key, `^` then `e` typing `ê` and `^` then
+    /// space the `^` itself. Needs the `compose` feature.
+    #[default]
+    Compose,
+    /// They are dropped, and the next key types itself.
+    Ignore,
+}
+
+/// Dead keysyms, from `dead_grave` on.
+#[cfg(feature = "compose")]
+const XK_DEAD_KEYS: std::ops::RangeInclusive<Keysym> = 0xfe50..=0xfe93;
+
+/// Dead keys for locales without a compose table: each with what it types
+/// on its own, the letters it combines with and what they become.
+#[cfg(feature = "compose")]
+const DEAD_KEY_RULES: &[(&str, char, &str, &str)] = &[
+    ("dead_grave", '`', "aeiouAEIOU", "àèìòùÀÈÌÒÙ"),
+    ("dead_acute", '\'', "aeiouyAEIOUY", "áéíóúýÁÉÍÓÚÝ"),
+    ("dead_circumflex", '^', "aeiouAEIOU", "âêîôûÂÊÎÔÛ"),
+    ("dead_tilde", '~', "anoANO", "ãñõÃÑÕ"),
+    ("dead_diaeresis", '"', "aeiouyAEIOU", "äëïöüÿÄËÏÖÜ"),
+    ("dead_cedilla", ',', "cC", "çÇ"),
+];
+
+/// Keeps auto-repeat off for `key_repeat = "off"`, turning it back on once
+/// dropped if it was on before.
+pub struct RepeatOff<'connection> {
+    conn: &'connection RustConnection,
+    was_on: bool,
+}
+
+impl<'connection> RepeatOff<'connection> {
+    pub fn new(connection: &'connection RustConnection) -> Result<Self> {
+        let was_on = connection
+            .get_keyboard_control()?
+            .reply()?
+            .global_auto_repeat
+            == AutoRepeatMode::ON;
+        connection.change_keyboard_control(
+            &ChangeKeyboardControlAux::new().auto_repeat_mode(AutoRepeatMode::OFF),
+        )?;
+        Ok(Self {
+            conn: connection,
+            was_on,
+        })
+    }
+}
+
+impl Drop for RepeatOff<'_> {
+    fn drop(&mut self) {
+        if self.was_on {
+            let _ = self.conn.change_keyboard_control(
+                &ChangeKeyboardControlAux::new().auto_repeat_mode(AutoRepeatMode::ON),
+            );
+            let _ = self.conn.flush();
+        }
+    }
+}
+
+/// Ask XKB to repeat held keys with presses alone, rather than releasing
+/// and pressing them again, so that a release means the key went up. Stays
+/// on for the connection. Returns whether the server obliged.
+pub fn detectable_auto_repeat(connection: &RustConnection) -> Result<bool> {
+    if connection
+        .extension_information(X11_EXTENSION_NAME)?
+        .is_none()
+        || !connection.xkb_use_extension(1, 0)?.reply()?.supported
+    {
+        return Ok(false);
+    }
+    let flag = PerClientFlag::DETECTABLE_AUTO_REPEAT;
+    let none = BoolCtrl::from(0u32);
+    let reply = connection
+        .xkb_per_client_flags(ID::USE_CORE_KBD.into(), flag, flag, none, none, none)?
+        .reply()?;
+    Ok(reply.value.contains(flag))
+}
+
+/// Recognizes the key presses of auto-repeat, for `key_repeat = "filter"`.
+///
+/// X repeats a held key with a release and a press of it with the same
+/// timestamp, and without the release for clients that asked for detectable
+/// auto-repeat.
+#[derive(Debug, Default)]
+pub struct RepeatFilter {
+    /// The key released by the event right before, and when
+    last_release: Option<(Keycode, Timestamp)>,
+}
+
+impl RepeatFilter {
+    /// Whether `event` is a repeated key press, given that `held` says if
+    /// its key was down already. Needs to see every event, so that only a
+    /// release right before a press counts.
+    pub fn is_repeat(&mut self, event: &Event, held: impl FnOnce(Keycode) -> bool) -> bool {
+        let last_release = self.last_release.take();
+        match event {
+            Event::KeyRelease(event) => {
+                self.last_release = Some((event.detail, event.time));
+                false
+            }
+            Event::KeyPress(event) => {
+                last_release == Some((event.detail, event.time)) || held(event.detail)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The keyboard the PIN is taken from with `pin_device`.
+///
+/// Core key events don't say which keyboard they came from. Raw XInput2
+/// events do, and the server sends them ahead of the core event of the same
+/// key, so a key press counts if the device's raw press of it came first.
+#[cfg(feature = "xinput2")]
+pub struct PinDevice<'connection> {
+    devices: Vec<u16>,
+    /// Keys pressed on the device whose core events are yet to come
+    pending: Vec<Keycode>,
+    root: u32,
+    connection: &'connection RustConnection,
+}
+
+#[cfg(feature = "xinput2")]
+impl<'connection> PinDevice<'connection> {
+    /// Ask for the raw key presses of the keyboards matching `device` by
+    /// name or id. Returns `None` with a warning if there are none, in which
+    /// case every keyboard is used.
+    pub fn select(
+        connection: &'connection RustConnection,
+        root: u32,
+        device: &str,
+    ) -> Option<Self> {
+        match Self::try_select(connection, root, device) {
+            Ok(Some(pin_device)) => Some(pin_device),
+            Ok(None) => {
+                log::warn!("No keyboard `{device}` found, taking the PIN from every keyboard");
+                None
+            }
+            Err(err) => {
+                log::warn!(
+                    "Failed to select `{device}`, taking the PIN from every keyboard: {err:#}"
+                );
+                None
+            }
+        }
+    }
+
+    fn try_select(
+        connection: &'connection RustConnection,
+        root: u32,
+        device: &str,
+    ) -> Result<Option<Self>> {
+        use x11rb::{
+            connection::RequestConnection,
+            protocol::xinput::{self, ConnectionExt as _, DeviceType},
+        };
+
+        if connection
+            .extension_information(xinput::X11_EXTENSION_NAME)?
+            .is_none()
+        {
+            bail!("XInput2 is unavailable");
+        }
+        // Raw events only reach the grabbing client from 2.1 on
+        let version = connection.xinput_xi_query_version(2, 1)?.reply()?;
+        if (version.major_version, version.minor_version) < (2, 1) {
+            bail!("XInput 2.1 is unavailable");
+        }
+
+        let devices: Vec<u16> = connection
+            .xinput_xi_query_device(xinput::Device::ALL)?
+            .reply()?
+            .infos
+            .into_iter()
+            .filter(|info| {
+                info.type_ == DeviceType::SLAVE_KEYBOARD
+                    && (info.name == device.as_bytes() || info.deviceid.to_string() == device)
+            })
+            .map(|info| info.deviceid)
+            .collect();
+        if devices.is_empty() {
+            return Ok(None);
+        }
+
+        let pin_device = Self {
+            devices,
+            pending: Vec::new(),
+            root,
+            connection,
+        };
+        pin_device.select_raw_presses(true)?;
+        Ok(Some(pin_device))
+    }
+
+    fn select_raw_presses(&self, selected: bool) -> Result<()> {
+        use x11rb::protocol::xinput::{ConnectionExt as _, EventMask, XIEventMask};
+
+        let mask = if selected {
+            XIEventMask::RAW_KEY_PRESS
+        } else {
+            XIEventMask::from(0u32)
+        };
+        let masks: Vec<EventMask> = self
+            .devices
+            .iter()
+            .map(|&deviceid| EventMask {
+                deviceid,
+                mask: vec![mask],
+            })
+            .collect();
+        self.connection.xinput_xi_select_events(self.root, &masks)?;
+        Ok(())
+    }
+
+    /// Note a raw key press, which the core one follows.
+    pub fn on_raw_press(&mut self, deviceid: u16, keycode: u32) {
+        if self.devices.contains(&deviceid) {
+            if let Ok(keycode) = Keycode::try_from(keycode) {
+                self.pending.push(keycode);
+            }
+        }
+    }
+
+    /// Whether the core press of `keycode` came from the device.
+    pub fn take(&mut self, keycode: Keycode) -> bool {
+        match self.pending.iter().position(|&pending| pending == keycode) {
+            Some(index) => {
+                self.pending.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Stops the raw events, which would otherwise keep coming between locks in
+/// daemon mode.
+#[cfg(feature = "xinput2")]
+impl Drop for PinDevice<'_> {
+    fn drop(&mut self) {
+        if let Err(err) = self.select_raw_presses(false) {
+            log::warn!("Failed to stop the raw key events: {err:#}");
+        }
+    }
+}
+
+/// Compose key sequences, turning e.g. Compose, `'`, `e` into `é`, and
+/// dead keys, turning `^`, `e` into `ê`.
+///
+/// Keys that are part of a sequence are swallowed, so partial and cancelled
+/// sequences never leave stray characters in the input. A key that doesn't
+/// go with a dead key drops the dead key and types itself.
+#[cfg(feature = "compose")]
+pub struct Compose {
+    state: xkb::compose::State,
+    dead_keys: DeadKeys,
+    /// The key the sequence being composed started with
+    started_with: Option<Keysym>,
+}
+
+#[cfg(feature = "compose")]
+impl Compose {
+    /// Load the compose table of the user's locale, which also takes
+    /// `~/.XCompose` into account. Falls back to a table of common dead
+    /// keys if there is none.
+    pub fn from_locale(dead_keys: DeadKeys) -> Option<Self> {
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+            .into_iter()
+            .filter_map(std::env::var_os)
+            .find(|locale| !locale.is_empty())
+            .unwrap_or_else(|| "C".into());
+
+        let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+        match xkb::compose::Table::new_from_locale(
+            &context,
+            &locale,
+            xkb::compose::COMPILE_NO_FLAGS,
+        ) {
+            Ok(table) => Some(Self::new(&table, dead_keys)),
+            Err(()) => {
+                log::debug!("No compose table for {locale:?}, only composing dead keys");
+                Self::from_rules(&dead_key_rules(), dead_keys)
+            }
+        }
+    }
+
+    /// Compose with the rules of a compose file.
+    fn from_rules(rules: &str, dead_keys: DeadKeys) -> Option<Self> {
+        let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+        let table = xkb::compose::Table::new_from_buffer(
+            &context,
+            rules,
+            "C",
+            xkb::compose::FORMAT_TEXT_V1,
+            xkb::compose::COMPILE_NO_FLAGS,
+        )
+        .ok()?;
+        Some(Self::new(&table, dead_keys))
+    }
+
+    fn new(table: &xkb::compose::Table, dead_keys: DeadKeys) -> Self {
+        Self {
+            state: xkb::compose::State::new(table, xkb::compose::STATE_NO_FLAGS),
+            dead_keys,
+            started_with: None,
+        }
+    }
+
+    /// Feed a keysym through the compose state machine, resulting in what it
+    /// means for the PIN entry.
+    pub fn action(&mut self, keysym: Keysym) -> InputAction {
+        if self.dead_keys == DeadKeys::Ignore && XK_DEAD_KEYS.contains(&keysym) {
+            return InputAction::Ignore;
+        }
+        // Modifiers and the like don't affect the sequence
+        if self.state.feed(keysym.into()) == xkb::compose::FeedResult::Ignored {
+            return InputAction::from_keysym(keysym);
+        }
+
+        match self.state.status() {
+            xkb::compose::Status::Nothing => InputAction::from_keysym(keysym),
+            xkb::compose::Status::Composing => {
+                self.started_with.get_or_insert(keysym);
+                InputAction::Ignore
+            }
+            xkb::compose::Status::Composed => {
+                let composed = self
+                    .state
+                    .keysym()
+                    .and_then(|keysym| keysym_to_char(keysym.raw()))
+                    .or_else(|| {
+                        let text = self.state.utf8()?;
+                        let mut chars = text.chars();
+                        chars.next().filter(|_| chars.next().is_none())
+                    });
+                self.state.reset();
+                self.started_with = None;
+                composed.map_or(InputAction::Ignore, InputAction::Char)
+            }
+            xkb::compose::Status::Cancelled => {
+                self.state.reset();
+                let after_dead_key = self
+                    .started_with
+                    .take()
+                    .is_some_and(|started| XK_DEAD_KEYS.contains(&started));
+                match InputAction::from_keysym(keysym) {
+                    InputAction::Char(c) if after_dead_key => InputAction::Char(c),
+                    _ => InputAction::Ignore,
+                }
+            }
+        }
+    }
+}
+
+/// [`DEAD_KEY_RULES`] in the format of compose files.
+#[cfg(feature = "compose")]
+fn dead_key_rules() -> String {
+    let quoted = |c: char| match c {
+        '"' | '\\' => format!("\"\\{c}\""),
+        _ => format!("\"{c}\""),
+    };
+    let mut rules = String::new();
+    for &(dead_key, alone, letters, composed) in DEAD_KEY_RULES {
+        for (letter, composed) in letters.chars().zip(composed.chars()) {
+            rules += &format!("<{dead_key}> <{letter}> : {}\n", quoted(composed));
+        }
+        rules += &format!("<{dead_key}> <space> : {}\n", quoted(alone));
+    }
+    rules
+}
+
+/// Keys that can be named in a [`KeyCombo`], besides letters, digits and
+/// F1 to F12.
+const KEY_NAMES: &[(&str, Keysym)] = &[
+    ("Super_L", 0xffeb),
+    ("Super_R", 0xffec),
+    ("Alt_L", 0xffe9),
+    ("Alt_R", 0xffea),
+    ("Control_L", 0xffe3),
+    ("Control_R", 0xffe4),
+    ("Menu", 0xff67),
+    ("Print", 0xff61),
+    ("Tab", 0xff09),
+    ("Return", XK_RETURN),
+    ("KP_Enter", XK_KP_ENTER),
+    ("Escape", XK_ESCAPE),
+    ("Delete", 0xffff),
+    ("space", 0x20),
+];
+
+/// A key and the modifiers held with it, written like `"Super_L"`,
+/// `"Mod4+d"` or `"Control+Alt+Delete"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct KeyCombo {
+    pub modifiers: ModMask,
+    pub keysym: Keysym,
+}
+
+impl TryFrom<String> for KeyCombo {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        let mut parts: Vec<&str> = value.split('+').map(str::trim).collect();
+        let key = parts.pop().unwrap_or_default();
+
+        let mut modifiers = ModMask::from(0u16);
+        for part in parts {
+            modifiers |= match part.to_ascii_lowercase().as_str() {
+                "shift" => ModMask::SHIFT,
+                "control" | "ctrl" => ModMask::CONTROL,
+                "mod1" | "alt" => ModMask::M1,
+                "mod2" => ModMask::M2,
+                "mod3" => ModMask::M3,
+                "mod4" | "super" => ModMask::M4,
+                "mod5" => ModMask::M5,
+                _ => bail!("Unknown modifier `{part}` in `{value}`"),
+            };
+        }
+
+        let keysym = match key.as_bytes() {
+            [c] if c.is_ascii_alphanumeric() => Keysym::from(c.to_ascii_lowercase()),
+            _ => match key.strip_prefix('F').and_then(|n| n.parse::<Keysym>().ok()) {
+                // F1 is 0xffbe
+                Some(n @ 1..=12) => 0xffbd + n,
+                _ => match KEY_NAMES.iter().find(|(name, _)| *name == key) {
+                    Some(&(_, keysym)) => keysym,
+                    None => bail!("Unknown key `{key}` in `{value}`"),
+                },
+            },
+        };
+
+        Ok(Self { modifiers, keysym })
+    }
+}
+
+/// Lock and Mod2 (Num Lock), the modifiers that stay active after their key
+/// was released.
+const LOCKING_MODIFIERS: u16 = 1 << 1 | 1 << 4;
+
+/// The keycode to keysym table of the core keyboard.
+pub struct Keymap {
+    min_keycode: Keycode,
+    keysyms_per_keycode: usize,
+    keysyms: Vec<Keysym>,
+    /// Keycodes of each of the eight modifiers, in modifier order
+    modifier_keycodes: Vec<Keycode>,
+}
+
+impl Keymap {
+    pub fn load(connection: &RustConnection) -> Result<Self> {
+        let setup = connection.setup();
+        let min_keycode = setup.min_keycode;
+        let count = setup.max_keycode - min_keycode + 1;
+
+        let reply = connection
+            .get_keyboard_mapping(min_keycode, count)?
+            .reply()?;
+        let modifiers = connection.get_modifier_mapping()?.reply()?;
+
+        Ok(Self {
+            min_keycode,
+            keysyms_per_keycode: reply.keysyms_per_keycode.into(),
+            keysyms: reply.keysyms,
+            modifier_keycodes: modifiers.keycodes,
+        })
+    }
+
+    /// Reconstruct the modifier state from the bitmap of held keys sent in a
+    /// `KeymapNotify`. Whether Caps Lock and Num Lock are engaged isn't
+    /// visible from held keys, so those are taken from `locked`, the current
+    /// state as reported by the server.
+    pub fn modifiers(&self, keys: &[u8; 31], locked: KeyButMask) -> KeyButMask {
+        let per_modifier = (self.modifier_keycodes.len() / 8).max(1);
+        let held = self
+            .modifier_keycodes
+            .chunks(per_modifier)
+            .enumerate()
+            .filter(|(_, keycodes)| keycodes.iter().any(|&keycode| is_pressed(keys, keycode)))
+            .fold(0u16, |mask, (index, _)| mask | 1 << index);
+
+        KeyButMask::from((held & !LOCKING_MODIFIERS) | (u16::from(locked) & LOCKING_MODIFIERS))
+    }
+
+    /// Resolve a keycode to a keysym, taking Shift and Caps Lock into account.
+    pub fn keysym(&self, keycode: Keycode, state: KeyButMask) -> Keysym {
+        let syms = self.syms(keycode);
+
+        let lower = syms.first().copied().unwrap_or(0);
+        let upper = match syms.get(1).copied() {
+            Some(0) | None => lower,
+            Some(sym) => sym,
+        };
+
+        let shift = state.contains(KeyButMask::SHIFT);
+
+        // Num Lock picks the second keysym of the keypad, the digits, which
+        // Shift reverses
+        if is_keypad(upper) {
+            return if shift != state.contains(KeyButMask::MOD2) {
+                upper
+            } else {
+                lower
+            };
+        }
+
+        let caps = state.contains(KeyButMask::LOCK) && is_alphabetic(lower);
+
+        if shift != caps {
+            upper
+        } else {
+            lower
+        }
+    }
+
+    /// The digit on a key of the number row, whatever the layout and the
+    /// modifiers. Layouts like AZERTY put the digits on the shifted level,
+    /// others on the second group, so every level of both groups is searched
+    /// for one. Other keys, the keypad's included, have none.
+    ///
+    /// Unlike [`Keymap::keysym`] this ignores the active layout, so that a
+    /// numeric PIN is typed the same everywhere. The cost is that the
+    /// symbols sharing the number row can't be typed through it.
+    pub fn digit_from_keycode(&self, keycode: Keycode) -> Option<char> {
+        self.syms(keycode)
+            .iter()
+            .take(4)
+            .find(|sym| (0x30..=0x39).contains(*sym))
+            .and_then(|&sym| char::from_u32(sym))
+    }
+
+    /// The keycodes with `keysym` on any of their levels.
+    pub fn keycodes(&self, keysym: Keysym) -> Vec<Keycode> {
+        (self.min_keycode..=Keycode::MAX)
+            .take(self.keysyms.len() / self.keysyms_per_keycode.max(1))
+            .filter(|&keycode| self.syms(keycode).contains(&keysym))
+            .collect()
+    }
+
+    /// The keysyms of a keycode, by group and level.
+    fn syms(&self, keycode: Keycode) -> &[Keysym] {
+        let Some(index) = keycode.checked_sub(self.min_keycode) else {
+            return &[];
+        };
+        let start = usize::from(index) * self.keysyms_per_keycode;
+        self.keysyms
+            .get(start..start + self.keysyms_per_keycode)
+            .unwrap_or(&[])
+    }
+}
+
+/// Whether a `KeymapNotify` bitmap, which starts at keycode 8, has a key down.
+fn is_pressed(keys: &[u8; 31], keycode: Keycode) -> bool {
+    let Some(index) = keycode.checked_sub(8).map(usize::from) else {
+        return false;
+    };
+    keys.get(index / 8)
+        .is_some_and(|byte| byte & (1 << (index % 8)) != 0)
+}
+
+fn is_keypad(keysym: Keysym) -> bool {
+    (0xff80..=0xffbd).contains(&keysym)
+}
+
+fn is_alphabetic(keysym: Keysym) -> bool {
+    keysym_to_char(keysym).is_some_and(char::is_alphabetic)
+}
+
+/// Map printable keysyms to the character they produce.
+fn keysym_to_char(keysym: Keysym) -> Option<char> {
+    match keysym {
+        // Latin-1 keysyms are equal to their code points
+        0x20..=0x7e | 0xa0..=0xff => char::from_u32(keysym),
+        // Keypad digits, 0xffb0 being KP_0
+        0xffb0..=0xffb9 => char::from_digit(keysym - 0xffb0, 10),
+        // Keysyms with the Unicode bit carry the code point directly
+        0x0100_0100..=0x0110_ffff => char::from_u32(keysym - 0x0100_0000),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use x11rb::protocol::xproto::{KeyPressEvent, KeyReleaseEvent};
+
+    use super::*;
+
+    /// Keycodes 10 and 11, the `1` and `2` keys of the number row, and 24,
+    /// the `q` key, in two layouts of two groups of two levels each
+    fn keymap(rows: [[Keysym; 4]; 3]) -> Keymap {
+        let mut keysyms = vec![0; 17 * 4];
+        for (keycode, row) in [10, 11, 24].into_iter().zip(rows) {
+            let start = (keycode - 8) * 4;
+            keysyms[start..start + 4].copy_from_slice(&row);
+        }
+        Keymap {
+            min_keycode: 8,
+            keysyms_per_keycode: 4,
+            keysyms,
+            modifier_keycodes: Vec::new(),
+        }
+    }
+
+    #[cfg(feature = "compose")]
+    #[test]
+    fn dead_keys_compose_with_the_next_key() {
+        const XK_DEAD_ACUTE: Keysym = 0xfe51;
+        const XK_DEAD_CIRCUMFLEX: Keysym = 0xfe52;
+        let mut compose = Compose::from_rules(&dead_key_rules(), DeadKeys::Compose).unwrap();
+        let mut typed = |keysyms: &[Keysym]| -> Vec<InputAction> {
+            keysyms
+                .iter()
+                .map(|&keysym| compose.action(keysym))
+                .collect()
+        };
+
+        assert_eq!(
+            typed(&[XK_DEAD_CIRCUMFLEX, 'e' as Keysym]),
+            [InputAction::Ignore, InputAction::Char('ê')]
+        );
+        assert_eq!(
+            typed(&[XK_DEAD_ACUTE, ' ' as Keysym]),
+            [InputAction::Ignore, InputAction::Char('\'')]
+        );
+        // A key that doesn't go with it drops the dead key
+        assert_eq!(
+            typed(&[XK_DEAD_CIRCUMFLEX, 'x' as Keysym]),
+            [InputAction::Ignore, InputAction::Char('x')]
+        );
+
+        let mut ignoring = Compose::from_rules(&dead_key_rules(), DeadKeys::Ignore).unwrap();
+        assert_eq!(ignoring.action(XK_DEAD_CIRCUMFLEX), InputAction::Ignore);
+        assert_eq!(ignoring.action('e' as Keysym), InputAction::Char('e'));
+    }
+
+    #[test]
+    fn modifiers_on_their_own_type_nothing() {
+        // Shift_L, Control_R, Alt_L, Super_L, Caps_Lock and ISO_Level3_Shift
+        for keysym in [0xffe1, 0xffe4, 0xffe9, 0xffeb, 0xffe5, 0xfe03] {
+            assert_eq!(InputAction::from_keysym(keysym), InputAction::Modifier);
+        }
+        // Keys that aren't modifiers nor type anything, like F1
+        assert_eq!(InputAction::from_keysym(0xffbe), InputAction::Ignore);
+    }
+
+    #[test]
+    fn both_enter_keys_submit() {
+        assert_eq!(InputAction::from_keysym(XK_RETURN), InputAction::Submit);
+        assert_eq!(InputAction::from_keysym(XK_KP_ENTER), InputAction::Submit);
+    }
+
+    #[test]
+    fn number_row_digits_are_the_same_in_every_layout() {
+        // US with Russian as its second group
+        let us = keymap([
+            [0x31, 0x21, 0x31, 0x21],
+            [0x32, 0x40, 0x32, 0x22],
+            [0x71, 0x51, 0x6ca, 0x6ea],
+        ]);
+        // French AZERTY, digits on the shifted level, with Czech as its
+        // second group, which has no digits at the base level either
+        let azerty = keymap([
+            [0x26, 0x31, 0x2b, 0x31],
+            [0xe9, 0x32, 0x1ec, 0x32],
+            [0x61, 0x41, 0x71, 0x51],
+        ]);
+
+        for keymap in [&us, &azerty] {
+            assert_eq!(keymap.digit_from_keycode(10), Some('1'));
+            assert_eq!(keymap.digit_from_keycode(11), Some('2'));
+            assert_eq!(keymap.digit_from_keycode(24), None);
+        }
+        // While the active layout differs
+        assert_ne!(
+            us.keysym(10, KeyButMask::default()),
+            azerty.keysym(10, KeyButMask::default())
+        );
+    }
+
+    #[test]
+    fn key_combos_name_modifiers_and_a_key() {
+        let combo = |text: &str| KeyCombo::try_from(text.to_owned()).ok();
+        assert_eq!(
+            combo("Super_L"),
+            Some(KeyCombo {
+                modifiers: ModMask::from(0u16),
+                keysym: 0xffeb,
+            })
+        );
+        assert_eq!(
+            combo("Mod4+D"),
+            Some(KeyCombo {
+                modifiers: ModMask::M4,
+                keysym: 0x64,
+            })
+        );
+        assert_eq!(
+            combo("Ctrl + Alt + F12").map(|combo| (combo.modifiers, combo.keysym)),
+            Some((ModMask::CONTROL | ModMask::M1, 0xffc9))
+        );
+        assert_eq!(combo("Hyper+x"), None);
+        assert_eq!(combo("F13"), None);
+        assert_eq!(combo(""), None);
+    }
+
+    #[test]
+    fn keycodes_are_found_on_any_level() {
+        let us = keymap([
+            [0x31, 0x21, 0x31, 0x21],
+            [0x32, 0x40, 0x32, 0x22],
+            [0x71, 0x51, 0x6ca, 0x6ea],
+        ]);
+        assert_eq!(us.keycodes(0x21), [10]);
+        assert_eq!(us.keycodes(0x6ca), [24]);
+        assert!(us.keycodes(0xffeb).is_empty());
+    }
+
+    #[test]
+    fn a_release_and_press_at_the_same_time_is_a_repeat() {
+        let release = |time| {
+            Event::KeyRelease(KeyReleaseEvent {
+                detail: 10,
+                time,
+                ..Default::default()
+            })
+        };
+        let press = |time| {
+            Event::KeyPress(KeyPressEvent {
+                detail: 10,
+                time,
+                ..Default::default()
+            })
+        };
+
+        let mut filter = RepeatFilter::default();
+        assert!(!filter.is_repeat(&press(100), |_| false));
+        assert!(!filter.is_repeat(&release(130), |_| false));
+        assert!(filter.is_repeat(&press(130), |_| false));
+        assert!(!filter.is_repeat(&release(160), |_| false));
+        assert!(!filter.is_repeat(&press(200), |_| false));
+        // Detectable auto-repeat skips the release
+        assert!(filter.is_repeat(&press(230), |_| true));
+    }
+}
@@ -0,0 +1,94 @@
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, Write},
+    os::fd::AsRawFd,
+    path::PathBuf,
+};
+
+use anyhow::{Context, Result};
+
+use crate::error::AlreadyRunning;
+
+/// Held while this is the only pinlock on its display, preventing a second
+/// one from fighting over the grabs.
+///
+/// Backed by an exclusive `flock` on a pidfile in `$XDG_RUNTIME_DIR`, which
+/// the kernel releases however the process ends.
+pub struct InstanceLock {
+    _file: File,
+}
+
+impl InstanceLock {
+    /// Take the lock for `display`, `$DISPLAY` if not given, or fail with
+    /// [`AlreadyRunning`] if another instance holds it.
+    pub fn acquire(display: Option<&str>) -> Result<Self> {
+        let path = pidfile_path(display)?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+
+        // SAFETY: the descriptor is valid for as long as `file` lives
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::WouldBlock {
+                return Err(err).with_context(|| format!("Failed to lock {}", path.display()));
+            }
+
+            let mut contents = String::new();
+            file.read_to_string(&mut contents)?;
+            return Err(AlreadyRunning {
+                pid: contents.trim().parse().ok(),
+            }
+            .into());
+        }
+
+        file.set_len(0)?;
+        file.rewind()?;
+        writeln!(file, "{}", std::process::id())
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+
+        Ok(Self { _file: file })
+    }
+}
+
+impl AlreadyRunning {
+    /// Ask the running instance to lock the screen, as if its idle timeout
+    /// ran out. An instance that is locked already stays that way.
+    pub fn request_lock(&self) -> Result<()> {
+        let pid = self
+            .pid
+            .context("The running instance didn't record its pid")?;
+        // SAFETY: only sends a signal
+        if unsafe { libc::kill(pid, libc::SIGUSR1) } != 0 {
+            return Err(io::Error::last_os_error())
+                .with_context(|| format!("Failed to signal pid {pid}"));
+        }
+        Ok(())
+    }
+}
+
+/// One pidfile per display, so that sessions on different displays don't
+/// block each other.
+fn pidfile_path(display: Option<&str>) -> Result<PathBuf> {
+    let runtime_dir = match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        // SAFETY: always safe to call
+        _ => env::temp_dir().join(format!("pinlock-{}", unsafe { libc::getuid() })),
+    };
+
+    let display = match display {
+        Some(display) => display.to_owned(),
+        None => env::var("DISPLAY").unwrap_or_default(),
+    }
+    .replace('/', "_");
+    Ok(runtime_dir.join(format!("pinlock{display}.pid")))
+}
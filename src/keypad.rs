@@ -0,0 +1,92 @@
+use log::warn;
+
+use crate::random::RandomnessSource;
+
+/// Digits of the on-screen keypad in the order of its keys, left to right and
+/// top to bottom, the last one sitting between Clear and Submit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keypad {
+    digits: [char; 10],
+}
+
+/// The layout of a phone keypad.
+const ORDERED: [char; 10] = ['1', '2', '3', '4', '5', '6', '7', '8', '9', '0'];
+
+impl Keypad {
+    /// The ordered layout, or with `source` a random one so that onlookers
+    /// learn nothing from where the keys are touched.
+    pub fn new(source: Option<&RandomnessSource>) -> Self {
+        let mut keypad = Self { digits: ORDERED };
+        if let Some(source) = source {
+            keypad.shuffle(source);
+        }
+        keypad
+    }
+
+    pub fn digits(&self) -> [char; 10] {
+        self.digits
+    }
+
+    /// Move the digits to new random keys, drawn from `source`. Should that
+    /// fail, the keypad falls back to the ordered layout rather than one
+    /// that onlookers may have learnt already.
+    pub fn shuffle(&mut self, source: &RandomnessSource) {
+        let mut digits = self.digits;
+        match shuffle(&mut digits, || source.u32()) {
+            Ok(()) => self.digits = digits,
+            Err(err) => {
+                warn!("Failed to shuffle the keypad, keeping it ordered: {err:#}");
+                self.digits = ORDERED;
+            }
+        }
+    }
+}
+
+/// Fisher-Yates shuffle, unbiased as long as `random` is.
+fn shuffle<T, E>(items: &mut [T], mut random: impl FnMut() -> Result<u32, E>) -> Result<(), E> {
+    for i in (1..items.len()).rev() {
+        let j = below(i as u32 + 1, &mut random)?;
+        items.swap(i, j as usize);
+    }
+    Ok(())
+}
+
+/// A random number below `bound`, rejecting the values that would favor
+/// some results over others.
+fn below<E>(bound: u32, random: &mut impl FnMut() -> Result<u32, E>) -> Result<u32, E> {
+    let zone = u32::MAX - u32::MAX % bound;
+    loop {
+        let value = random()?;
+        if value < zone {
+            return Ok(value % bound);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use super::*;
+
+    #[test]
+    fn a_shuffled_keypad_has_every_digit_once() {
+        let mut digits = Keypad::new(Some(&RandomnessSource::Os)).digits();
+        digits.sort_unstable();
+        assert_eq!(digits, ['0', '1', '2', '3', '4', '5', '6', '7', '8', '9']);
+    }
+
+    #[test]
+    fn an_unavailable_source_leaves_the_keypad_ordered() {
+        let mut keypad = Keypad::new(Some(&RandomnessSource::Os));
+        keypad.shuffle(&RandomnessSource::Device("/nonexistent/hwrng".into()));
+        assert_eq!(keypad, Keypad::new(None));
+    }
+
+    #[test]
+    fn values_that_would_bias_the_shuffle_are_drawn_again() {
+        let mut values = [u32::MAX, 7].into_iter();
+        let mut random = || Ok::<_, Infallible>(values.next().unwrap());
+        assert_eq!(below(10, &mut random), Ok(7));
+    }
+}
@@ -0,0 +1,143 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use log::debug;
+use serde::Deserialize;
+use x11rb::{
+    connection::RequestConnection,
+    protocol::{
+        xkb::{self, ConnectionExt as _, EventType, MapPart, SelectEventsAux, ID},
+        xproto::{ChangeKeyboardControlAux, ConnectionExt, LedMode},
+    },
+    rust_connection::RustConnection,
+};
+
+/// How long the LED stays toggled after a failed attempt.
+const FLASH_DURATION: Duration = Duration::from_millis(300);
+
+/// Keyboard LEDs by their conventional core protocol numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum Led {
+    #[serde(rename = "caps_lock")]
+    Caps,
+    #[serde(rename = "num_lock")]
+    Num,
+    #[serde(rename = "scroll_lock")]
+    Scroll,
+}
+
+impl Led {
+    fn number(self) -> u32 {
+        match self {
+            Self::Caps => 1,
+            Self::Num => 2,
+            Self::Scroll => 3,
+        }
+    }
+
+    /// The LED's bit in a mask of lit LEDs.
+    pub fn mask(self) -> u32 {
+        1 << (self.number() - 1)
+    }
+}
+
+/// The mask of lit keyboard LEDs. With XKB, changes are reported from then
+/// on as `XkbIndicatorStateNotify`, whose indicators match the LEDs. They
+/// also change without our keypresses, through other keyboards for one.
+pub fn follow(connection: &RustConnection) -> Result<u32> {
+    let xkb_supported = connection
+        .extension_information(xkb::X11_EXTENSION_NAME)?
+        .is_some()
+        && connection.xkb_use_extension(1, 0)?.reply()?.supported;
+    if xkb_supported {
+        connection.xkb_select_events(
+            ID::USE_CORE_KBD.into(),
+            EventType::from(0u16),
+            EventType::INDICATOR_STATE_NOTIFY,
+            MapPart::from(0u16),
+            MapPart::from(0u16),
+            &SelectEventsAux::new(),
+        )?;
+    } else {
+        debug!("No XKB, the LED indicators keep their state from locking");
+    }
+    Ok(connection.get_keyboard_control()?.reply()?.led_mask)
+}
+
+/// Briefly toggles a keyboard LED, restoring its prior state afterwards.
+pub struct LedFlash<'connection> {
+    conn: &'connection RustConnection,
+    led: Led,
+    /// When to restore the LED and whether it was lit before
+    restore: Option<(Instant, bool)>,
+}
+
+impl<'connection> LedFlash<'connection> {
+    pub fn new(connection: &'connection RustConnection, led: Led) -> Self {
+        Self {
+            conn: connection,
+            led,
+            restore: None,
+        }
+    }
+
+    /// Toggle the LED, or extend the flash if it is already toggled.
+    pub fn flash(&mut self) -> Result<()> {
+        let was_on = match self.restore {
+            Some((_, was_on)) => was_on,
+            None => {
+                let mask = self.conn.get_keyboard_control()?.reply()?.led_mask;
+                let was_on = mask & self.led.mask() != 0;
+                self.set(!was_on)?;
+                was_on
+            }
+        };
+
+        self.restore = Some((Instant::now() + FLASH_DURATION, was_on));
+        Ok(())
+    }
+
+    /// `mask` as if the LED wasn't flashing.
+    pub fn unflashed(&self, mask: u32) -> u32 {
+        match self.restore {
+            Some((_, true)) => mask | self.led.mask(),
+            Some((_, false)) => mask & !self.led.mask(),
+            None => mask,
+        }
+    }
+
+    /// Time left until the LED has to be restored.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.restore
+            .map(|(at, _)| at.saturating_duration_since(Instant::now()))
+    }
+
+    /// Restore the LED if its flash is over.
+    pub fn update(&mut self) -> Result<()> {
+        if let Some((at, was_on)) = self.restore {
+            if at <= Instant::now() {
+                self.restore = None;
+                self.set(was_on)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn set(&self, on: bool) -> Result<()> {
+        let mode = if on { LedMode::ON } else { LedMode::OFF };
+        self.conn.change_keyboard_control(
+            &ChangeKeyboardControlAux::new()
+                .led(self.led.number())
+                .led_mode(mode),
+        )?;
+        Ok(())
+    }
+}
+
+impl<'connection> Drop for LedFlash<'connection> {
+    fn drop(&mut self) {
+        if let Some((_, was_on)) = self.restore.take() {
+            let _ = self.set(was_on);
+        }
+    }
+}
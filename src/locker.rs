@@ -0,0 +1,1095 @@
+use std::{
+    env,
+    os::fd::AsRawFd,
+    os::unix::process::CommandExt,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use log::{error, info, trace, warn};
+use x11rb::{
+    connection::{Connection, RequestConnection},
+    protocol::{
+        randr, render,
+        screensaver::{self, ConnectionExt as _},
+        shape,
+        xproto::{ConnectionExt as _, NotifyMode, Screen},
+        Event,
+    },
+    rust_connection::RustConnection,
+};
+
+#[cfg(feature = "camera")]
+use crate::camera;
+#[cfg(feature = "dbus")]
+use crate::dbus::mpris;
+#[cfg(feature = "compose")]
+use crate::input::Compose;
+#[cfg(feature = "xinput2")]
+use crate::input::PinDevice;
+#[cfg(feature = "metrics")]
+use crate::metrics;
+#[cfg(feature = "replay")]
+use crate::replay::Replay;
+#[cfg(feature = "vtlock")]
+use crate::vt::VtLock;
+use crate::{
+    audit,
+    auth::{Authenticator, OneTimePin},
+    config::{Config, FailureCount},
+    dpms,
+    error::SetupError,
+    event_log::{self, EventLog},
+    image::{BackgroundImage, Encoded},
+    input::{self, InputAction, KeyRepeat, Keymap, RepeatFilter, RepeatOff},
+    led::{self, LedFlash},
+    persist::Persisted,
+    publish::PublishedState,
+    screensaver::Idle,
+    signals::Signals,
+    sound::Sounds,
+    state::{InputFilter, LockState, UnlockReason},
+    ui::{Button, Ui},
+    widget::{self, Region, Widget},
+    window::{ServerGrab, Window},
+};
+
+/// How long the check mark of `unlock_flash` is shown
+const UNLOCK_FLASH_DURATION: Duration = Duration::from_millis(200);
+
+/// The background image, with the server grabbed from before the
+/// screenshot is taken if `grab_server` is on, to be released once the lock
+/// window shows it.
+fn background_image<'connection>(
+    conn: &'connection RustConnection,
+    screen: &Screen,
+    config: &Config,
+) -> Result<(Option<Encoded>, Option<ServerGrab<'connection>>)> {
+    let runs_command = matches!(config.background_image, Some(BackgroundImage::Command(_)));
+    let mut server_grab = (config.grab_server && !runs_command)
+        .then(|| ServerGrab::new(conn))
+        .transpose()?;
+    let background_image = Encoded::from_config(conn, screen, config);
+    if config.grab_server && server_grab.is_none() {
+        server_grab = Some(ServerGrab::new(conn)?);
+    }
+    Ok((background_image, server_grab))
+}
+
+/// Wait for the next event, giving up after `timeout` if one is given.
+///
+/// Returns `None` on timeout, or early when a signal interrupted the wait.
+fn wait_for_event(conn: &RustConnection, timeout: Option<Duration>) -> Result<Option<Event>> {
+    if let Some(event) = conn.poll_for_event()? {
+        return Ok(Some(event));
+    }
+    conn.flush()?;
+
+    let mut fd = libc::pollfd {
+        fd: conn.stream().as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let timeout_ms = timeout.map_or(-1, |timeout| {
+        timeout.as_millis().try_into().unwrap_or(i32::MAX)
+    });
+    // SAFETY: `fd` is a single valid pollfd that outlives the call
+    if unsafe { libc::poll(&mut fd, 1, timeout_ms) } < 0 {
+        let err = std::io::Error::last_os_error();
+        if err.kind() != std::io::ErrorKind::Interrupted {
+            return Err(err.into());
+        }
+    }
+
+    Ok(conn.poll_for_event()?)
+}
+
+/// Locks the screen of an X connection, once or repeatedly.
+pub struct Locker {
+    conn: RustConnection,
+    screen_num: usize,
+    config: Config,
+    /// Where the configuration was read from, to reload it from there
+    config_path: Option<PathBuf>,
+    /// Drawn on the lock screen in their regions
+    widgets: Vec<(Region, Box<dyn Widget>)>,
+    event_log: Option<EventLog>,
+    /// Typed into the next lock screen
+    #[cfg(feature = "replay")]
+    replay: Option<Replay>,
+}
+
+impl Locker {
+    /// Connect to `display`, or to the one in `$DISPLAY` if not given.
+    pub fn new(
+        config: Config,
+        config_path: Option<PathBuf>,
+        display: Option<&str>,
+    ) -> Result<Self> {
+        let (conn, screen_num) = connect(display)?;
+
+        Ok(Self {
+            conn,
+            screen_num,
+            widgets: widget::from_config(&config),
+            config,
+            config_path,
+            event_log: None,
+            #[cfg(feature = "replay")]
+            replay: None,
+        })
+    }
+
+    /// Append the X events the lock screen receives to `path`, see
+    /// [`EventLog`].
+    pub fn log_events(&mut self, path: &Path) -> Result<()> {
+        self.event_log = Some(EventLog::open(path)?);
+        Ok(())
+    }
+
+    /// Type the keys of the script at `path` into the next lock screen, see
+    /// [`Replay`].
+    #[cfg(feature = "replay")]
+    pub fn replay(&mut self, path: &Path) -> Result<()> {
+        warn!("Replaying {}, this build is for tests only", path.display());
+        self.replay = Some(Replay::load(path)?);
+        Ok(())
+    }
+
+    /// How long the user has not touched any input device.
+    pub fn idle_time(&self) -> Result<Duration> {
+        let root = self.screen().root;
+        let info = self
+            .conn
+            .screensaver_query_info(root)
+            .context("The MIT-SCREEN-SAVER extension is required to detect idleness")?
+            .reply()?;
+
+        Ok(Duration::from_millis(info.ms_since_user_input.into()))
+    }
+
+    /// Whether a monitor other than the laptop's own panel is on, going by
+    /// the output names that panels have. Unknown counts as not docked, so
+    /// that closing the lid still locks.
+    #[cfg(feature = "logind")]
+    pub fn is_docked(&self) -> bool {
+        match self.external_outputs() {
+            Ok(outputs) => outputs > 0,
+            Err(err) => {
+                warn!("Failed to look for external monitors: {err:#}");
+                false
+            }
+        }
+    }
+
+    #[cfg(feature = "logind")]
+    fn external_outputs(&self) -> Result<usize> {
+        use x11rb::protocol::randr::{Connection as OutputConnection, ConnectionExt as _};
+
+        const PANELS: &[&str] = &["eDP", "LVDS", "DSI"];
+
+        let resources = self
+            .conn
+            .randr_get_screen_resources_current(self.screen().root)?
+            .reply()?;
+        let mut external = 0;
+        for output in resources.outputs {
+            let info = self
+                .conn
+                .randr_get_output_info(output, resources.config_timestamp)?
+                .reply()?;
+            let name = String::from_utf8_lossy(&info.name);
+            let panel = PANELS.iter().any(|panel| name.starts_with(panel));
+            if info.connection == OutputConnection::CONNECTED && info.crtc != 0 && !panel {
+                external += 1;
+            }
+        }
+        Ok(external)
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Reload the configuration if SIGHUP asked for it.
+    pub fn reload_if_requested(&mut self, signals: &Signals) {
+        if signals.take_reload() {
+            if let Some(config) = reload_config(self.config_path.as_deref()) {
+                self.widgets = widget::from_config(&config);
+                self.config = config;
+            }
+        }
+    }
+
+    fn screen(&self) -> &Screen {
+        &self.conn.setup().roots[self.screen_num]
+    }
+
+    /// Lock the screen until it is unlocked or pinlock is told to terminate.
+    ///
+    /// A configuration reloaded while locked only updates the texts, colors
+    /// and the failure LED right away. Everything else, the PINs in particular, takes
+    /// effect with the next lock.
+    pub fn lock(&mut self, signals: &Signals) -> Result<UnlockReason> {
+        self.cover(signals, false)
+    }
+
+    /// Cover the screen without locking it, until any key is pressed, which
+    /// ends it with [`UnlockReason::Grace`]. The input is grabbed all the
+    /// same, but nothing is recorded or paused.
+    pub fn blank(&mut self, signals: &Signals) -> Result<UnlockReason> {
+        self.cover(signals, true)
+    }
+
+    /// Go through locking without asking for the PIN: create the lock
+    /// window, take the grabs and draw once, hold it for `hold`, then take
+    /// it all down again. Prints which extensions the server has and how
+    /// long each step took.
+    pub fn selftest(&mut self, signals: &Signals, hold: Duration) -> Result<()> {
+        let (conn, config) = (&self.conn, &self.config);
+        let screen = &conn.setup().roots[self.screen_num];
+
+        print_extensions(conn)?;
+
+        let started = Instant::now();
+        let (background_image, server_grab) = background_image(conn, screen, config)?;
+        let keymap = Keymap::load(conn)?;
+        let mut window = Window::create(conn, screen, config, &keymap)?;
+        println!("Locked the input in {:?}", started.elapsed());
+
+        let started = Instant::now();
+        let ui = Ui::new(conn, &window, config, background_image)?;
+        if let Some(server_grab) = server_grab {
+            ui.show_background()?;
+            println!("Held the server for {:?}", server_grab.release());
+        }
+        ui.draw(
+            &LockState::new(config, &Persisted::default()),
+            &self.widgets,
+        )?;
+        // A round trip, so that the drawing is done
+        conn.get_input_focus()?.reply()?;
+        println!("Drew the lock screen in {:?}", started.elapsed());
+
+        // Input is dropped meanwhile
+        let until = Instant::now() + hold;
+        while let Some(remaining) = until
+            .checked_duration_since(Instant::now())
+            .filter(|remaining| !remaining.is_zero() && !signals.terminate())
+        {
+            wait_for_event(conn, Some(remaining))?;
+        }
+
+        let started = Instant::now();
+        drop(ui);
+        window.cleanup();
+        conn.get_input_focus()?.reply()?;
+        println!("Unlocked in {:?}", started.elapsed());
+        Ok(())
+    }
+
+    fn cover(&mut self, signals: &Signals, blank: bool) -> Result<UnlockReason> {
+        let (conn, config) = (&self.conn, &self.config);
+        let config_path = self.config_path.as_deref();
+        let screen = &conn.setup().roots[self.screen_num];
+        let widgets = &mut self.widgets;
+        let mut event_log = self.event_log.as_mut();
+        #[cfg(feature = "replay")]
+        let mut replay = self.replay.take();
+
+        // Run a background command while the desktop is still visible
+        // Failing before the window holds the input leaves the screen as it
+        // was
+        let (background_image, server_grab) =
+            background_image(conn, screen, config).context(SetupError)?;
+        let keymap = Keymap::load(conn).context(SetupError)?;
+        let mut window = Window::create(conn, screen, config, &keymap).context(SetupError)?;
+        #[cfg(feature = "compose")]
+        let mut compose = Compose::from_locale(config.dead_keys);
+        let mut ui = Ui::new(conn, &window, config, background_image)?;
+        if let Some(server_grab) = server_grab {
+            ui.show_background()?;
+            server_grab.release();
+        }
+        #[cfg(feature = "xinput2")]
+        let mut pin_device = config
+            .pin_device
+            .as_deref()
+            .and_then(|device| PinDevice::select(conn, screen.root, device));
+
+        if !blank {
+            audit::record(audit::Event::Locked);
+        }
+
+        #[cfg(feature = "dbus")]
+        let paused_media = (config.pause_media_on_lock && !blank).then(mpris::pause);
+
+        let mut led_flash = config.led_on_failure.map(|led| LedFlash::new(conn, led));
+        let mut sounds = Sounds::new(conn, config);
+
+        let one_time_pin = config
+            .otp_command
+            .as_deref()
+            .filter(|_| !blank)
+            .map(|command| OneTimePin::new(command, config.otp_window(), config.auth_timeout()));
+        let auth = Authenticator::from_config(config).with_one_time_pin(one_time_pin);
+
+        // Kept until unlocking, switching comes back when dropped
+        #[cfg(feature = "vtlock")]
+        let vt_lock = (config.lock_vt_switch && !blank)
+            .then(|| VtLock::acquire().map_err(|err| warn!("{err:#}")).ok())
+            .flatten();
+
+        let mut idle = Idle::from_config(config);
+
+        let mut state = if blank {
+            LockState::blank(config)
+        } else {
+            LockState::new(config, &Persisted::load())
+        };
+        state.set_leds(led::follow(conn)?);
+        let repeat_off = (config.key_repeat == KeyRepeat::Off)
+            .then(|| RepeatOff::new(conn))
+            .transpose()?;
+        let mut repeat_filter = RepeatFilter::default();
+        if !config.hold_to_submit().is_zero() && !input::detectable_auto_repeat(conn)? {
+            warn!("The X server repeats keys with releases, holding Enter past the repeat delay starts hold_to_submit_ms over");
+        }
+        let mut published = config
+            .publish_state
+            .then(|| PublishedState::new(conn, screen.root))
+            .transpose()?;
+        let mut first_frame = FirstFrame::new(config.first_frame_wait());
+        let mut frame_cap = FrameCap::new(config.max_fps);
+
+        let grab_check = config.grab_check();
+        let mut next_grab_check = grab_check.map(|interval| Instant::now() + interval);
+
+        let mut next_tick = (!widgets.is_empty()).then(|| Instant::now() + widget::TICK_INTERVAL);
+
+        let mut reloaded = None;
+
+        // Released before waiting for the next event, once the last one was processed
+        let mut keyboard_frozen = false;
+
+        // When the PIN field is redrawn for the keys typed since it last was
+        let mut input_redraw: Option<Instant> = None;
+
+        // Only unlock requests made while locked count
+        #[cfg(feature = "logind")]
+        signals.take_unlock();
+        signals.take_unlock_signal();
+
+        let reason = loop {
+            if signals.terminate() {
+                break UnlockReason::Signal;
+            }
+            #[cfg(feature = "logind")]
+            if signals.take_unlock() {
+                if config.logind_unlock {
+                    break UnlockReason::Requested;
+                }
+                info!("Ignoring the unlock request from logind, logind_unlock is off");
+            }
+            if signals.take_unlock_signal() {
+                if config.signal_unlock {
+                    break UnlockReason::SignalUnlock;
+                }
+                info!("Ignoring SIGUSR2, signal_unlock is off");
+            }
+
+            if signals.take_reload() {
+                if let Some(config) = reload_config(config_path) {
+                    ui.apply_config(&config)?;
+                    led_flash = config.led_on_failure.map(|led| LedFlash::new(conn, led));
+                    reloaded = Some(config);
+                    ui.draw(&state, widgets)?;
+                }
+            }
+
+            if keyboard_frozen {
+                window.thaw_keyboard()?;
+                keyboard_frozen = false;
+            }
+
+            if let Some(published) = &mut published {
+                warn_on_error(published.update(&state), "publish the lock state");
+            }
+
+            // Redrawn below on timeout for the next frame, or to settle
+            let animating = state.shake_frame().is_some()
+                || state.entrance_frame().is_some()
+                || state.submit_hold_frame().is_some();
+
+            // While in grace, wake up when it ends to swap in the PIN field
+            let timeout = [
+                first_frame.remaining(),
+                state.grace_remaining(),
+                state.lockout_tick(),
+                state.shake_frame(),
+                state.entrance_frame(),
+                state.submit_hold_frame(),
+                state.verification_poll(),
+                state.message_remaining(),
+                ui.dim_remaining(&state),
+                led_flash.as_ref().and_then(LedFlash::remaining),
+                idle.as_ref().map(Idle::remaining),
+                next_grab_check.map(|at| at.saturating_duration_since(Instant::now())),
+                ui.background_poll(),
+                next_tick.map(|at| at.saturating_duration_since(Instant::now())),
+                input_redraw.map(|at| at.saturating_duration_since(Instant::now())),
+                #[cfg(feature = "replay")]
+                replay.as_ref().and_then(Replay::remaining),
+            ]
+            .into_iter()
+            .flatten()
+            .min();
+            let timeout = frame_cap.timeout(timeout, Instant::now());
+            #[cfg(feature = "replay")]
+            let event = match &mut replay {
+                Some(replay) => match replay.next(&keymap, window.id, screen.root)? {
+                    Some(event) => Some(event),
+                    None => wait_for_event(conn, timeout)?,
+                },
+                None => wait_for_event(conn, timeout)?,
+            };
+            #[cfg(not(feature = "replay"))]
+            let event = wait_for_event(conn, timeout)?;
+            match &event {
+                Some(event) => keyboard_frozen = window.keyboard_mode.freezes_after(event),
+                None => frame_cap.timed_out(Instant::now()),
+            }
+
+            if let Some(led_flash) = &mut led_flash {
+                warn_on_error(led_flash.update(), "restore the failure LED");
+            }
+
+            if first_frame.timed_out() {
+                ui.draw(&state, widgets)?;
+            }
+
+            if state.expire_message() {
+                ui.draw_areas(&state, widgets, &ui.input_areas())?;
+            }
+
+            if state.hold_submits() {
+                submit(&mut state, LockState::on_submit, &auth, &mut sounds);
+                ui.draw_areas(&state, widgets, &ui.input_areas())?;
+            }
+
+            if let Some(reason) = state.take_verified() {
+                if let Some(reason) =
+                    verified(&state, reason, config, led_flash.as_mut(), &mut sounds)
+                {
+                    break reason;
+                }
+                ui.draw_areas(&state, widgets, &ui.input_areas())?;
+            }
+
+            // Redrawn below on timeout, if not on the event
+            let mut ticked = false;
+            if let Some(at) = next_tick.filter(|&at| at <= Instant::now()) {
+                for (_, widget) in widgets.iter_mut() {
+                    ticked |= widget.tick();
+                }
+                next_tick = Some(at + widget::TICK_INTERVAL);
+            }
+
+            if ui.update_background()? {
+                ui.draw(&state, widgets)?;
+            }
+
+            if let (Some(interval), Some(at)) = (grab_check, next_grab_check) {
+                if at <= Instant::now() {
+                    // Giving up would leave the screen unlocked, retry on the
+                    // next check instead. The grabs still held are kept.
+                    if let Err(err) = window.check_grabs() {
+                        error!("Failed to grab the input again: {err:#}");
+                    }
+                    next_grab_check = Some(Instant::now() + interval);
+                }
+            }
+
+            // The grace period takes precedence, so that it can still be
+            // ended by touching the mouse
+            let screensaver = idle
+                .as_mut()
+                .filter(|idle| idle.is_active() && !state.in_grace());
+
+            // Also flushed while events keep coming in
+            let input_due = input_redraw.is_some_and(|at| at <= Instant::now());
+            if input_due {
+                input_redraw = None;
+            }
+
+            let Some(event) = event else {
+                match screensaver {
+                    Some(idle) => ui.draw_screensaver(idle)?,
+                    None if animating || ticked || input_due => {
+                        let mut areas = ui.widget_areas(widgets);
+                        if animating || input_due {
+                            areas.extend(ui.input_areas());
+                        }
+                        ui.draw_areas(&state, widgets, &areas)?
+                    }
+                    // Such as the grace period or a lockout ending, while
+                    // timeouts for the LED flash or the grab check draw
+                    // nothing
+                    None if ui.is_stale(&state) => ui.draw(&state, widgets)?,
+                    None => {}
+                }
+                continue;
+            };
+            if input_due {
+                ui.draw_areas(&state, widgets, &ui.input_areas())?;
+            }
+
+            // The keycodes go to the private event log only
+            trace!("{}", event_log::describe_without_keys(&event));
+            if let Some(event_log) = &mut event_log {
+                event_log.record(&event);
+            }
+            let repeated = config.key_repeat == KeyRepeat::Filter
+                && repeat_filter.is_repeat(&event, |keycode| state.is_held(keycode));
+
+            match event {
+                // Redrawn once the last of a series of exposures arrived
+                Event::Expose(event) if first_frame.on_expose(event.count) => match screensaver {
+                    Some(idle) => ui.draw_screensaver(idle)?,
+                    None => ui.draw(&state, widgets)?,
+                },
+                // Moves and restacking leave the size alone
+                Event::ConfigureNotify(event)
+                    if event.window == window.id
+                        && (event.width, event.height) != (window.width, window.height) =>
+                {
+                    (window.width, window.height) = (event.width, event.height);
+                    ui.resize(&window, reloaded.as_ref().unwrap_or(config))?;
+                    ui.draw(&state, widgets)?;
+                }
+                Event::ButtonPress(event) => {
+                    // Only the primary button, which is also what a touch becomes
+                    if event.detail != 1 || screensaver.is_some() {
+                        continue;
+                    }
+                    if let Some(dot) = ui.pattern_dot_at(&state, event.event_x, event.event_y) {
+                        if state.on_pattern_tap(dot) {
+                            submit(&mut state, LockState::on_pattern_submit, &auth, &mut sounds);
+                        } else {
+                            warn_on_error(sounds.key(), "play the key sound");
+                        }
+                        ui.draw(&state, widgets)?;
+                        continue;
+                    }
+                    match ui.button_at(&state, event.event_x, event.event_y) {
+                        Some(Button::Submit) => {
+                            submit(&mut state, LockState::on_submit, &auth, &mut sounds);
+                        }
+                        Some(Button::Clear) => {
+                            state.on_clear();
+                            warn_on_error(sounds.key(), "play the key sound");
+                        }
+                        Some(Button::Digit(c)) => {
+                            state.on_char(c);
+                            warn_on_error(sounds.key(), "play the key sound");
+                        }
+                        None => continue,
+                    }
+                    ui.draw(&state, widgets)?;
+                }
+                // Taking the pointer grab again, as the grab check does, is
+                // no activity
+                Event::EnterNotify(event) if event.mode != NotifyMode::NORMAL => {}
+                // Activity, but never an unlock attempt. Entering happens by
+                // itself when the window is mapped below the pointer, so
+                // only motion ends the grace period.
+                Event::MotionNotify(_) | Event::EnterNotify(_) => {
+                    if matches!(event, Event::MotionNotify(_)) && state.in_grace() && !blank {
+                        break UnlockReason::Grace;
+                    }
+                    if state.on_activity() {
+                        warn_on_error(dpms::force_on(conn), "turn the display on");
+                    }
+                    if idle.as_mut().is_some_and(Idle::wake) || ui.is_dimmed() {
+                        ui.draw(&state, widgets)?;
+                    }
+                }
+                Event::LeaveNotify(_) => {}
+                // Focus moving with our own grabs taken or released is expected
+                Event::FocusOut(event)
+                    if event.event == window.id
+                        && config.clear_on_focus_change
+                        && state.input_len() > 0
+                        && !matches!(event.mode, NotifyMode::GRAB | NotifyMode::UNGRAB) =>
+                {
+                    warn!("The lock window lost the input focus, clearing the PIN typed so far");
+                    state.on_clear();
+                    ui.draw_areas(&state, widgets, &ui.input_areas())?;
+                }
+                Event::KeymapNotify(event) => {
+                    // Sync with modifiers that were already held when grabbing
+                    let locked = conn.query_pointer(window.id)?.reply()?.mask;
+                    state.set_modifiers(keymap.modifiers(&event.keys, locked));
+                    ui.draw(&state, widgets)?;
+                }
+                Event::KeyPress(event) => {
+                    #[cfg(feature = "xinput2")]
+                    let from_pin_device = pin_device
+                        .as_mut()
+                        .is_none_or(|pin_device| pin_device.take(event.detail));
+                    #[cfg(not(feature = "xinput2"))]
+                    let from_pin_device = true;
+                    state.on_key_press(event.detail);
+                    let keysym = keymap.keysym(event.detail, event.state);
+                    if !config.modifier_activity && input::is_modifier(keysym) {
+                        state.set_modifiers(event.state);
+                        continue;
+                    }
+                    if state.on_activity() {
+                        warn_on_error(dpms::force_on(conn), "turn the display on");
+                    }
+                    if state.ignores_input() {
+                        state.set_modifiers(event.state);
+                        continue;
+                    }
+                    if state.in_grace() {
+                        break UnlockReason::Grace;
+                    }
+                    state.set_modifiers(event.state);
+
+                    // The key that stops the screensaver only brings back the
+                    // PIN field
+                    if idle.as_mut().is_some_and(Idle::wake) {
+                        ui.draw(&state, widgets)?;
+                        continue;
+                    }
+                    // Other keyboards only wake the screen, and filtered
+                    // repeats type nothing
+                    if !from_pin_device || repeated {
+                        continue;
+                    }
+
+                    #[cfg(feature = "compose")]
+                    let action = match &mut compose {
+                        Some(compose) => compose.action(keysym),
+                        None => InputAction::from_keysym(keysym),
+                    };
+                    #[cfg(not(feature = "compose"))]
+                    let action = InputAction::from_keysym(keysym);
+                    let action = match keymap.digit_from_keycode(event.detail) {
+                        Some(digit) if config.input_filter == InputFilter::DigitsOnly => {
+                            InputAction::Char(digit)
+                        }
+                        _ => action,
+                    };
+
+                    if matches!(
+                        action,
+                        InputAction::Char(_) | InputAction::Backspace | InputAction::Clear
+                    ) {
+                        warn_on_error(sounds.key(), "play the key sound");
+                    }
+                    match action {
+                        InputAction::Char(c) => state.on_char(c),
+                        InputAction::Backspace => state.on_backspace(),
+                        InputAction::Clear => state.on_clear(),
+                        InputAction::Submit if !state.on_submit_key(event.detail) => {}
+                        InputAction::Submit => {
+                            submit(&mut state, LockState::on_submit, &auth, &mut sounds);
+                        }
+                        InputAction::Modifier | InputAction::Ignore => continue,
+                    }
+                    // What submitting started is shown right away
+                    let coalesce = config.input_redraw();
+                    if action == InputAction::Submit || coalesce.is_zero() {
+                        input_redraw = None;
+                        ui.draw_areas(&state, widgets, &ui.input_areas())?;
+                    } else {
+                        input_redraw.get_or_insert_with(|| Instant::now() + coalesce);
+                    }
+                }
+                Event::XkbIndicatorStateNotify(event) => {
+                    let leds = match &led_flash {
+                        Some(led_flash) => led_flash.unflashed(event.state),
+                        None => event.state,
+                    };
+                    state.set_leds(leds);
+                    ui.draw_areas(&state, widgets, &ui.input_areas())?;
+                }
+                #[cfg(feature = "xinput2")]
+                Event::XinputRawKeyPress(event) => {
+                    if let Some(pin_device) = &mut pin_device {
+                        pin_device.on_raw_press(event.deviceid, event.detail);
+                    }
+                }
+                Event::KeyRelease(event) => {
+                    let held = state.submit_hold().is_some();
+                    state.on_key_release(event.detail);
+                    if held && state.submit_hold().is_none() {
+                        ui.draw_areas(&state, widgets, &ui.input_areas())?;
+                    }
+                    if event.state != state.modifiers() {
+                        state.set_modifiers(event.state);
+                        ui.draw_areas(&state, widgets, &ui.input_areas())?;
+                    }
+                }
+                // Events the lock screen doesn't act on
+                _ => {}
+            }
+        };
+
+        let pin_unlock = matches!(
+            reason,
+            UnlockReason::Authenticated | UnlockReason::Duress { .. }
+        );
+        if pin_unlock && config.unlock_flash {
+            ui.draw_unlocked()?;
+            // Still grabbed meanwhile, input is dropped
+            let until = Instant::now() + UNLOCK_FLASH_DURATION;
+            while let Some(remaining) = until
+                .checked_duration_since(Instant::now())
+                .filter(|remaining| !remaining.is_zero())
+            {
+                wait_for_event(conn, Some(remaining))?;
+            }
+        }
+
+        if !blank {
+            audit::record(audit::Event::Unlocked {
+                reason: &reason,
+                failed_attempts: state.failed_attempts(),
+            });
+        }
+        #[cfg(feature = "metrics")]
+        metrics::log_summary(&ui.frame_times(), state.auth_times());
+
+        // Take the lock down in a fixed order, each step going ahead even if
+        // the one before failed. Leaving early on an error drops these in
+        // reverse order of declaration instead, the window still last, and
+        // leaves the display as it is.
+        if let Err(err) = dpms::force_on(conn) {
+            warn!("Failed to turn the display on: {err:#}");
+        }
+        #[cfg(feature = "vtlock")]
+        drop(vt_lock);
+        // Restores the LED if it is flashing
+        drop(led_flash);
+        drop(published);
+        drop(repeat_off);
+        window.cleanup();
+
+        // Also after a duress PIN, which has to look like any other unlock
+        if let Some(command) = config.unlock_command.as_deref().filter(|_| pin_unlock) {
+            if let Err(err) = Command::new("sh").arg("-c").arg(command).spawn() {
+                error!("Failed to run the unlock command: {err}");
+            }
+        }
+
+        #[cfg(feature = "dbus")]
+        if let Some(paused_media) = paused_media {
+            if config.resume_media_on_unlock {
+                paused_media.resume();
+            }
+        }
+
+        if let Some(config) = reloaded {
+            self.widgets = widget::from_config(&config);
+            self.config = config;
+        }
+
+        Ok(reason)
+    }
+}
+
+/// Start checking the entered PIN, or the unlock pattern, with `check`,
+/// see [`verified`] for the outcome.
+fn submit(
+    state: &mut LockState,
+    check: fn(&mut LockState, &Authenticator),
+    auth: &Authenticator,
+    sounds: &mut Sounds,
+) {
+    warn_on_error(sounds.submit(), "play the submit sound");
+    check(state, auth);
+}
+
+/// Act on the outcome of a checked PIN or pattern, `reason`, returning it.
+/// A wrong one is recorded, runs the `on_failure` command for the new count
+/// and the panic wipe if it is due, and is signalled with the failure LED.
+/// Failing sounds or LEDs are only logged, they must not end the lock.
+fn verified(
+    state: &LockState,
+    reason: Option<UnlockReason>,
+    config: &Config,
+    led_flash: Option<&mut LedFlash>,
+    sounds: &mut Sounds,
+) -> Option<UnlockReason> {
+    state.persisted().save();
+    if reason.is_some() {
+        return reason;
+    }
+
+    let failed_attempts = state.failed_attempts();
+    audit::record(audit::Event::FailedAttempt { failed_attempts });
+    warn_on_error(sounds.failure(), "play the failure sound");
+    if let Some(command) = config.on_failure.get(&FailureCount(failed_attempts)) {
+        if let Err(err) = Command::new("sh").arg("-c").arg(command).spawn() {
+            error!("Failed to run the on_failure command for {failed_attempts} attempts: {err}");
+        }
+    }
+    panic_wipe(config, failed_attempts);
+    #[cfg(feature = "camera")]
+    if config.capture_after == Some(failed_attempts) {
+        camera::capture(config);
+    }
+    if let Some(led_flash) = led_flash {
+        warn_on_error(led_flash.flash(), "flash the failure LED");
+    }
+    None
+}
+
+/// Log the failure to `what`, for feedback such as sounds and LEDs that the
+/// lock goes on without. Only losing the lock itself ends it.
+fn warn_on_error(result: Result<()>, what: &str) {
+    if let Err(err) = result {
+        warn!("Failed to {what}: {err:#}");
+    }
+}
+
+/// Start `panic_wipe_command` if the panic wipe is armed and `failed_attempts`
+/// reached its count. It runs detached, in a process group of its own, so
+/// that it isn't ended along with pinlock.
+pub fn panic_wipe(config: &Config, failed_attempts: u32) {
+    let Some((after, command)) = config.panic_wipe() else {
+        return;
+    };
+    if failed_attempts != after {
+        return;
+    }
+    error!("{failed_attempts} failed attempts, running the panic wipe");
+    audit::record(audit::Event::PanicWipe { failed_attempts });
+    let started = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .process_group(0)
+        .spawn();
+    if let Err(err) = started {
+        error!("Failed to run panic_wipe_command: {err}");
+    }
+}
+
+/// Connect to `display`, or to the one in `$DISPLAY` if not given.
+pub fn connect(display: Option<&str>) -> Result<(RustConnection, usize)> {
+    if display.is_none() && !has_display_env() {
+        bail!("$DISPLAY is not set and no --display given, can't connect to the X server");
+    }
+    x11rb::connect(display).with_context(|| match display {
+        Some(display) => format!("Failed to connect to the X server on {display}"),
+        None => "Failed to connect to the X server on $DISPLAY".to_owned(),
+    })
+}
+
+/// Connect to `display` only to print which of the extensions pinlock uses
+/// the X server has.
+pub fn probe(display: Option<&str>) -> Result<()> {
+    let (conn, _) = connect(display)?;
+    print_extensions(&conn)
+}
+
+fn print_extensions(conn: &RustConnection) -> Result<()> {
+    for (name, extension) in [
+        ("DPMS", x11rb::protocol::dpms::X11_EXTENSION_NAME),
+        ("MIT-SCREEN-SAVER", screensaver::X11_EXTENSION_NAME),
+        ("RandR", randr::X11_EXTENSION_NAME),
+        ("RENDER", render::X11_EXTENSION_NAME),
+        ("SHAPE", shape::X11_EXTENSION_NAME),
+        ("XKB", "XKEYBOARD"),
+    ] {
+        if conn.extension_information(extension)?.is_none() {
+            println!("{name}: missing");
+        } else if extension == randr::X11_EXTENSION_NAME {
+            // Monitors need 1.5, older servers fall back to the whole screen
+            let version = randr::query_version(conn, 1, 5)?.reply()?;
+            println!(
+                "{name}: available, version {}.{}",
+                version.major_version, version.minor_version
+            );
+        } else {
+            println!("{name}: available");
+        }
+    }
+    Ok(())
+}
+
+/// Whether `$DISPLAY` names a display to connect to.
+pub fn has_display_env() -> bool {
+    env::var_os("DISPLAY").is_some_and(|display| !display.is_empty())
+}
+
+/// Check that `display` looks like `[host]:number[.screen]`, e.g. `:1` or
+/// `localhost:10.0`, before trying to connect to it.
+pub fn parse_display(display: &str) -> Result<String, String> {
+    let valid = display.rsplit_once(':').is_some_and(|(_, rest)| {
+        let (number, screen) = rest.split_once('.').unwrap_or((rest, "0"));
+        [number, screen]
+            .iter()
+            .all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()))
+    });
+    if valid {
+        Ok(display.to_owned())
+    } else {
+        Err(format!(
+            "`{display}` is not an X display, which looks like `:1` or `host:0.0`"
+        ))
+    }
+}
+
+/// Load the configuration anew, logging the outcome. On failure the caller
+/// keeps using the current configuration.
+fn reload_config(path: Option<&Path>) -> Option<Config> {
+    if path == Some(Path::new("-")) {
+        error!("The configuration was read from stdin and can't be reloaded");
+        return None;
+    }
+
+    match Config::load(path) {
+        Ok(config) => {
+            info!("Reloaded the configuration");
+            Some(config)
+        }
+        Err(err) => {
+            error!("Failed to reload the configuration, keeping the current one: {err:#}");
+            None
+        }
+    }
+}
+
+/// Holds the first frame back until the server is done exposing the newly
+/// mapped lock window, so that it is drawn once for the whole batch of
+/// Expose events rather than also before it.
+struct FirstFrame {
+    /// When it is drawn without waiting any longer, `None` once it was
+    deadline: Option<Instant>,
+}
+
+impl FirstFrame {
+    fn new(wait: Duration) -> Self {
+        Self {
+            deadline: Some(Instant::now() + wait),
+        }
+    }
+
+    fn remaining(&self) -> Option<Duration> {
+        self.deadline
+            .map(|at| at.saturating_duration_since(Instant::now()))
+    }
+
+    /// Whether an Expose event followed by `count` more calls for a frame,
+    /// as the last of a batch does.
+    fn on_expose(&mut self, count: u16) -> bool {
+        if count > 0 {
+            return false;
+        }
+        self.deadline = None;
+        true
+    }
+
+    /// Whether the exposures took too long to wait for any more, so that
+    /// the first frame is drawn without them. Only ever once.
+    fn timed_out(&mut self) -> bool {
+        let timed_out = self.deadline.is_some_and(|at| at <= Instant::now());
+        if timed_out {
+            self.deadline = None;
+        }
+        timed_out
+    }
+}
+
+/// Keeps the lock screen from waking up by itself, as it does to draw the
+/// next frame of an animation, more than `max_fps` times a second.
+struct FrameCap {
+    interval: Duration,
+    /// When a wait for events last timed out
+    last_timeout: Option<Instant>,
+}
+
+impl FrameCap {
+    fn new(max_fps: u32) -> Self {
+        Self {
+            interval: Duration::from_secs(1) / max_fps.max(1),
+            last_timeout: None,
+        }
+    }
+
+    /// How long to wait for events at `now`, when the soonest of what is
+    /// timed is due in `soonest`. Without anything timed, events are waited
+    /// for without a timeout.
+    fn timeout(&self, soonest: Option<Duration>, now: Instant) -> Option<Duration> {
+        let soonest = soonest?;
+        let held_off = self.last_timeout.map_or(Duration::ZERO, |at| {
+            (at + self.interval).saturating_duration_since(now)
+        });
+        Some(soonest.max(held_off))
+    }
+
+    fn timed_out(&mut self, now: Instant) {
+        self.last_timeout = Some(now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn displays_need_a_number_and_may_have_a_host_and_screen() {
+        for display in [":0", ":1", ":10.1", "localhost:10.0", "unix:2"] {
+            assert_eq!(parse_display(display).as_deref(), Ok(display));
+        }
+        for display in ["", "1", ":", ":a", ":1.", ":1.x", "host"] {
+            assert!(parse_display(display).is_err(), "{display}");
+        }
+    }
+
+    #[test]
+    fn a_batch_of_exposures_draws_the_first_frame_once() {
+        let mut first_frame = FirstFrame::new(Duration::from_secs(60));
+        let frames = [3, 2, 1, 0]
+            .into_iter()
+            .filter(|&count| first_frame.on_expose(count))
+            .count();
+        assert_eq!(frames, 1);
+        assert!(!first_frame.timed_out());
+        assert_eq!(first_frame.remaining(), None);
+
+        let mut first_frame = FirstFrame::new(Duration::ZERO);
+        assert!(first_frame.timed_out());
+        assert!(!first_frame.timed_out());
+    }
+
+    #[test]
+    fn timed_wake_ups_are_spaced_by_the_frame_cap() {
+        let mut frame_cap = FrameCap::new(10);
+        let start = Instant::now();
+        assert_eq!(
+            frame_cap.timeout(Some(Duration::ZERO), start),
+            Some(Duration::ZERO)
+        );
+
+        frame_cap.timed_out(start);
+        let later = start + Duration::from_millis(20);
+        assert_eq!(
+            frame_cap.timeout(Some(Duration::ZERO), later),
+            Some(Duration::from_millis(80))
+        );
+        assert_eq!(
+            frame_cap.timeout(Some(Duration::from_secs(1)), later),
+            Some(Duration::from_secs(1))
+        );
+        // Nothing to animate, nothing to wake up for
+        assert_eq!(frame_cap.timeout(None, later), None);
+    }
+}
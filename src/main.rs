@@ -1,183 +1,407 @@
-use anyhow::Result;
-use x11rb::{
-    connection::Connection,
-    protocol::{
-        xproto::{
-            ConnectionExt, CreateWindowAux, EventMask, GrabMode, InputFocus, Screen, WindowClass,
-        },
-        Event,
-    },
-    rust_connection::RustConnection,
-    COPY_DEPTH_FROM_PARENT, CURRENT_TIME, NONE,
+mod audit;
+mod auth;
+mod backend;
+mod buffer;
+#[cfg(feature = "camera")]
+mod camera;
+mod config;
+mod console;
+mod cursor;
+#[cfg(feature = "dbus")]
+mod dbus;
+mod dpms;
+mod error;
+mod event_log;
+mod image;
+mod indicator;
+mod input;
+mod instance;
+mod keypad;
+mod led;
+mod locker;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod persist;
+mod preview;
+mod publish;
+mod random;
+#[cfg(feature = "replay")]
+mod replay;
+mod screensaver;
+mod signals;
+mod sound;
+mod state;
+mod sysinfo;
+mod theme;
+mod ui;
+mod visual;
+#[cfg(feature = "vtlock")]
+mod vt;
+mod widget;
+mod window;
+
+use std::{
+    os::unix::process::CommandExt,
+    path::PathBuf,
+    process::{self, ExitCode},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use log::{error, info, warn};
+
+use crate::{
+    config::Config,
+    error::{AlreadyRunning, ConfigError, GrabError, NotLocked, SetupError},
+    instance::InstanceLock,
+    locker::Locker,
+    signals::Signals,
+    state::UnlockReason,
 };
 
-struct Window<'connection> {
-    id: u32,
-    conn: &'connection RustConnection,
+/// Optional Cargo features, as reported by `--version`.
+const FEATURES: &[(&str, bool)] = &[
+    ("audio", cfg!(feature = "audio")),
+    ("camera", cfg!(feature = "camera")),
+    ("compose", cfg!(feature = "compose")),
+    ("dbus", cfg!(feature = "dbus")),
+    ("journald", cfg!(feature = "journald")),
+    ("logind", cfg!(feature = "logind")),
+    ("metrics", cfg!(feature = "metrics")),
+    ("replay", cfg!(feature = "replay")),
+    ("vtlock", cfg!(feature = "vtlock")),
+    ("xinput2", cfg!(feature = "xinput2")),
+];
+
+/// An X11 screen locker.
+#[derive(Parser)]
+#[command(disable_version_flag = true)]
+struct Args {
+    /// Print the version and the enabled features
+    #[arg(short = 'V', long)]
+    version: bool,
+
+    /// Read the configuration from this file instead of
+    /// $XDG_CONFIG_HOME/pinlock/config.toml, `-` reads it from stdin
+    #[arg(short, long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// If pinlock is already running on this display, make it lock the
+    /// screen instead of refusing to start
+    #[arg(short, long)]
+    force: bool,
+
+    /// The X display to lock, such as `:1`, instead of $DISPLAY or the
+    /// configured one
+    #[arg(short, long, value_parser = locker::parse_display)]
+    display: Option<String>,
+
+    /// Lock the terminal instead should there be no X server to lock,
+    /// asking for the PIN on it like `vlock`
+    #[arg(long)]
+    allow_console: bool,
+
+    /// Append every X event the lock screen receives to this file, with
+    /// keys as keycodes only, never what they type. The file must be
+    /// private to the user, it is created that way
+    #[arg(long, value_name = "PATH")]
+    debug_events: Option<PathBuf>,
+
+    /// Type the keys of this script into the lock screen, for end-to-end
+    /// tests
+    #[cfg(feature = "replay")]
+    #[arg(long, value_name = "PATH")]
+    replay: Option<PathBuf>,
+
+    /// Render the lock screen into this PNG file instead of locking, to
+    /// preview the configuration without a display
+    #[arg(long, value_name = "PATH")]
+    print_layout: Option<PathBuf>,
+
+    /// The size of the `--print-layout` image
+    #[arg(
+        long,
+        value_name = "WIDTHxHEIGHT",
+        default_value = "1920x1080",
+        value_parser = preview::parse_size
+    )]
+    layout_size: (u16, u16),
+
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-impl<'connection> Window<'connection> {
-    fn create(connection: &'connection RustConnection, screen: &Screen) -> Result<Self> {
-        let win = connection.generate_id()?;
-
-        let settings = CreateWindowAux::default()
-            .override_redirect(1)
-            .background_pixel(31)
-            .event_mask(
-                EventMask::EXPOSURE
-                    | EventMask::BUTTON_PRESS
-                    | EventMask::BUTTON_RELEASE
-                    | EventMask::POINTER_MOTION
-                    | EventMask::ENTER_WINDOW
-                    | EventMask::LEAVE_WINDOW
-                    | EventMask::KEY_PRESS
-                    | EventMask::KEY_RELEASE,
-            );
-
-        // Create the window
-        connection.create_window(
-            COPY_DEPTH_FROM_PARENT,    // depth (same as root)
-            win,                       // window Id
-            screen.root,               // parent window
-            455,                       // x
-            140,                       // y
-            1000,                      // width
-            800,                       // height
-            0,                         // border width
-            WindowClass::INPUT_OUTPUT, // class
-            screen.root_visual,        // visual
-            &settings,
-        )?; // masks, not used yet
-
-        // Map the window on the screen
-        connection.map_window(win)?;
-
-        connection.flush()?;
-
-        connection.set_input_focus(InputFocus::PARENT, win, CURRENT_TIME)?;
-        connection.grab_keyboard(
-            true,
-            win, //screen.root,
-            CURRENT_TIME,
-            GrabMode::ASYNC,
-            GrabMode::ASYNC,
-        )?;
-
-        // let font = connection.generate_id()?;
-        // connection.open_font(font, b"cursor")?;
-
-        // let cursor = connection.generate_id()?;
-        // connection.create_glyph_cursor(cursor, font, font, 58, 58 + 1, 0, 0, 0, 0, 0, 0)?;
-
-        connection.grab_pointer(
-            true,
-            win, //screen.root,
-            EventMask::NO_EVENT,
-            GrabMode::ASYNC,
-            GrabMode::ASYNC,
-            win,
-            NONE,
-            CURRENT_TIME,
-        )?;
-
-        connection.flush()?;
-
-        Ok(Self {
-            id: win,
-            conn: connection,
-        })
-    }
+#[derive(Subcommand)]
+enum Command {
+    /// Lock the screen once and exit when it is unlocked (the default)
+    Lock,
+    /// Cover the screen WITHOUT locking it, any key shows it again. No PIN
+    /// is asked for, this only hides the screen from onlookers
+    Blank,
+    /// Stay resident, locking the screen whenever the session goes idle or
+    /// on SIGUSR1
+    Daemon,
+    /// Print the enabled features and which of the X extensions pinlock uses
+    /// the display has
+    Features,
+    /// Check that locking works on this display: lock without asking for the
+    /// PIN, unlock again after a moment and report how it went
+    Selftest {
+        /// How long to stay locked, in milliseconds
+        #[arg(long, value_name = "MS", default_value_t = 1000)]
+        hold: u64,
+    },
 }
 
-impl<'connection> Drop for Window<'connection> {
-    fn drop(&mut self) {
-        self.conn
-            .ungrab_keyboard(CURRENT_TIME)
-            .expect("Failed to ungrab the keyboard")
-            .check()
-            .expect("Keyboard ungrab caused error");
-        self.conn
-            .ungrab_pointer(CURRENT_TIME)
-            .expect("Failed to ungrab the pointer")
-            .check()
-            .expect("Pointer ungrab caused error");
-        self.conn.flush().expect("Failed to send clean up commands");
+fn print_features(display: Option<&str>) -> ExitCode {
+    print_version();
+    match locker::probe(display) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {err:?}");
+            ExitCode::FAILURE
+        }
     }
 }
 
-fn main() -> Result<()> {
-    // Open the connection to the X server. Use the DISPLAY environment variable.
-    let (conn, screen_num) = x11rb::connect(None)?;
+fn print_version() {
+    let features: Vec<&str> = FEATURES
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| *name)
+        .collect();
+
+    println!("pinlock {}", env!("CARGO_PKG_VERSION"));
+    if features.is_empty() {
+        println!("features: none");
+    } else {
+        println!("features: {}", features.join(", "));
+    }
+}
 
-    // Get the screen #screen_num
-    let screen = &conn.setup().roots[screen_num];
+/// Lock the screen every time the user has been idle for long enough, or
+/// the session was unlocked for `max_session_secs`.
+fn daemon(locker: &mut Locker, signals: &Signals) -> Result<UnlockReason> {
+    #[cfg(feature = "dbus")]
+    let _bus = dbus::serve(&signals.requests(), locker.config().lock_on_lid_close)
+        .map_err(|err| error!("Not accepting lock requests from the desktop: {err:#}"))
+        .ok();
 
-    let _window = Window::create(&conn, screen)?;
+    // Starting the daemon counts as unlocking
+    let mut unlocked_at = Instant::now();
 
     loop {
-        let event = conn.wait_for_event()?;
-        match event {
-            Event::Expose(event) => {
-                println!(
-                    "Window {} exposed. Region to be redrawn at location ({},{}) with dimensions \
-                     ({},{})",
-                    event.window, event.x, event.y, event.width, event.height
-                );
+        if signals.terminate() {
+            return Ok(UnlockReason::Signal);
+        }
+        locker.reload_if_requested(signals);
+
+        #[cfg_attr(not(feature = "logind"), allow(unused_mut))]
+        let mut requested = signals.take_lock();
+        #[cfg(feature = "logind")]
+        if signals.take_lid_closed() && locker.config().lock_on_lid_close {
+            if locker.is_docked() {
+                info!("The lid was closed while docked, not locking");
+            } else {
+                info!("The lid was closed, locking");
+                requested = true;
             }
-            Event::ButtonPress(event) => {
-                println!("{:#?}", event.state);
-                match event.detail {
-                    1 => break Ok(()),
-                    4 => println!(
-                        "Wheel Button up in window {}, at coordinates ({},{})",
-                        event.event, event.event_x, event.event_y
-                    ),
-                    5 => println!(
-                        "Wheel Button down in window {}, at coordinates ({},{})",
-                        event.event, event.event_x, event.event_y
-                    ),
-                    _ => println!(
-                        "Button {} pressed in window {}, at coordinates ({},{})",
-                        event.detail, event.event, event.event_x, event.event_y
-                    ),
-                }
+        }
+
+        let idle_timeout = locker.config().idle();
+        let session_left = locker
+            .config()
+            .max_session()
+            .map(|max| max.saturating_sub(unlocked_at.elapsed()));
+
+        let idle = locker.idle_time()?;
+        let session_over = session_left.is_some_and(|left| left.is_zero());
+        if !requested && !session_over && idle < idle_timeout {
+            let wait =
+                session_left.map_or(idle_timeout - idle, |left| left.min(idle_timeout - idle));
+            // Sleep in short steps to notice termination requests
+            thread::sleep(wait.min(Duration::from_secs(1)));
+            continue;
+        }
+        if session_over {
+            info!("The session was unlocked for max_session_secs, locking");
+        }
+
+        match locker.lock(signals)? {
+            UnlockReason::Signal => return Ok(UnlockReason::Signal),
+            UnlockReason::Duress { command } => run_duress_command(&command),
+            UnlockReason::Authenticated | UnlockReason::Grace | UnlockReason::SignalUnlock => {}
+            #[cfg(feature = "logind")]
+            UnlockReason::Requested => {}
+        }
+
+        // Requests that came in while locked are already served
+        signals.take_lock();
+        #[cfg(feature = "logind")]
+        signals.take_lid_closed();
+        unlocked_at = Instant::now();
+    }
+}
+
+fn run_duress_command(command: &str) {
+    if let Err(err) = process::Command::new("sh").arg("-c").arg(command).spawn() {
+        error!("Failed to run the duress command: {err}");
+    }
+}
+
+/// Replace pinlock with `fallback_command`, having failed to lock the
+/// screen with `err`. Only returns if it can't be run.
+fn hand_over(err: &anyhow::Error, fallback_command: &str) -> ExitCode {
+    error!("{err:#}, handing over to `{fallback_command}`");
+    let exec_err = process::Command::new("sh")
+        .arg("-c")
+        .arg(fallback_command)
+        .exec();
+    eprintln!("Error: Failed to run the fallback command `{fallback_command}`: {exec_err}");
+    ExitCode::FAILURE
+}
+
+/// Exit codes, so that scripts and service managers can tell outcomes apart.
+const EXIT_GRAB_FAILED: u8 = 2;
+const EXIT_CONFIG_ERROR: u8 = 3;
+const EXIT_SIGNAL: u8 = 4;
+const EXIT_ALREADY_RUNNING: u8 = 5;
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    if args.version {
+        print_version();
+        return ExitCode::SUCCESS;
+    }
+    if let Some(Command::Features) = args.command {
+        // Needs no configuration, so that it works before there is one
+        return print_features(args.display.as_deref());
+    }
+
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    // Loaded first, as it may choose the display that the instance lock is for
+    let config = match Config::load(args.config.as_deref()) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("Error: {:?}", err.context(ConfigError));
+            return ExitCode::from(EXIT_CONFIG_ERROR);
+        }
+    };
+    if let Some(path) = &args.print_layout {
+        return match preview::print_layout(config, path, args.layout_size) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => {
+                eprintln!("Error: {err:?}");
+                ExitCode::FAILURE
             }
-            Event::ButtonRelease(event) => {
-                println!("{:#?}", event.state);
-                println!(
-                    "Button {} released in window {}, at coordinates ({},{})",
-                    event.detail, event.event, event.event_x, event.event_y
-                );
+        };
+    }
+    #[cfg_attr(not(feature = "logind"), allow(unused_mut))]
+    let mut display = args.display.or_else(|| config.display.clone());
+    // A systemd user unit doesn't get the session's environment
+    #[cfg(feature = "logind")]
+    if display.is_none()
+        && matches!(args.command, Some(Command::Daemon))
+        && !locker::has_display_env()
+    {
+        match dbus::logind::session_display() {
+            Ok(session_display) => {
+                info!("$DISPLAY is not set, using {session_display} of the logind session");
+                display = Some(session_display);
             }
-            Event::MotionNotify(event) => {
-                println!(
-                    "Mouse moved in window {} at coordinates ({},{})",
-                    event.event, event.event_x, event.event_y
-                );
+            Err(err) => error!("$DISPLAY is not set and logind doesn't tell either: {err:#}"),
+        }
+    }
+
+    let _instance = match InstanceLock::acquire(display.as_deref()) {
+        Ok(instance) => instance,
+        Err(err) => match err.downcast_ref::<AlreadyRunning>() {
+            Some(running) if args.force => {
+                return match running.request_lock() {
+                    Ok(()) => {
+                        info!("{running}, asked it to lock the screen");
+                        ExitCode::SUCCESS
+                    }
+                    Err(err) => {
+                        eprintln!("Error: {err:?}");
+                        ExitCode::FAILURE
+                    }
+                };
             }
-            Event::EnterNotify(event) => {
-                println!(
-                    "Mouse entered window {} at coordinates ({},{})",
-                    event.event, event.event_x, event.event_y
-                );
+            Some(running) => {
+                eprintln!("Error: {running}, use --force to make it lock the screen");
+                return ExitCode::from(EXIT_ALREADY_RUNNING);
             }
-            Event::LeaveNotify(event) => {
-                println!(
-                    "Mouse left window {} at coordinates ({},{})",
-                    event.event, event.event_x, event.event_y
-                );
+            None => {
+                eprintln!("Error: {err:?}");
+                return ExitCode::FAILURE;
             }
-            Event::KeyPress(event) => {
-                println!("{:#?}", event.state);
-                println!("Key pressed in window {}", event.event);
+        },
+    };
+
+    let command = args.command.unwrap_or(Command::Lock);
+    let fallback_command = config
+        .fallback_command
+        .clone()
+        .filter(|_| matches!(command, Command::Lock));
+    let result = (|| {
+        let signals = Signals::register()?;
+        if args.allow_console && matches!(command, Command::Lock) {
+            if let Err(err) = locker::connect(display.as_deref()) {
+                warn!("{err:#}, locking the terminal instead");
+                return console::lock(&config, &signals);
             }
-            Event::KeyRelease(event) => {
-                println!("{:#?}", event.state);
-                println!("Key released in window {}", event.event);
+        }
+        let mut locker =
+            Locker::new(config, args.config, display.as_deref()).context(SetupError)?;
+        if let Some(path) = &args.debug_events {
+            locker.log_events(path)?;
+        }
+        #[cfg(feature = "replay")]
+        if let Some(path) = &args.replay {
+            locker.replay(path)?;
+        }
+
+        match command {
+            Command::Lock => locker.lock(&signals),
+            Command::Blank => locker.blank(&signals),
+            Command::Daemon => daemon(&mut locker, &signals),
+            Command::Features => unreachable!("handled before loading the configuration"),
+            Command::Selftest { hold } => {
+                locker.selftest(&signals, Duration::from_millis(hold))?;
+                println!("Self-test passed");
+                // Exits like any other unlock
+                Ok(UnlockReason::Authenticated)
             }
-            _ => {
-                // Unknown event type, ignore it
-                println!("Unknown event: {:?}", event);
+        }
+    })();
+
+    match result {
+        Ok(UnlockReason::Authenticated | UnlockReason::Grace | UnlockReason::SignalUnlock) => {
+            ExitCode::SUCCESS
+        }
+        #[cfg(feature = "logind")]
+        Ok(UnlockReason::Requested) => ExitCode::SUCCESS,
+        // Indistinguishable from a regular unlock, apart from the command
+        Ok(UnlockReason::Duress { command }) => {
+            run_duress_command(&command);
+            ExitCode::SUCCESS
+        }
+        Ok(UnlockReason::Signal) => ExitCode::from(EXIT_SIGNAL),
+        Err(err) if err.is::<SetupError>() && fallback_command.is_some() => {
+            hand_over(&err, fallback_command.as_deref().unwrap_or_default())
+        }
+        Err(err) => {
+            eprintln!("Error: {err:?}");
+            if err.is::<GrabError>() || err.is::<NotLocked>() {
+                ExitCode::from(EXIT_GRAB_FAILED)
+            } else if err.is::<ConfigError>() {
+                ExitCode::from(EXIT_CONFIG_ERROR)
+            } else {
+                ExitCode::FAILURE
             }
         }
     }
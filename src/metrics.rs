@@ -0,0 +1,139 @@
+//! Timings of drawing frames and of checking PINs, to find out where a lock
+//! screen on slow hardware spends its time. Only built with the `metrics`
+//! feature, and logged at the debug level once the screen is unlocked.
+//!
+//! Durations go into buckets rather than being kept, so that a lock screen
+//! animating for days uses as little memory as one that was just locked.
+
+use std::time::Duration;
+
+use log::debug;
+
+/// Buckets per doubling of the duration, which is what percentiles are
+/// rounded to: within a fifth of the actual value.
+const BUCKETS_PER_OCTAVE: u32 = 4;
+
+/// Buckets up to about 70 minutes in microseconds, anything longer counts
+/// into the last one.
+const BUCKETS: usize = 32 * BUCKETS_PER_OCTAVE as usize;
+
+/// How many durations fell into each of a range of buckets, growing
+/// exponentially from a microsecond.
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    counts: [u64; BUCKETS],
+    count: u64,
+    min: Duration,
+    max: Duration,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            counts: [0; BUCKETS],
+            count: 0,
+            min: Duration::MAX,
+            max: Duration::ZERO,
+        }
+    }
+}
+
+impl Histogram {
+    pub fn record(&mut self, duration: Duration) {
+        self.counts[bucket(duration)] += 1;
+        self.count += 1;
+        self.min = self.min.min(duration);
+        self.max = self.max.max(duration);
+    }
+
+    /// The duration `quantile` of the recorded ones are shorter than, from
+    /// 0 to 1, rounded to its bucket. `None` without any.
+    pub fn quantile(&self, quantile: f64) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = ((self.count as f64 * quantile).ceil() as u64).clamp(1, self.count);
+        let mut seen = 0;
+        let index = self.counts.iter().position(|&count| {
+            seen += count;
+            seen >= rank
+        })?;
+        Some(lower_bound(index).clamp(self.min, self.max))
+    }
+
+    /// One line of the count, minimum, median, 99th percentile and maximum.
+    pub fn summary(&self) -> Option<String> {
+        Some(format!(
+            "{} times, min {:?}, median {:?}, p99 {:?}, max {:?}",
+            self.count,
+            self.min,
+            self.quantile(0.5)?,
+            self.quantile(0.99)?,
+            self.max
+        ))
+    }
+}
+
+/// The bucket of `duration`: a run of them for each power of two of its
+/// microseconds, splitting it evenly.
+fn bucket(duration: Duration) -> usize {
+    let micros = u64::try_from(duration.as_micros()).unwrap_or(u64::MAX);
+    if micros < u64::from(BUCKETS_PER_OCTAVE) {
+        return micros as usize;
+    }
+    let octave = micros.ilog2();
+    let step = octave - BUCKETS_PER_OCTAVE.ilog2();
+    let within = (micros >> step) as u32 - BUCKETS_PER_OCTAVE;
+    (((octave - 1) * BUCKETS_PER_OCTAVE + within) as usize).min(BUCKETS - 1)
+}
+
+/// The shortest duration in the bucket at `index`.
+fn lower_bound(index: usize) -> Duration {
+    let index = index as u32;
+    if index < BUCKETS_PER_OCTAVE {
+        return Duration::from_micros(index.into());
+    }
+    let (octave, within) = (index / BUCKETS_PER_OCTAVE + 1, index % BUCKETS_PER_OCTAVE);
+    let step = octave - BUCKETS_PER_OCTAVE.ilog2();
+    Duration::from_micros(u64::from(BUCKETS_PER_OCTAVE + within) << step)
+}
+
+/// Log the frame and PIN check timings of a lock.
+pub fn log_summary(frames: &Histogram, auth: &Histogram) {
+    for (name, histogram) in [("Frames", frames), ("PIN checks", auth)] {
+        if let Some(summary) = histogram.summary() {
+            debug!("{name}: {summary}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles_are_rounded_to_their_bucket() {
+        let mut histogram = Histogram::default();
+        assert_eq!(histogram.summary(), None);
+        for millis in 1..=100 {
+            histogram.record(Duration::from_millis(millis));
+        }
+        let median = histogram.quantile(0.5).unwrap();
+        assert!(median <= Duration::from_millis(50));
+        assert!(median > Duration::from_millis(40));
+        let p99 = histogram.quantile(0.99).unwrap();
+        assert!(p99 > Duration::from_millis(80));
+        assert_eq!(histogram.quantile(0.0), Some(Duration::from_millis(1)));
+    }
+
+    #[test]
+    fn buckets_start_where_their_durations_do() {
+        for micros in [0, 3, 4, 5, 7, 8, 1000, 123_456] {
+            let duration = Duration::from_micros(micros);
+            let index = bucket(duration);
+            assert!(lower_bound(index) <= duration);
+            assert!(lower_bound(index + 1) > duration);
+        }
+        assert_eq!(bucket(Duration::MAX), BUCKETS - 1);
+    }
+}
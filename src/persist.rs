@@ -0,0 +1,132 @@
+use std::{
+    env,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+/// State that has to survive a restart of pinlock, so that killing and
+/// restarting it doesn't reset the failed attempts or end a lockout.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Persisted {
+    /// Failed attempts since the last unlock or lockout.
+    pub failures: u32,
+    /// Unix time in seconds until which PIN entry is refused.
+    pub lockout_until: Option<u64>,
+    /// Unix time in seconds of the last unlock with a PIN.
+    pub last_unlocked: Option<u64>,
+    /// Unix time in seconds of the last wrong PIN.
+    pub last_failed: Option<u64>,
+}
+
+impl Persisted {
+    /// Read the state file, starting fresh if there is none or it's unreadable.
+    pub fn load() -> Self {
+        let result = state_path().and_then(|path| match fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents)
+                .with_context(|| format!("Failed to parse {}", path.display())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        });
+
+        result.unwrap_or_else(|err| {
+            warn!("Ignoring the persisted state: {err:#}");
+            Self::default()
+        })
+    }
+
+    /// Write the state file. Failing to do so is logged, as it must not keep
+    /// the user from unlocking.
+    pub fn save(&self) {
+        let result = state_path().and_then(|path| {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)
+                    .with_context(|| format!("Failed to create {}", dir.display()))?;
+            }
+            replace(&path, toml::to_string(self)?.as_bytes())
+                .with_context(|| format!("Failed to write {}", path.display()))
+        });
+
+        if let Err(err) = result {
+            warn!("Failed to persist the state: {err:#}");
+        }
+    }
+}
+
+/// Replace the file at `path` with `contents`, readable by the user only.
+/// Written to a file next to it first and renamed over it, so that being
+/// killed halfway leaves the old file rather than a truncated one.
+fn replace(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&temporary)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&temporary, path)
+}
+
+pub fn to_unix(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+pub fn from_unix(secs: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+/// `$XDG_STATE_HOME/pinlock`, where the state file lives.
+pub fn state_dir() -> Result<PathBuf> {
+    let state_dir = match env::var_os("XDG_STATE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => {
+            let home = env::var_os("HOME").context("Neither XDG_STATE_HOME nor HOME is set")?;
+            PathBuf::from(home).join(".local").join("state")
+        }
+    };
+
+    Ok(state_dir.join("pinlock"))
+}
+
+fn state_path() -> Result<PathBuf> {
+    Ok(state_dir()?.join("state.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{os::unix::fs::PermissionsExt, process};
+
+    use super::*;
+
+    #[test]
+    fn the_state_file_is_replaced_whole() {
+        let dir = env::temp_dir().join(format!("pinlock-persist-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("state.toml");
+        fs::write(&path, "failures = 1\n").unwrap();
+
+        replace(&path, b"failures = 2\n").unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        let left = fs::read_dir(&dir).unwrap().count();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(contents, "failures = 2\n");
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(left, 1);
+    }
+}
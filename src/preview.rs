@@ -0,0 +1,562 @@
+//! `--print-layout`: the lock screen rendered into a PNG file instead of a
+//! window, to preview themes and layouts without a display.
+//!
+//! Shapes are rasterized in software, and text in a built-in 5x7 font the
+//! width of `fixed`, so that text takes the place it takes on screen. The
+//! background image is used if it is a file or a command, not a screenshot.
+
+use std::{
+    cell::{Cell, RefCell},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use image::{Rgba, RgbaImage};
+use log::warn;
+use x11rb::protocol::xproto::{Arc, KeyButMask, Point, Rectangle};
+
+use crate::{
+    backend::{Backend, FONT_SIZE},
+    config::Config,
+    image::{self as background, Encoded},
+    persist::Persisted,
+    state::LockState,
+    theme::Color,
+    ui::{self, Ui},
+    widget::{self, Rect},
+};
+
+/// What is typed into the previewed PIN field
+const SAMPLE_INPUT: &str = "1234";
+
+/// Width and height of the glyphs of `GLYPHS`, and their advance
+const GLYPH_SIZE: (u32, u32) = (5, 7);
+const ADVANCE: u32 = 6;
+/// Ascent and descent of `fixed`, which text boxes follow
+const ASCENT: i32 = 11;
+const DESCENT: i32 = 2;
+
+/// Render the lock screen as configured at `width` by `height` into a PNG
+/// file at `path`, with a few characters typed.
+pub fn print_layout(mut config: Config, path: &Path, (width, height): (u16, u16)) -> Result<()> {
+    // Settled, as it looks a moment after typing
+    config.animations = false;
+    config.grace_secs = 0;
+
+    let scale = ui::scale(&config, || 1.0);
+    let background = background_image(&config, width, height);
+    let backend = PngBackend::new(width, height, scale, background);
+    let whole = Rect {
+        x: 0,
+        y: 0,
+        width,
+        height,
+    };
+    let ui = Ui::with_backend(backend, (width, height), vec![whole], scale, None, &config)?;
+
+    let mut state = LockState::new(&config, &Persisted::default());
+    // The lock keys are all on, to show their indicators
+    state.set_leds(!0);
+    state.set_modifiers(KeyButMask::LOCK);
+    SAMPLE_INPUT.chars().for_each(|c| state.on_char(c));
+    ui.draw(&state, &widget::from_config(&config))?;
+
+    ui.backend().save(path)
+}
+
+/// Parse the size of `--layout-size`, such as `1920x1080`.
+pub fn parse_size(size: &str) -> Result<(u16, u16), String> {
+    let parsed = size.split_once('x').and_then(|(width, height)| {
+        Some((width.parse::<u16>().ok()?, height.parse::<u16>().ok()?))
+    });
+    match parsed {
+        Some((width, height))
+            if width > 0 && height > 0 && width <= i16::MAX as u16 && height <= i16::MAX as u16 =>
+        {
+            Ok((width, height))
+        }
+        _ => Err(format!("`{size}` is not a size like 1920x1080")),
+    }
+}
+
+/// The configured background image fitted to the whole frame, if it can be
+/// had without a display.
+fn background_image(config: &Config, width: u16, height: u16) -> Option<RgbaImage> {
+    let result = (|| {
+        let Some(image) = Encoded::without_display(config)? else {
+            return Ok(None);
+        };
+        let decoded = image.decode()?;
+        Ok::<_, anyhow::Error>(Some(background::fit(
+            &decoded,
+            config.background_mode,
+            width.into(),
+            height.into(),
+            config.theme.background,
+        )))
+    })();
+    result.unwrap_or_else(|err| {
+        warn!("Falling back to the background color: {err:#}");
+        None
+    })
+}
+
+/// Draws into an image in memory.
+pub struct PngBackend {
+    frame: RefCell<RgbaImage>,
+    background: Option<RgbaImage>,
+    clip: RefCell<Option<Vec<Rectangle>>>,
+    /// What text is drawn on, as in X: the color the frame was cleared with
+    text_background: Cell<Color>,
+    /// Pixels per pixel of the glyphs
+    glyph_scale: u32,
+}
+
+impl PngBackend {
+    pub fn new(width: u16, height: u16, scale: f64, background: Option<RgbaImage>) -> Self {
+        Self {
+            frame: RefCell::new(RgbaImage::new(width.into(), height.into())),
+            background,
+            clip: RefCell::new(None),
+            text_background: Cell::new(Color(0)),
+            glyph_scale: (scale.round() as u32).max(1),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        self.frame
+            .borrow()
+            .save(path)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Paint every pixel of `area` accepted by `covers` in `color`, within
+    /// the frame and the clip areas. `covers` is given the pixel's center.
+    fn paint(&self, color: Color, area: Rectangle, covers: impl Fn(f64, f64) -> bool) {
+        let mut frame = self.frame.borrow_mut();
+        let clip = self.clip.borrow();
+        let (frame_width, frame_height) = (frame.width() as i32, frame.height() as i32);
+        let [_, r, g, b] = color.pixel().to_be_bytes();
+
+        let left = i32::from(area.x).max(0);
+        let top = i32::from(area.y).max(0);
+        let right = (i32::from(area.x) + i32::from(area.width)).min(frame_width);
+        let bottom = (i32::from(area.y) + i32::from(area.height)).min(frame_height);
+        for y in top..bottom {
+            for x in left..right {
+                let clipped = clip
+                    .as_ref()
+                    .is_some_and(|areas| !areas.iter().any(|area| contains(*area, x, y)));
+                if !clipped && covers(f64::from(x) + 0.5, f64::from(y) + 0.5) {
+                    frame.put_pixel(x as u32, y as u32, Rgba([r, g, b, 0xff]));
+                }
+            }
+        }
+    }
+
+    fn draw_glyph(&self, color: Color, c: char, x: i32, baseline: i32) {
+        let scale = self.glyph_scale as i32;
+        let rows = glyph(c);
+        let (width, height) = (GLYPH_SIZE.0 as i32, GLYPH_SIZE.1 as i32);
+        let top = baseline - height * scale;
+        let area = rectangle(x, top, width * scale, height * scale);
+        self.paint(color, area, |px, py| {
+            let column = (px as i32 - x) / scale;
+            let row = (py as i32 - top) / scale;
+            rows[row as usize] & (1 << (width - 1 - column)) != 0
+        });
+    }
+}
+
+impl Backend for PngBackend {
+    fn line_height(&self) -> i16 {
+        (FONT_SIZE as u32 * self.glyph_scale) as i16
+    }
+
+    fn text_width(&self, text: &str) -> Result<i32> {
+        Ok((text.chars().count() as u32 * ADVANCE * self.glyph_scale) as i32)
+    }
+
+    fn clear(&self, color: Color) -> Result<()> {
+        self.text_background.set(color);
+        match &self.background {
+            Some(background) => self.frame.borrow_mut().clone_from(background),
+            None => {
+                let (width, height) = self.frame.borrow().dimensions();
+                self.paint(
+                    color,
+                    rectangle(0, 0, width as i32, height as i32),
+                    |_, _| true,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn fill_rectangles(&self, color: Color, rectangles: &[Rectangle]) -> Result<()> {
+        for &area in rectangles {
+            self.paint(color, area, |_, _| true);
+        }
+        Ok(())
+    }
+
+    fn draw_rectangles(&self, color: Color, rectangles: &[Rectangle]) -> Result<()> {
+        // Outlines cover one more pixel than filling does, as in X
+        for &Rectangle {
+            x,
+            y,
+            width,
+            height,
+        } in rectangles
+        {
+            let (x, y, width, height) = (x.into(), y.into(), i32::from(width), i32::from(height));
+            for edge in [
+                rectangle(x, y, width + 1, 1),
+                rectangle(x, y + height, width + 1, 1),
+                rectangle(x, y, 1, height + 1),
+                rectangle(x + width, y, 1, height + 1),
+            ] {
+                self.paint(color, edge, |_, _| true);
+            }
+        }
+        Ok(())
+    }
+
+    fn fill_arcs(&self, color: Color, arcs: &[Arc]) -> Result<()> {
+        for arc in arcs {
+            let ellipse = Ellipse::of(arc);
+            self.paint(color, ellipse.bounds(0.0), |x, y| {
+                ellipse.radius_at(x, y) <= 1.0 && ellipse.spans(arc, x, y)
+            });
+        }
+        Ok(())
+    }
+
+    fn draw_arcs(&self, color: Color, line_width: u16, arcs: &[Arc]) -> Result<()> {
+        let half = f64::from(line_width.max(1)) / 2.0;
+        for arc in arcs {
+            let ellipse = Ellipse::of(arc);
+            self.paint(color, ellipse.bounds(half), |x, y| {
+                let distance = (ellipse.radius_at(x, y) - 1.0).abs() * ellipse.mean_radius();
+                distance <= half && ellipse.spans(arc, x, y)
+            });
+        }
+        Ok(())
+    }
+
+    fn draw_lines(&self, color: Color, line_width: u16, points: &[Point]) -> Result<()> {
+        let half = f64::from(line_width.max(1)) / 2.0;
+        for line in points.windows(2) {
+            let (from, to) = (line[0], line[1]);
+            let margin = half.ceil() as i32 + 1;
+            let (left, top) = (from.x.min(to.x), from.y.min(to.y));
+            let area = rectangle(
+                i32::from(left) - margin,
+                i32::from(top) - margin,
+                i32::from(from.x.abs_diff(to.x)) + 2 * margin,
+                i32::from(from.y.abs_diff(to.y)) + 2 * margin,
+            );
+            self.paint(color, area, |x, y| {
+                distance_to_segment((x, y), from, to) <= half
+            });
+        }
+        Ok(())
+    }
+
+    fn draw_text(&self, color: Color, text: &str, x: i16, y: i16) -> Result<()> {
+        let scale = self.glyph_scale as i32;
+        let (x, y) = (i32::from(x), i32::from(y));
+        // Image text fills its box first
+        let width = self.text_width(text)?;
+        let text_box = rectangle(x, y - ASCENT * scale, width, (ASCENT + DESCENT) * scale);
+        self.paint(self.text_background.get(), text_box, |_, _| true);
+        for (i, c) in text.chars().enumerate() {
+            self.draw_glyph(color, c, x + i as i32 * ADVANCE as i32 * scale, y);
+        }
+        Ok(())
+    }
+
+    fn clip(&self, areas: &[Rectangle]) -> Result<()> {
+        *self.clip.borrow_mut() = Some(areas.to_vec());
+        Ok(())
+    }
+
+    fn unclip(&self) -> Result<()> {
+        *self.clip.borrow_mut() = None;
+        Ok(())
+    }
+
+    fn present(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// The ellipse an arc is a part of.
+struct Ellipse {
+    center: (f64, f64),
+    radii: (f64, f64),
+}
+
+impl Ellipse {
+    fn of(arc: &Arc) -> Self {
+        let radii = (f64::from(arc.width) / 2.0, f64::from(arc.height) / 2.0);
+        Self {
+            center: (f64::from(arc.x) + radii.0, f64::from(arc.y) + radii.1),
+            radii: (radii.0.max(0.5), radii.1.max(0.5)),
+        }
+    }
+
+    /// The area around the ellipse, grown by `margin`.
+    fn bounds(&self, margin: f64) -> Rectangle {
+        let (x, y) = (
+            self.center.0 - self.radii.0 - margin,
+            self.center.1 - self.radii.1 - margin,
+        );
+        rectangle(
+            x.floor() as i32,
+            y.floor() as i32,
+            (2.0 * (self.radii.0 + margin)).ceil() as i32 + 1,
+            (2.0 * (self.radii.1 + margin)).ceil() as i32 + 1,
+        )
+    }
+
+    /// How far a point is from the center, relative to the ellipse's
+    /// radius in its direction.
+    fn radius_at(&self, x: f64, y: f64) -> f64 {
+        let dx = (x - self.center.0) / self.radii.0;
+        let dy = (y - self.center.1) / self.radii.1;
+        dx.hypot(dy)
+    }
+
+    fn mean_radius(&self) -> f64 {
+        (self.radii.0 + self.radii.1) / 2.0
+    }
+
+    /// Whether a point lies within the angles of `arc`, which X counts in
+    /// 64ths of a degree counterclockwise from three o'clock.
+    fn spans(&self, arc: &Arc, x: f64, y: f64) -> bool {
+        let (mut start, mut extent) = (f64::from(arc.angle1) / 64.0, f64::from(arc.angle2) / 64.0);
+        if extent.abs() >= 360.0 {
+            return true;
+        }
+        if extent < 0.0 {
+            (start, extent) = (start + extent, -extent);
+        }
+        let angle = (self.center.1 - y).atan2(x - self.center.0).to_degrees();
+        (angle - start).rem_euclid(360.0) <= extent
+    }
+}
+
+/// A rectangle from `i32` coordinates, cut to what X coordinates can hold.
+fn rectangle(x: i32, y: i32, width: i32, height: i32) -> Rectangle {
+    let coordinate = |value: i32| value.clamp(i16::MIN.into(), i16::MAX.into()) as i16;
+    let size = |value: i32| value.clamp(0, u16::MAX.into()) as u16;
+    Rectangle {
+        x: coordinate(x),
+        y: coordinate(y),
+        width: size(width),
+        height: size(height),
+    }
+}
+
+fn contains(area: Rectangle, x: i32, y: i32) -> bool {
+    let (left, top) = (i32::from(area.x), i32::from(area.y));
+    (left..left + i32::from(area.width)).contains(&x)
+        && (top..top + i32::from(area.height)).contains(&y)
+}
+
+fn distance_to_segment((x, y): (f64, f64), from: Point, to: Point) -> f64 {
+    let (x1, y1) = (f64::from(from.x), f64::from(from.y));
+    let (dx, dy) = (f64::from(to.x) - x1, f64::from(to.y) - y1);
+    let length = dx * dx + dy * dy;
+    let along = if length == 0.0 {
+        0.0
+    } else {
+        (((x - x1) * dx + (y - y1) * dy) / length).clamp(0.0, 1.0)
+    };
+    (x - (x1 + along * dx)).hypot(y - (y1 + along * dy))
+}
+
+/// The rows of the glyph for `c`, a box for characters beyond ASCII.
+fn glyph(c: char) -> [u8; 7] {
+    let index = u32::from(c).wrapping_sub(0x20);
+    GLYPHS.get(index as usize).copied().unwrap_or([
+        0b11111, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b11111,
+    ])
+}
+
+/// A 5x7 font of printable ASCII from the space on, a row per byte with
+/// the leftmost pixel in the fifth bit.
+#[rustfmt::skip]
+const GLYPHS: [[u8; 7]; 95] = [
+    [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000],
+    [0b00100, 0b00100, 0b00100, 0b00100, 0b00000, 0b00000, 0b00100],
+    [0b01010, 0b01010, 0b01010, 0b00000, 0b00000, 0b00000, 0b00000],
+    [0b01010, 0b01010, 0b11111, 0b01010, 0b11111, 0b01010, 0b01010],
+    [0b00100, 0b01111, 0b10100, 0b01110, 0b00101, 0b11110, 0b00100],
+    [0b11000, 0b11001, 0b00010, 0b00100, 0b01000, 0b10011, 0b00011],
+    [0b01100, 0b10010, 0b10100, 0b01000, 0b10101, 0b10010, 0b01101],
+    [0b01100, 0b00100, 0b01000, 0b00000, 0b00000, 0b00000, 0b00000],
+    [0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010],
+    [0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000],
+    [0b00000, 0b00100, 0b10101, 0b01110, 0b10101, 0b00100, 0b00000],
+    [0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000],
+    [0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b00100, 0b01000],
+    [0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000],
+    [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100],
+    [0b00000, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b00000],
+    [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110],
+    [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110],
+    [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111],
+    [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110],
+    [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010],
+    [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110],
+    [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110],
+    [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000],
+    [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110],
+    [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100],
+    [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000],
+    [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b00100, 0b01000],
+    [0b00010, 0b00100, 0b01000, 0b10000, 0b01000, 0b00100, 0b00010],
+    [0b00000, 0b00000, 0b11111, 0b00000, 0b11111, 0b00000, 0b00000],
+    [0b01000, 0b00100, 0b00010, 0b00001, 0b00010, 0b00100, 0b01000],
+    [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100],
+    [0b01110, 0b10001, 0b00001, 0b01101, 0b10101, 0b10101, 0b01110],
+    [0b01110, 0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001],
+    [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110],
+    [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110],
+    [0b11100, 0b10010, 0b10001, 0b10001, 0b10001, 0b10010, 0b11100],
+    [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111],
+    [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000],
+    [0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111],
+    [0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001],
+    [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110],
+    [0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100],
+    [0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001],
+    [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111],
+    [0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001],
+    [0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001],
+    [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110],
+    [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000],
+    [0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101],
+    [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001],
+    [0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110],
+    [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100],
+    [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110],
+    [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100],
+    [0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010],
+    [0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001],
+    [0b10001, 0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100],
+    [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111],
+    [0b01110, 0b01000, 0b01000, 0b01000, 0b01000, 0b01000, 0b01110],
+    [0b00000, 0b10000, 0b01000, 0b00100, 0b00010, 0b00001, 0b00000],
+    [0b01110, 0b00010, 0b00010, 0b00010, 0b00010, 0b00010, 0b01110],
+    [0b00100, 0b01010, 0b10001, 0b00000, 0b00000, 0b00000, 0b00000],
+    [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111],
+    [0b01000, 0b00100, 0b00010, 0b00000, 0b00000, 0b00000, 0b00000],
+    [0b00000, 0b00000, 0b01110, 0b00001, 0b01111, 0b10001, 0b01111],
+    [0b10000, 0b10000, 0b10110, 0b11001, 0b10001, 0b10001, 0b11110],
+    [0b00000, 0b00000, 0b01110, 0b10000, 0b10000, 0b10001, 0b01110],
+    [0b00001, 0b00001, 0b01101, 0b10011, 0b10001, 0b10001, 0b01111],
+    [0b00000, 0b00000, 0b01110, 0b10001, 0b11111, 0b10000, 0b01110],
+    [0b00110, 0b01001, 0b01000, 0b11100, 0b01000, 0b01000, 0b01000],
+    [0b00000, 0b01111, 0b10001, 0b10001, 0b01111, 0b00001, 0b01110],
+    [0b10000, 0b10000, 0b10110, 0b11001, 0b10001, 0b10001, 0b10001],
+    [0b00100, 0b00000, 0b01100, 0b00100, 0b00100, 0b00100, 0b01110],
+    [0b00010, 0b00000, 0b00110, 0b00010, 0b00010, 0b10010, 0b01100],
+    [0b10000, 0b10000, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010],
+    [0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110],
+    [0b00000, 0b00000, 0b11010, 0b10101, 0b10101, 0b10001, 0b10001],
+    [0b00000, 0b00000, 0b10110, 0b11001, 0b10001, 0b10001, 0b10001],
+    [0b00000, 0b00000, 0b01110, 0b10001, 0b10001, 0b10001, 0b01110],
+    [0b00000, 0b00000, 0b11110, 0b10001, 0b11110, 0b10000, 0b10000],
+    [0b00000, 0b00000, 0b01101, 0b10011, 0b01111, 0b00001, 0b00001],
+    [0b00000, 0b00000, 0b10110, 0b11001, 0b10000, 0b10000, 0b10000],
+    [0b00000, 0b00000, 0b01110, 0b10000, 0b01110, 0b00001, 0b11110],
+    [0b01000, 0b01000, 0b11100, 0b01000, 0b01000, 0b01001, 0b00110],
+    [0b00000, 0b00000, 0b10001, 0b10001, 0b10001, 0b10011, 0b01101],
+    [0b00000, 0b00000, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100],
+    [0b00000, 0b00000, 0b10001, 0b10001, 0b10101, 0b10101, 0b01010],
+    [0b00000, 0b00000, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001],
+    [0b00000, 0b00000, 0b10001, 0b10001, 0b01111, 0b00001, 0b01110],
+    [0b00000, 0b00000, 0b11111, 0b00010, 0b00100, 0b01000, 0b11111],
+    [0b00010, 0b00100, 0b00100, 0b01000, 0b00100, 0b00100, 0b00010],
+    [0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100],
+    [0b01000, 0b00100, 0b00100, 0b00010, 0b00100, 0b00100, 0b01000],
+    [0b00000, 0b00000, 0b01000, 0b10101, 0b00010, 0b00000, 0b00000],
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: Color = Color(0xffffff);
+
+    fn pixel(backend: &PngBackend, x: u32, y: u32) -> [u8; 4] {
+        backend.frame.borrow().get_pixel(x, y).0
+    }
+
+    fn opaque(color: Color) -> [u8; 4] {
+        let [_, r, g, b] = color.0.to_be_bytes();
+        [r, g, b, 0xff]
+    }
+
+    #[test]
+    fn sizes_are_width_by_height() {
+        assert_eq!(parse_size("1920x1080"), Ok((1920, 1080)));
+        assert!(parse_size("1920").is_err());
+        assert!(parse_size("0x1080").is_err());
+    }
+
+    #[test]
+    fn text_is_as_wide_as_in_fixed() {
+        let backend = PngBackend::new(100, 100, 1.0, None);
+        assert_eq!(backend.text_width("12:34").unwrap(), 30);
+        assert_eq!(backend.line_height(), 13);
+
+        let doubled = PngBackend::new(100, 100, 2.0, None);
+        assert_eq!(doubled.text_width("12:34").unwrap(), 60);
+    }
+
+    #[test]
+    fn the_pin_field_covers_the_card() {
+        let config = Config {
+            card: true,
+            animations: false,
+            ..Config::default()
+        };
+        let backend = PngBackend::new(400, 300, 1.0, None);
+        let whole = Rect {
+            x: 0,
+            y: 0,
+            width: 400,
+            height: 300,
+        };
+        let ui = Ui::with_backend(backend, (400, 300), vec![whole], 1.0, None, &config).unwrap();
+        let mut state = LockState::new(&config, &Persisted::default());
+        SAMPLE_INPUT.chars().for_each(|c| state.on_char(c));
+        ui.draw(&state, &[]).unwrap();
+
+        // The second dot, on the card, and the card between it and the third
+        let (dot, card) = (pixel(ui.backend(), 188, 150), pixel(ui.backend(), 200, 150));
+        assert_eq!(dot, opaque(config.theme.foreground));
+        assert_ne!(card, opaque(config.theme.background));
+        assert_ne!(card, dot);
+    }
+
+    #[test]
+    fn dots_are_filled_circles() {
+        let backend = PngBackend::new(20, 20, 1.0, None);
+        let dot = Arc {
+            x: 2,
+            y: 2,
+            width: 16,
+            height: 16,
+            angle1: 0,
+            angle2: 360 * 64,
+        };
+        backend.fill_arcs(WHITE, &[dot]).unwrap();
+        assert_eq!(pixel(&backend, 10, 10), [0xff; 4]);
+        assert_eq!(pixel(&backend, 3, 3), [0; 4]);
+    }
+}
@@ -0,0 +1,96 @@
+//! The lock state on a property of the root window, for `publish_state`,
+//! so that scripts can follow it with `xprop -root -spy _PINLOCK_STATE`.
+
+use anyhow::Result;
+use x11rb::{
+    connection::Connection,
+    protocol::xproto::{Atom, AtomEnum, ConnectionExt as _, PropMode},
+    rust_connection::RustConnection,
+    wrapper::ConnectionExt as _,
+};
+
+use crate::state::LockState;
+
+const PROPERTY: &[u8] = b"_PINLOCK_STATE";
+
+/// Sets the property while the screen is locked, and deletes it once
+/// dropped.
+pub struct PublishedState<'connection> {
+    conn: &'connection RustConnection,
+    root: u32,
+    atom: Atom,
+    /// The last value set, to only change the property when it changes
+    last: Option<String>,
+}
+
+impl<'connection> PublishedState<'connection> {
+    pub fn new(connection: &'connection RustConnection, root: u32) -> Result<Self> {
+        let atom = connection.intern_atom(false, PROPERTY)?.reply()?.atom;
+        Ok(Self {
+            conn: connection,
+            root,
+            atom,
+            last: None,
+        })
+    }
+
+    /// Publish `state` if it changed since the last call.
+    pub fn update(&mut self, state: &LockState) -> Result<()> {
+        let value = describe(state);
+        if self.last.as_ref() == Some(&value) {
+            return Ok(());
+        }
+        self.conn.change_property8(
+            PropMode::REPLACE,
+            self.root,
+            self.atom,
+            AtomEnum::STRING,
+            value.as_bytes(),
+        )?;
+        self.last = Some(value);
+        Ok(())
+    }
+}
+
+impl Drop for PublishedState<'_> {
+    fn drop(&mut self) {
+        let _ = self.conn.delete_property(self.root, self.atom);
+        let _ = self.conn.flush();
+    }
+}
+
+/// What becomes of the property: the phase of the lock, the failed
+/// attempts and how many characters were entered, but never which.
+fn describe(state: &LockState) -> String {
+    let phase = if state.is_blank() {
+        "blank"
+    } else if state.in_grace() {
+        "grace"
+    } else if state.is_locked_out() {
+        "locked_out"
+    } else {
+        "locked"
+    };
+    format!(
+        "state={phase} failed_attempts={} input_length={}",
+        state.failed_attempts(),
+        state.input_len()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Config, persist::Persisted};
+
+    #[test]
+    fn only_the_length_of_the_input_is_published() {
+        let mut state = LockState::new(&Config::default(), &Persisted::default());
+        state.on_char('4');
+        state.on_char('2');
+        assert_eq!(
+            describe(&state),
+            "state=locked failed_attempts=0 input_length=2"
+        );
+    }
+}
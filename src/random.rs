@@ -0,0 +1,76 @@
+//! Everything random that pinlock draws goes through here: the pads of
+//! protected secrets and the jitter of `constant_time_response_ms` from the
+//! operating system's CSPRNG, and the keypad layout from the configured
+//! `randomness_source`.
+
+use std::{fs::File, io::Read, path::PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+
+/// Where the keypad layout is drawn from: `"os"` for the operating system's
+/// CSPRNG, or the path of a device such as `/dev/hwrng`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(from = "String")]
+pub enum RandomnessSource {
+    #[default]
+    Os,
+    Device(PathBuf),
+}
+
+impl From<String> for RandomnessSource {
+    fn from(source: String) -> Self {
+        match source.as_str() {
+            "os" => Self::Os,
+            _ => Self::Device(source.into()),
+        }
+    }
+}
+
+impl RandomnessSource {
+    /// Fill `bytes` from this source. A device that can't be read is an
+    /// error, never a reason to draw from another source.
+    pub fn fill(&self, bytes: &mut [u8]) -> Result<()> {
+        match self {
+            Self::Os => os_fill(bytes).map_err(|err| anyhow!("The OS CSPRNG failed: {err}")),
+            Self::Device(path) => File::open(path)
+                .and_then(|mut device| device.read_exact(bytes))
+                .with_context(|| format!("Failed to read randomness from {}", path.display())),
+        }
+    }
+
+    pub fn u32(&self) -> Result<u32> {
+        let mut bytes = [0; 4];
+        self.fill(&mut bytes)?;
+        Ok(u32::from_ne_bytes(bytes))
+    }
+}
+
+/// Fill `bytes` from the operating system's CSPRNG.
+pub fn os_fill(bytes: &mut [u8]) -> Result<(), getrandom::Error> {
+    getrandom::fill(bytes)
+}
+
+/// A number from the operating system's CSPRNG.
+pub fn os_u64() -> Result<u64, getrandom::Error> {
+    getrandom::u64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn an_unreadable_device_is_an_error() {
+        assert_eq!(
+            RandomnessSource::from("os".to_owned()),
+            RandomnessSource::Os
+        );
+        let source = RandomnessSource::from("/nonexistent/hwrng".to_owned());
+        assert!(source.u32().is_err());
+        // Read like any device
+        assert!(RandomnessSource::from("/dev/urandom".to_owned())
+            .u32()
+            .is_ok());
+    }
+}
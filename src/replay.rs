@@ -0,0 +1,159 @@
+//! Scripted key presses for `--replay`, so that tests can drive a real lock
+//! screen end to end: type a PIN, submit it and see it unlock.
+//!
+//! Only built with the `replay` feature, as anything that can type into the
+//! lock screen could unlock it.
+//!
+//! A script has a step per line, `#` starting a comment:
+//!
+//! ```text
+//! # A wrong PIN, then the right one
+//! 9 9 9 9 Return
+//! wait 500
+//! 1 2 3 4 Return
+//! ```
+//!
+//! Keys are written like [`KeyCombo`]s, several to a line, and `wait MS`
+//! pauses before the next step.
+
+use std::{
+    collections::VecDeque,
+    fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use x11rb::protocol::{
+    xproto::{KeyButMask, KeyPressEvent},
+    Event,
+};
+
+use crate::input::{KeyCombo, Keymap};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    Key(KeyCombo),
+    Wait(Duration),
+}
+
+/// The steps of a script still to come.
+pub struct Replay {
+    steps: VecDeque<Step>,
+    /// The release of the key pressed last, sent right after it
+    release: Option<KeyPressEvent>,
+    /// When the current `wait` ends
+    resume_at: Option<Instant>,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> Result<Self> {
+        let script = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let steps = parse(&script).with_context(|| format!("In {}", path.display()))?;
+        Ok(Self {
+            steps: steps.into(),
+            release: None,
+            resume_at: None,
+        })
+    }
+
+    /// How long until the next event is due, `None` once the script is
+    /// over.
+    pub fn remaining(&self) -> Option<Duration> {
+        if self.release.is_some() {
+            return Some(Duration::ZERO);
+        }
+        if self.steps.is_empty() {
+            return None;
+        }
+        Some(self.resume_at.map_or(Duration::ZERO, |at| {
+            at.saturating_duration_since(Instant::now())
+        }))
+    }
+
+    /// The next key press or release for `window` on `root`, if one is
+    /// due.
+    pub fn next(&mut self, keymap: &Keymap, window: u32, root: u32) -> Result<Option<Event>> {
+        if let Some(release) = self.release.take() {
+            return Ok(Some(Event::KeyRelease(release)));
+        }
+        if self.resume_at.is_some_and(|at| at > Instant::now()) {
+            return Ok(None);
+        }
+        self.resume_at = None;
+
+        match self.steps.pop_front() {
+            None => Ok(None),
+            Some(Step::Wait(duration)) => {
+                self.resume_at = Some(Instant::now() + duration);
+                Ok(None)
+            }
+            Some(Step::Key(combo)) => {
+                let Some(&keycode) = keymap.keycodes(combo.keysym).first() else {
+                    bail!("No key of the keymap types {:#x}", combo.keysym);
+                };
+                let press = KeyPressEvent {
+                    detail: keycode,
+                    root,
+                    event: window,
+                    state: KeyButMask::from(u16::from(combo.modifiers)),
+                    same_screen: true,
+                    ..Default::default()
+                };
+                self.release = Some(press);
+                Ok(Some(Event::KeyPress(press)))
+            }
+        }
+    }
+}
+
+fn parse(script: &str) -> Result<Vec<Step>> {
+    let mut steps = Vec::new();
+    for (number, line) in script.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default();
+        let mut words = line.split_whitespace();
+        while let Some(word) = words.next() {
+            let step = if word == "wait" {
+                let Some(ms) = words.next().and_then(|ms| ms.parse().ok()) else {
+                    bail!("`wait` takes milliseconds, on line {}", number + 1);
+                };
+                Step::Wait(Duration::from_millis(ms))
+            } else {
+                let combo = KeyCombo::try_from(word.to_owned())
+                    .with_context(|| format!("On line {}", number + 1))?;
+                Step::Key(combo)
+            };
+            steps.push(step);
+        }
+    }
+    Ok(steps)
+}
+
+#[cfg(test)]
+mod tests {
+    use x11rb::protocol::xproto::ModMask;
+
+    use super::*;
+
+    #[test]
+    fn scripts_are_keys_and_waits() {
+        let key = |keysym| {
+            Step::Key(KeyCombo {
+                modifiers: ModMask::from(0u16),
+                keysym,
+            })
+        };
+        assert_eq!(
+            parse("# Unlock\n1 2 Return\nwait 250 # settle\n").unwrap(),
+            [
+                key(u32::from(b'1')),
+                key(u32::from(b'2')),
+                key(0xff0d),
+                Step::Wait(Duration::from_millis(250)),
+            ]
+        );
+        assert!(parse("wait soon").is_err());
+        assert!(parse("Hyper+1").is_err());
+    }
+}
@@ -0,0 +1,247 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use chrono::Local;
+use serde::Deserialize;
+use x11rb::protocol::xproto::Rectangle;
+
+use crate::{config::Config, theme::Color, ui::Canvas};
+
+/// Time between two frames of an animation.
+const FRAME_INTERVAL: Duration = Duration::from_millis(50);
+
+/// An animation shown in place of the PIN field while nobody is typing.
+pub trait Screensaver {
+    /// Draw frame number `tick` onto a cleared canvas.
+    fn draw(&mut self, canvas: &Canvas, tick: u64) -> Result<()>;
+
+    /// Time between two frames.
+    fn frame_interval(&self) -> Duration {
+        FRAME_INTERVAL
+    }
+}
+
+/// The built-in animations, selected by the `screensaver` option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScreensaverKind {
+    /// The current time, bouncing off the screen edges.
+    Clock,
+    Starfield,
+}
+
+impl ScreensaverKind {
+    fn create(self, config: &Config) -> Box<dyn Screensaver> {
+        match self {
+            Self::Clock => Box::new(BouncingClock {
+                format: config.clock_format.clone(),
+            }),
+            Self::Starfield => Box::new(Starfield::default()),
+        }
+    }
+}
+
+/// Runs a screensaver once there was no keyboard input for a while.
+pub struct Idle {
+    screensaver: Box<dyn Screensaver>,
+    delay: Duration,
+    last_input: Instant,
+    tick: u64,
+}
+
+impl Idle {
+    /// The configured screensaver, or else the clock of
+    /// `idle_clock_after_secs`, if either is set.
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let (screensaver, delay) = match (config.screensaver, config.idle_clock_after()) {
+            (Some(kind), _) => (kind.create(config), config.screensaver_delay()),
+            (None, Some(delay)) => (Box::new(IdleClock::new(config)) as _, delay),
+            (None, None) => return None,
+        };
+        Some(Self {
+            screensaver,
+            delay,
+            last_input: Instant::now(),
+            tick: 0,
+        })
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.last_input.elapsed() >= self.delay
+    }
+
+    /// Time left until the next frame, or until the screensaver starts.
+    pub fn remaining(&self) -> Duration {
+        if self.is_active() {
+            self.screensaver.frame_interval()
+        } else {
+            self.delay.saturating_sub(self.last_input.elapsed())
+        }
+    }
+
+    /// Record keyboard input, stopping the screensaver. Returns whether it
+    /// was running.
+    pub fn wake(&mut self) -> bool {
+        let was_active = self.is_active();
+        self.last_input = Instant::now();
+        self.tick = 0;
+        was_active
+    }
+
+    /// Draw the next frame.
+    pub fn draw(&mut self, canvas: &Canvas) -> Result<()> {
+        self.tick += 1;
+        self.screensaver.draw(canvas, self.tick)
+    }
+}
+
+/// Show the local time formatted by `clock_format`, drifting diagonally
+/// across the screen.
+struct BouncingClock {
+    format: String,
+}
+
+impl Screensaver for BouncingClock {
+    fn draw(&mut self, canvas: &Canvas, tick: u64) -> Result<()> {
+        let text = Local::now().format(&self.format).to_string();
+
+        // Measured every frame, the width changes with the time of day in
+        // formats like "%-I:%M %p"
+        let width = canvas.text_width(&text)?;
+        let x = bounce(tick * 2, i32::from(canvas.width()) - width);
+        let y = bounce(
+            tick,
+            i32::from(canvas.height()) - i32::from(canvas.line_height()),
+        );
+
+        canvas.draw_text(
+            &text,
+            x as i16,
+            (y + i32::from(canvas.line_height())) as i16,
+        )
+    }
+}
+
+/// Only the local time formatted by `clock_format`, centered and dimmed to
+/// `idle_clock_brightness`, like a bedside clock.
+struct IdleClock {
+    format: String,
+    color: Color,
+}
+
+impl IdleClock {
+    fn new(config: &Config) -> Self {
+        let theme = &config.theme;
+        Self {
+            format: config.clock_format.clone(),
+            color: theme
+                .background
+                .blend(theme.foreground, config.idle_clock_brightness),
+        }
+    }
+}
+
+impl Screensaver for IdleClock {
+    fn draw(&mut self, canvas: &Canvas, _tick: u64) -> Result<()> {
+        let text = Local::now().format(&self.format).to_string();
+        let width = canvas.text_width(&text)?;
+        let x = (i32::from(canvas.width()) - width) / 2;
+        let y = canvas.height() as i16 / 2 + canvas.line_height() / 2;
+        canvas.in_color(self.color).draw_text(&text, x as i16, y)
+    }
+
+    /// The time only changes by the second
+    fn frame_interval(&self) -> Duration {
+        Duration::from_secs(1)
+    }
+}
+
+/// Position moving back and forth between 0 and `range`, one pixel per step.
+fn bounce(step: u64, range: i32) -> i32 {
+    let range = u64::try_from(range).unwrap_or(0);
+    if range == 0 {
+        return 0;
+    }
+    let position = step % (2 * range);
+    position.min(2 * range - position) as i32
+}
+
+const STAR_COUNT: usize = 200;
+/// Depth at which stars are spawned, they come closer by one unit per frame.
+const STAR_DEPTH: u32 = 256;
+
+/// Stars flying towards the viewer from the center of the screen.
+struct Starfield {
+    /// Offsets from the center and depth of every star
+    stars: Vec<(i32, i32, u32)>,
+    /// State of the random number generator placing new stars
+    seed: u32,
+}
+
+impl Default for Starfield {
+    fn default() -> Self {
+        let mut starfield = Self {
+            stars: Vec::with_capacity(STAR_COUNT),
+            seed: 0x2545_f491,
+        };
+        for _ in 0..STAR_COUNT {
+            let mut star = starfield.spawn();
+            star.2 = starfield.random() % STAR_DEPTH + 1;
+            starfield.stars.push(star);
+        }
+        starfield
+    }
+}
+
+impl Starfield {
+    /// A xorshift generator, good enough for scattering stars.
+    fn random(&mut self) -> u32 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        self.seed
+    }
+
+    fn spawn(&mut self) -> (i32, i32, u32) {
+        let x = (self.random() % 2048) as i32 - 1024;
+        let y = (self.random() % 2048) as i32 - 1024;
+        (x, y, STAR_DEPTH)
+    }
+}
+
+impl Screensaver for Starfield {
+    fn draw(&mut self, canvas: &Canvas, _tick: u64) -> Result<()> {
+        let (half_width, half_height) = (
+            i32::from(canvas.width()) / 2,
+            i32::from(canvas.height()) / 2,
+        );
+
+        let mut points = Vec::with_capacity(self.stars.len());
+        for i in 0..self.stars.len() {
+            let (x, y, depth) = self.stars[i];
+            let (screen_x, screen_y) = (
+                half_width + x * half_width / depth as i32 / 4,
+                half_height + y * half_width / depth as i32 / 4,
+            );
+
+            let visible = (0..i32::from(canvas.width())).contains(&screen_x)
+                && (0..i32::from(canvas.height())).contains(&screen_y);
+            if !visible || depth <= 1 {
+                self.stars[i] = self.spawn();
+                continue;
+            }
+            self.stars[i].2 = depth - 1;
+
+            // Closer stars are drawn bigger
+            let size = if depth < STAR_DEPTH / 4 { 2 } else { 1 };
+            points.push(Rectangle {
+                x: screen_x as i16,
+                y: screen_y as i16,
+                width: size,
+                height: size,
+            });
+        }
+
+        canvas.fill_rectangles(&points)
+    }
+}
@@ -0,0 +1,177 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use anyhow::Result;
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM, SIGUSR1, SIGUSR2};
+
+/// Flags set by signal handlers, polled by the event loops.
+///
+/// - SIGTERM and SIGINT end pinlock, unlocking the screen.
+/// - SIGHUP reloads the configuration.
+/// - SIGUSR1 locks right away in daemon mode, and does nothing while locked.
+/// - SIGUSR2 unlocks with `signal_unlock`, and does nothing otherwise.
+///
+/// Delivery of a signal interrupts the event wait, so the loops see the flags
+/// right away rather than on the next X event.
+pub struct Signals {
+    terminate: Arc<AtomicBool>,
+    reload: Arc<AtomicBool>,
+    lock: Arc<AtomicBool>,
+    unlock_signal: Arc<AtomicBool>,
+    #[cfg(feature = "logind")]
+    unlock: Arc<AtomicBool>,
+    #[cfg(feature = "logind")]
+    lid_closed: Arc<AtomicBool>,
+    /// The thread running the event loops, woken up by lock requests
+    #[cfg(feature = "dbus")]
+    main_thread: libc::pthread_t,
+}
+
+impl Signals {
+    pub fn register() -> Result<Self> {
+        let terminate = Arc::new(AtomicBool::new(false));
+        for signal in [SIGTERM, SIGINT] {
+            signal_hook::flag::register(signal, Arc::clone(&terminate))?;
+        }
+
+        let reload = Arc::new(AtomicBool::new(false));
+        signal_hook::flag::register(SIGHUP, Arc::clone(&reload))?;
+
+        let lock = Arc::new(AtomicBool::new(false));
+        signal_hook::flag::register(SIGUSR1, Arc::clone(&lock))?;
+
+        let unlock_signal = Arc::new(AtomicBool::new(false));
+        signal_hook::flag::register(SIGUSR2, Arc::clone(&unlock_signal))?;
+
+        // Only there to interrupt the event wait, the unlock flag says what for
+        #[cfg(feature = "logind")]
+        signal_hook::flag::register(wake_up_signal(), Arc::new(AtomicBool::new(false)))?;
+
+        Ok(Self {
+            terminate,
+            reload,
+            lock,
+            unlock_signal,
+            #[cfg(feature = "logind")]
+            unlock: Arc::new(AtomicBool::new(false)),
+            #[cfg(feature = "logind")]
+            lid_closed: Arc::new(AtomicBool::new(false)),
+            #[cfg(feature = "dbus")]
+            // SAFETY: always safe to call
+            main_thread: unsafe { libc::pthread_self() },
+        })
+    }
+
+    /// Whether SIGTERM or SIGINT was received.
+    pub fn terminate(&self) -> bool {
+        self.terminate.load(Ordering::Relaxed)
+    }
+
+    /// Whether SIGHUP was received since the last call.
+    pub fn take_reload(&self) -> bool {
+        self.reload.swap(false, Ordering::Relaxed)
+    }
+
+    /// Whether SIGUSR1 asked to lock right away since the last call.
+    pub fn take_lock(&self) -> bool {
+        self.lock.swap(false, Ordering::Relaxed)
+    }
+
+    /// Whether SIGUSR2 asked to unlock since the last call.
+    pub fn take_unlock_signal(&self) -> bool {
+        self.unlock_signal.swap(false, Ordering::Relaxed)
+    }
+
+    /// Whether unlocking was requested from another thread since the last call.
+    #[cfg(feature = "logind")]
+    pub fn take_unlock(&self) -> bool {
+        self.unlock.swap(false, Ordering::Relaxed)
+    }
+
+    /// Whether the laptop lid was closed since the last call.
+    #[cfg(feature = "logind")]
+    pub fn take_lid_closed(&self) -> bool {
+        self.lid_closed.swap(false, Ordering::Relaxed)
+    }
+
+    /// A handle for other threads to request locking or unlocking.
+    #[cfg(feature = "dbus")]
+    pub fn requests(&self) -> Requests {
+        Requests {
+            #[cfg(feature = "logind")]
+            unlock: Arc::clone(&self.unlock),
+            #[cfg(feature = "logind")]
+            lid_closed: Arc::clone(&self.lid_closed),
+            main_thread: self.main_thread,
+        }
+    }
+}
+
+/// What other threads wake up the event wait with, rather than SIGUSR2,
+/// which would count as a request of its own.
+#[cfg(feature = "logind")]
+fn wake_up_signal() -> libc::c_int {
+    libc::SIGRTMIN()
+}
+
+/// Sets the lock and unlock flags of [`Signals`] from another thread.
+#[cfg(feature = "dbus")]
+#[derive(Clone)]
+pub struct Requests {
+    #[cfg(feature = "logind")]
+    unlock: Arc<AtomicBool>,
+    #[cfg(feature = "logind")]
+    lid_closed: Arc<AtomicBool>,
+    main_thread: libc::pthread_t,
+}
+
+#[cfg(feature = "dbus")]
+impl Requests {
+    /// Same as sending SIGUSR1.
+    pub fn lock(&self) {
+        self.signal(SIGUSR1);
+    }
+
+    #[cfg(feature = "logind")]
+    pub fn unlock(&self) {
+        self.unlock.store(true, Ordering::Relaxed);
+        self.signal(wake_up_signal());
+    }
+
+    /// Only polled, the daemon checks it at least every second.
+    #[cfg(feature = "logind")]
+    pub fn lid_closed(&self) {
+        self.lid_closed.store(true, Ordering::Relaxed);
+    }
+
+    /// Signal the main thread, which also interrupts its event wait so it
+    /// sees the flags.
+    fn signal(&self, signal: libc::c_int) {
+        // SAFETY: the main thread outlives the other threads, which are only
+        // left running when the process exits
+        unsafe { libc::pthread_kill(self.main_thread, signal) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_signals_ask_to_lock_and_unlock() {
+        let signals = Signals::register().unwrap();
+        // SAFETY: the handlers only set flags
+        unsafe { libc::raise(SIGUSR1) };
+        assert!(signals.take_lock());
+        assert!(!signals.take_lock());
+        assert!(!signals.take_unlock_signal());
+
+        // SAFETY: as above
+        unsafe { libc::raise(SIGUSR2) };
+        assert!(signals.take_unlock_signal());
+        assert!(!signals.take_unlock_signal());
+        assert!(!signals.terminate());
+    }
+}
@@ -0,0 +1,154 @@
+//! Sounds on typing, submitting and failed attempts, as feedback on public
+//! terminals.
+//!
+//! The X bell needs nothing else. WAV files need the `audio` feature and
+//! are played by `aplay`, in the background, so that the lock screen never
+//! waits for them.
+
+use std::path::PathBuf;
+#[cfg(feature = "audio")]
+use std::{
+    process::{Child, Command, Stdio},
+    thread,
+};
+
+use anyhow::Result;
+#[cfg(feature = "audio")]
+use log::warn;
+use serde::Deserialize;
+use x11rb::{
+    connection::Connection, protocol::xproto::ConnectionExt, rust_connection::RustConnection,
+};
+
+use crate::config::Config;
+
+/// A sound of `key_sound`, `submit_sound` or `failure_sound`: `"bell"`,
+/// `"bell:PERCENT"` or the path of a WAV file.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Sound {
+    /// The X bell, at a volume from -100 to 100 percent relative to its
+    /// base volume
+    Bell(i8),
+    Wav(PathBuf),
+}
+
+impl TryFrom<String> for Sound {
+    type Error = String;
+
+    fn try_from(sound: String) -> Result<Self, Self::Error> {
+        if sound == "bell" {
+            return Ok(Self::Bell(0));
+        }
+        if let Some(percent) = sound.strip_prefix("bell:") {
+            return match percent.parse() {
+                Ok(percent @ -100..=100) => Ok(Self::Bell(percent)),
+                _ => Err(format!(
+                    "`{percent}` is not a bell volume between -100 and 100"
+                )),
+            };
+        }
+        if sound.is_empty() {
+            return Err("A sound can't be empty".to_owned());
+        }
+        Ok(Self::Wav(sound.into()))
+    }
+}
+
+/// Plays the configured sounds.
+pub struct Sounds<'connection> {
+    conn: &'connection RustConnection,
+    key: Option<Sound>,
+    submit: Option<Sound>,
+    failure: Option<Sound>,
+    /// Players still running, reaped as new sounds start
+    #[cfg(feature = "audio")]
+    players: Vec<Child>,
+}
+
+impl<'connection> Sounds<'connection> {
+    pub fn new(connection: &'connection RustConnection, config: &Config) -> Self {
+        Self {
+            conn: connection,
+            key: config.key_sound.clone(),
+            submit: config.submit_sound.clone(),
+            failure: config.failure_sound.clone(),
+            #[cfg(feature = "audio")]
+            players: Vec::new(),
+        }
+    }
+
+    /// A key was typed into the PIN.
+    pub fn key(&mut self) -> Result<()> {
+        self.play(self.key.clone())
+    }
+
+    pub fn submit(&mut self) -> Result<()> {
+        self.play(self.submit.clone())
+    }
+
+    /// The PIN was wrong.
+    pub fn failure(&mut self) -> Result<()> {
+        self.play(self.failure.clone())
+    }
+
+    fn play(&mut self, sound: Option<Sound>) -> Result<()> {
+        match sound {
+            None => {}
+            Some(Sound::Bell(percent)) => {
+                self.conn.bell(percent)?;
+                self.conn.flush()?;
+            }
+            #[cfg(feature = "audio")]
+            Some(Sound::Wav(path)) => {
+                self.players
+                    .retain_mut(|player| matches!(player.try_wait(), Ok(None)));
+                match Command::new("aplay")
+                    .arg("-q")
+                    .arg(&path)
+                    .stdin(Stdio::null())
+                    .spawn()
+                {
+                    Ok(player) => self.players.push(player),
+                    Err(err) => warn!("Failed to play {}: {err}", path.display()),
+                }
+            }
+            // Warned about when loading the configuration
+            #[cfg(not(feature = "audio"))]
+            Some(Sound::Wav(_)) => {}
+        }
+        Ok(())
+    }
+}
+
+/// Players still running when the lock ends are waited for in the
+/// background, so that a daemon doesn't collect zombies.
+#[cfg(feature = "audio")]
+impl Drop for Sounds<'_> {
+    fn drop(&mut self) {
+        let players = std::mem::take(&mut self.players);
+        if !players.is_empty() {
+            thread::spawn(move || {
+                for mut player in players {
+                    let _ = player.wait();
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sounds_are_the_bell_or_a_file() {
+        assert_eq!(Sound::try_from("bell".to_owned()), Ok(Sound::Bell(0)));
+        assert_eq!(Sound::try_from("bell:-80".to_owned()), Ok(Sound::Bell(-80)));
+        assert!(Sound::try_from("bell:200".to_owned()).is_err());
+        assert_eq!(
+            Sound::try_from("/usr/share/sounds/click.wav".to_owned()),
+            Ok(Sound::Wav("/usr/share/sounds/click.wav".into()))
+        );
+    }
+}
@@ -0,0 +1,1011 @@
+use std::{
+    collections::HashSet,
+    time::{Duration, Instant, SystemTime},
+};
+
+use serde::Deserialize;
+use x11rb::protocol::xproto::KeyButMask;
+use zeroize::{Zeroize, Zeroizing};
+
+#[cfg(feature = "metrics")]
+use crate::metrics::Histogram;
+use crate::{
+    auth::{Authenticator, Verification},
+    config::Config,
+    keypad::Keypad,
+    led::Led,
+    persist::{self, Persisted},
+    random::RandomnessSource,
+};
+
+/// Inactivity after which input checks whether the display needs waking.
+/// Pointer motion comes in bursts, it shouldn't query the server every time.
+const BLANK_CHECK_AFTER: Duration = Duration::from_secs(1);
+
+/// How long the indicator shakes after a wrong PIN
+const SHAKE_DURATION: Duration = Duration::from_millis(300);
+
+/// How long the dots take to grow in as typing starts
+const ENTRANCE_DURATION: Duration = Duration::from_millis(150);
+
+/// Time between the frames of animations, about 60 per second
+const FRAME: Duration = Duration::from_millis(16);
+
+/// How often a PIN being checked is looked in on
+const VERIFICATION_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Bytes reserved for the input without `max_pin_length`. Longer input moves
+/// to a buffer twice the size, wiping the one it outgrew.
+const INPUT_CAPACITY: usize = 64;
+
+/// A transient message shown below the PIN field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Message {
+    IncorrectPin,
+}
+
+/// Why the lock screen went away.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UnlockReason {
+    /// The correct PIN was entered.
+    Authenticated,
+    /// A duress PIN was entered, its command is to be run after unlocking.
+    Duress { command: String },
+    /// Input arrived while the grace period was still running.
+    Grace,
+    /// Pinlock was asked to quit by SIGTERM or SIGINT.
+    Signal,
+    /// SIGUSR2 asked to unlock, with `signal_unlock`.
+    SignalUnlock,
+    /// The session was unlocked through logind.
+    #[cfg(feature = "logind")]
+    Requested,
+}
+
+/// Which characters are accepted into the PIN.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InputFilter {
+    #[default]
+    AnyChar,
+    /// Only `0` to `9`, for numeric PINs, dropping stray letters.
+    DigitsOnly,
+}
+
+impl InputFilter {
+    fn accepts(self, c: char) -> bool {
+        match self {
+            Self::AnyChar => true,
+            Self::DigitsOnly => c.is_ascii_digit(),
+        }
+    }
+}
+
+/// Everything the lock screen knows about the ongoing unlock attempt.
+pub struct LockState {
+    /// The input, wiped as it is taken back or submitted. Reserved up front,
+    /// so that typing doesn't leave copies behind in reallocated memory.
+    buffer: Zeroizing<String>,
+    /// The dots of the unlock pattern tapped so far, `'1'` to `'9'`
+    pattern: Zeroizing<String>,
+    message: Option<Message>,
+    /// When the message goes away by itself, if it does
+    message_until: Option<Instant>,
+    incorrect_pin_timeout: Option<Duration>,
+    locked_at: Instant,
+    /// Last keyboard or pointer input
+    last_activity: Instant,
+    grace: Duration,
+    /// Keypresses this soon after locking are dropped
+    input_ignore: Duration,
+    max_pin_length: Option<usize>,
+    input_filter: InputFilter,
+    /// Last known modifier state of the keyboard
+    modifiers: KeyButMask,
+    /// Keycodes pressed while locked and not released yet
+    pressed_keys: HashSet<u8>,
+    release_between_attempts: bool,
+    /// Submitting is refused until every key was released
+    awaiting_release: bool,
+    hold_to_submit: Duration,
+    /// The submit key held down with `hold_to_submit_ms`, and since when
+    submit_hold: Option<(u8, Instant)>,
+    keypad: Keypad,
+    keypad_reshuffle: bool,
+    /// What the keypad is shuffled with
+    randomness_source: RandomnessSource,
+    /// The PIN or pattern being checked, with the characters of the PIN to
+    /// shake should it be wrong
+    verification: Option<(Verification, usize)>,
+    /// How long checking PINs and patterns took
+    #[cfg(feature = "metrics")]
+    auth_times: Histogram,
+    /// Failed attempts since the last unlock or lockout
+    failures: u32,
+    max_attempts: Option<u32>,
+    /// Failed attempts since locking, unlike `failures` never reset
+    failed_attempts: u32,
+    lockout: Duration,
+    /// PIN entry is refused until then
+    lockout_until: Option<SystemTime>,
+    /// Unix times of the last unlock with a PIN and of the last wrong PIN
+    last_unlocked: Option<u64>,
+    last_failed: Option<u64>,
+    shake_on_failure: bool,
+    shake: Option<Shake>,
+    animations: bool,
+    /// When the first character of the input was entered, for the dots
+    /// to grow in
+    entrance: Option<Instant>,
+    /// Only covering the screen, until any key is pressed
+    blank: bool,
+    /// Lit keyboard LEDs, by [`Led::mask`]
+    leds: u32,
+}
+
+/// The indicator shaking after a wrong PIN.
+#[derive(Clone, Copy)]
+struct Shake {
+    started: Instant,
+    /// Characters of the wrong PIN, shown while shaking
+    len: usize,
+    /// Whether the wrong PIN started a lockout, which then only shows and
+    /// accepts input once the shake is over
+    holds_input: bool,
+}
+
+impl LockState {
+    pub fn new(config: &Config, persisted: &Persisted) -> Self {
+        // Four bytes for the longest UTF-8 characters
+        let capacity = config.max_pin_length.map_or(INPUT_CAPACITY, |max| 4 * max);
+        let mut state = Self {
+            buffer: Zeroizing::new(String::with_capacity(capacity)),
+            pattern: Zeroizing::default(),
+            message: None,
+            message_until: None,
+            incorrect_pin_timeout: config.incorrect_pin_timeout(),
+            locked_at: Instant::now(),
+            last_activity: Instant::now(),
+            grace: config.grace(),
+            input_ignore: config.input_ignore(),
+            max_pin_length: config.max_pin_length,
+            input_filter: config.input_filter,
+            modifiers: KeyButMask::default(),
+            pressed_keys: HashSet::new(),
+            release_between_attempts: config.release_between_attempts,
+            awaiting_release: false,
+            hold_to_submit: config.hold_to_submit(),
+            submit_hold: None,
+            keypad: Keypad::new(config.keypad_randomize.then_some(&config.randomness_source)),
+            randomness_source: config.randomness_source.clone(),
+            verification: None,
+            #[cfg(feature = "metrics")]
+            auth_times: Histogram::default(),
+            keypad_reshuffle: config.keypad_randomize && config.keypad_reshuffle,
+            failures: persisted.failures,
+            max_attempts: config.max_attempts,
+            failed_attempts: 0,
+            lockout: config.lockout(),
+            lockout_until: persisted.lockout_until.map(persist::from_unix),
+            last_unlocked: persisted.last_unlocked,
+            last_failed: persisted.last_failed,
+            shake_on_failure: config.shake_on_failure && config.animations,
+            shake: None,
+            animations: config.animations,
+            entrance: None,
+            blank: false,
+            leds: 0,
+        };
+
+        // A lockout carried over from before a restart can't be skipped
+        if state.is_locked_out() {
+            state.grace = Duration::ZERO;
+        }
+
+        state
+    }
+
+    /// A screen that is covered but not locked, as by an endless grace
+    /// period that pointer motion doesn't end.
+    pub fn blank(config: &Config) -> Self {
+        Self {
+            grace: Duration::MAX,
+            blank: true,
+            ..Self::new(config, &Persisted::default())
+        }
+    }
+
+    pub fn is_blank(&self) -> bool {
+        self.blank
+    }
+
+    /// The state to carry over to the next start of pinlock.
+    pub fn persisted(&self) -> Persisted {
+        Persisted {
+            failures: self.failures,
+            lockout_until: self
+                .lockout_until
+                .filter(|_| self.is_locked_out())
+                .map(persist::to_unix),
+            last_unlocked: self.last_unlocked,
+            last_failed: self.last_failed,
+        }
+    }
+
+    /// Time left until the PIN is required, `None` once the grace period is over.
+    pub fn grace_remaining(&self) -> Option<Duration> {
+        self.grace
+            .checked_sub(self.locked_at.elapsed())
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    pub fn in_grace(&self) -> bool {
+        self.grace_remaining().is_some()
+    }
+
+    /// Whether keypresses are still dropped, as they likely belong to the
+    /// hotkey that started the lock.
+    pub fn ignores_input(&self) -> bool {
+        self.locked_at.elapsed() < self.input_ignore
+    }
+
+    /// Record keyboard or pointer input. Returns whether there was none for
+    /// a while before, long enough for the display to have been blanked.
+    pub fn on_activity(&mut self) -> bool {
+        let inactive = self.last_activity.elapsed();
+        self.last_activity = Instant::now();
+        inactive >= BLANK_CHECK_AFTER
+    }
+
+    /// The layout of the on-screen keypad.
+    pub fn keypad(&self) -> &Keypad {
+        &self.keypad
+    }
+
+    /// Time since the last keyboard or pointer input.
+    pub fn inactive_for(&self) -> Duration {
+        self.last_activity.elapsed()
+    }
+
+    /// Wrong PINs submitted since locking.
+    pub fn failed_attempts(&self) -> u32 {
+        self.failed_attempts
+    }
+
+    /// Failed attempts left before a lockout, if `max_attempts` is set and
+    /// PIN entry isn't locked out already.
+    pub fn attempts_remaining(&self) -> Option<u32> {
+        let max = self.max_attempts.filter(|_| !self.is_locked_out())?;
+        Some(max.saturating_sub(self.failures))
+    }
+
+    /// Time left in a lockout after too many failed attempts.
+    pub fn lockout_remaining(&self) -> Option<Duration> {
+        self.lockout_until?
+            .duration_since(SystemTime::now())
+            .ok()
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    pub fn is_locked_out(&self) -> bool {
+        self.lockout_remaining().is_some()
+    }
+
+    /// Time until the lockout countdown shows the next second.
+    pub fn lockout_tick(&self) -> Option<Duration> {
+        self.lockout_remaining()
+            .map(|remaining| match remaining.subsec_nanos() {
+                0 => Duration::from_secs(1),
+                nanos => Duration::from_nanos(nanos.into()),
+            })
+    }
+
+    /// How far the shake after a wrong PIN got, from 0 to 1, and the
+    /// number of characters to shake. `None` when not shaking.
+    pub fn shake(&self) -> Option<(f64, usize)> {
+        let shake = self.shake?;
+        let progress = shake.started.elapsed().as_secs_f64() / SHAKE_DURATION.as_secs_f64();
+        (progress < 1.0).then_some((progress, shake.len))
+    }
+
+    /// Time until the next frame of the shake.
+    pub fn shake_frame(&self) -> Option<Duration> {
+        next_frame(self.shake?.started, SHAKE_DURATION)
+    }
+
+    /// How far the dots grew in since typing started, from 0 to 1. `None`
+    /// once they are fully shown.
+    pub fn entrance(&self) -> Option<f64> {
+        let progress = self.entrance?.elapsed().as_secs_f64() / ENTRANCE_DURATION.as_secs_f64();
+        (progress < 1.0 && !self.buffer.is_empty()).then_some(progress)
+    }
+
+    /// Time until the next frame of the dots growing in.
+    pub fn entrance_frame(&self) -> Option<Duration> {
+        self.entrance()?;
+        next_frame(self.entrance?, ENTRANCE_DURATION)
+    }
+
+    /// Whether PIN entry is refused, during a lockout and the shake that led
+    /// to it.
+    fn holds_input(&self) -> bool {
+        self.is_locked_out()
+            || self.shake.is_some_and(|shake| shake.holds_input) && self.shake().is_some()
+    }
+
+    /// Input ends the shake, unless it is held.
+    fn stop_shake(&mut self) {
+        if !self.holds_input() {
+            self.shake = None;
+        }
+    }
+
+    pub fn input_len(&self) -> usize {
+        self.buffer.chars().count()
+    }
+
+    /// Whether the input reached the maximum PIN length.
+    pub fn is_full(&self) -> bool {
+        self.max_pin_length
+            .is_some_and(|max| self.input_len() >= max)
+    }
+
+    pub fn message(&self) -> Option<Message> {
+        self.message
+    }
+
+    /// Time until the message goes away by itself.
+    pub fn message_remaining(&self) -> Option<Duration> {
+        self.message?;
+        Some(
+            self.message_until?
+                .saturating_duration_since(Instant::now()),
+        )
+    }
+
+    /// Clear the message once its timeout is up. Returns whether it was.
+    pub fn expire_message(&mut self) -> bool {
+        let expired = self
+            .message_remaining()
+            .is_some_and(|remaining| remaining.is_zero());
+        if expired {
+            self.message = None;
+            self.message_until = None;
+        }
+        expired
+    }
+
+    pub fn set_modifiers(&mut self, modifiers: KeyButMask) {
+        self.modifiers = modifiers;
+    }
+
+    pub fn modifiers(&self) -> KeyButMask {
+        self.modifiers
+    }
+
+    pub fn caps_lock(&self) -> bool {
+        self.modifiers.contains(KeyButMask::LOCK)
+    }
+
+    pub fn set_leds(&mut self, leds: u32) {
+        self.leds = leds;
+    }
+
+    /// Whether the indicator for `led` is to be shown. Caps Lock follows the
+    /// modifiers, which change along with the input.
+    pub fn indicates(&self, led: Led) -> bool {
+        match led {
+            Led::Caps => self.caps_lock(),
+            _ => self.leds & led.mask() != 0,
+        }
+    }
+
+    pub fn on_char(&mut self, c: char) {
+        if !self.input_filter.accepts(c) {
+            return;
+        }
+
+        self.message = None;
+        self.stop_shake();
+        if !self.is_full() && !self.holds_input() {
+            if self.buffer.is_empty() && self.animations {
+                self.entrance = Some(Instant::now());
+            }
+            if self.buffer.len() + c.len_utf8() > self.buffer.capacity() {
+                let mut grown = Zeroizing::new(String::with_capacity(2 * self.buffer.capacity()));
+                grown.push_str(&self.buffer);
+                self.buffer = grown;
+            }
+            self.buffer.push(c);
+        }
+    }
+
+    /// Take back the last character, wiping it.
+    pub fn on_backspace(&mut self) {
+        self.message = None;
+        self.stop_shake();
+        if let Some((index, c)) = self.buffer.char_indices().next_back() {
+            // Overwritten in place, as the same length
+            self.buffer
+                .replace_range(index.., &"\0\0\0\0"[..c.len_utf8()]);
+            self.buffer.truncate(index);
+        }
+    }
+
+    pub fn on_clear(&mut self) {
+        self.message = None;
+        self.stop_shake();
+        self.buffer.zeroize();
+        self.pattern.zeroize();
+    }
+
+    /// The input so far, leaving an empty buffer of the same size in its
+    /// place.
+    fn take_input(&mut self) -> Zeroizing<String> {
+        let capacity = self.buffer.capacity();
+        std::mem::replace(
+            &mut self.buffer,
+            Zeroizing::new(String::with_capacity(capacity)),
+        )
+    }
+
+    /// The dots of the unlock pattern tapped so far, from 0 to 8.
+    pub fn pattern(&self) -> impl Iterator<Item = usize> + '_ {
+        self.pattern.bytes().map(|dot| usize::from(dot - b'1'))
+    }
+
+    /// The dot of the unlock pattern `dot`, from 0 to 8, was tapped. Returns
+    /// whether that submits the pattern, by tapping the last dot again.
+    /// Dots tapped before are passed over otherwise.
+    pub fn on_pattern_tap(&mut self, dot: usize) -> bool {
+        if self.holds_input() || dot > 8 {
+            return false;
+        }
+        self.message = None;
+        self.stop_shake();
+        let dot = char::from(b'1' + dot as u8);
+        if self.pattern.ends_with(dot) {
+            return true;
+        }
+        if !self.pattern.contains(dot) {
+            self.pattern.push(dot);
+        }
+        false
+    }
+
+    pub fn on_key_press(&mut self, keycode: u8) {
+        self.pressed_keys.insert(keycode);
+    }
+
+    /// Whether the key is down, as far as the key events tell.
+    pub fn is_held(&self, keycode: u8) -> bool {
+        self.pressed_keys.contains(&keycode)
+    }
+
+    /// Releasing the submit key before it was held long enough submits
+    /// nothing.
+    pub fn on_key_release(&mut self, keycode: u8) {
+        self.pressed_keys.remove(&keycode);
+        if self.pressed_keys.is_empty() {
+            self.awaiting_release = false;
+        }
+        if self.submit_hold.is_some_and(|(held, _)| held == keycode) {
+            self.submit_hold = None;
+        }
+    }
+
+    /// The submit key `keycode` was pressed. Returns whether to submit
+    /// right away, or else once it was held for `hold_to_submit_ms`, see
+    /// [`Self::hold_submits`].
+    pub fn on_submit_key(&mut self, keycode: u8) -> bool {
+        if self.hold_to_submit.is_zero() {
+            return true;
+        }
+        // Auto-repeat presses the held key again
+        if self.submit_hold.is_none_or(|(held, _)| held != keycode) {
+            self.submit_hold = Some((keycode, Instant::now()));
+        }
+        false
+    }
+
+    /// How long the submit key was held, from 0 to 1 for submitting, while
+    /// it is.
+    pub fn submit_hold(&self) -> Option<f64> {
+        let (_, started) = self.submit_hold?;
+        Some((started.elapsed().as_secs_f64() / self.hold_to_submit.as_secs_f64()).min(1.0))
+    }
+
+    /// Time until the bar of the held submit key fills up further, or, once
+    /// full, right away to submit.
+    pub fn submit_hold_frame(&self) -> Option<Duration> {
+        let (_, started) = self.submit_hold?;
+        Some(next_frame(started, self.hold_to_submit).unwrap_or_default())
+    }
+
+    /// Whether the submit key was held long enough to submit, ending the
+    /// hold if so.
+    pub fn hold_submits(&mut self) -> bool {
+        let held_out = self
+            .submit_hold
+            .is_some_and(|(_, started)| started.elapsed() >= self.hold_to_submit);
+        if held_out {
+            self.submit_hold = None;
+        }
+        held_out
+    }
+
+    /// Start checking the entered PIN against the configured ones, clearing
+    /// the input, see [`Self::take_verified`]. Nothing is checked during a
+    /// lockout, or while the last PIN is still being checked, which keeps
+    /// the input. After a wrong PIN with `release_between_attempts`,
+    /// submitting is ignored until all keys were released, keeping the
+    /// input as well.
+    pub fn on_submit(&mut self, auth: &Authenticator) {
+        if self.holds_input() {
+            self.buffer.zeroize();
+            return;
+        }
+        if self.awaiting_release || self.is_verifying() {
+            return;
+        }
+
+        let len = self.input_len();
+        let input = self.take_input();
+        self.verification = Some((auth.spawn_verify(input), len));
+    }
+
+    /// Start checking the tapped unlock pattern, clearing it. Counts like a
+    /// PIN, attempts and lockouts included.
+    pub fn on_pattern_submit(&mut self, auth: &Authenticator) {
+        let pattern = std::mem::take(&mut self.pattern);
+        if self.holds_input() || self.is_verifying() {
+            return;
+        }
+        self.verification = Some((auth.spawn_verify_pattern(pattern), 0));
+    }
+
+    /// Whether a submitted PIN or pattern is still being checked. Typing
+    /// goes on meanwhile, for the next attempt.
+    pub fn is_verifying(&self) -> bool {
+        self.verification.is_some()
+    }
+
+    /// How long to wait before looking in on the PIN being checked again.
+    pub fn verification_poll(&self) -> Option<Duration> {
+        self.verification
+            .as_ref()
+            .map(|_| VERIFICATION_POLL_INTERVAL)
+    }
+
+    /// Record the outcome of the PIN or pattern being checked once it is
+    /// known: `Some` with the reason to unlock, or `None` for a wrong one,
+    /// which is also what a check taking too long counts as.
+    pub fn take_verified(&mut self) -> Option<Option<UnlockReason>> {
+        let reason = self.verification.as_ref()?.0.poll()?;
+        Some(self.finish_verification(reason))
+    }
+
+    /// [`Self::take_verified`], waiting for the outcome.
+    pub fn wait_verified(&mut self) -> Option<Option<UnlockReason>> {
+        let reason = self.verification.as_ref()?.0.wait();
+        Some(self.finish_verification(reason))
+    }
+
+    fn finish_verification(&mut self, reason: Option<UnlockReason>) -> Option<UnlockReason> {
+        let (_verification, len) = self.verification.take()?;
+        #[cfg(feature = "metrics")]
+        self.auth_times.record(_verification.elapsed());
+        self.on_verified(reason, len)
+    }
+
+    #[cfg(feature = "metrics")]
+    pub fn auth_times(&self) -> &Histogram {
+        &self.auth_times
+    }
+
+    /// Record the outcome of an attempt, with the `len` characters of a
+    /// wrong PIN to shake.
+    fn on_verified(&mut self, reason: Option<UnlockReason>, len: usize) -> Option<UnlockReason> {
+        let now = Some(persist::to_unix(SystemTime::now()));
+        if reason.is_some() {
+            self.message = None;
+            self.failures = 0;
+            self.last_unlocked = now;
+        } else {
+            self.message = Some(Message::IncorrectPin);
+            self.message_until = self
+                .incorrect_pin_timeout
+                .map(|timeout| Instant::now() + timeout);
+            self.last_failed = now;
+            self.failures += 1;
+            self.failed_attempts += 1;
+            self.awaiting_release = self.release_between_attempts && !self.pressed_keys.is_empty();
+            if self.keypad_reshuffle {
+                self.keypad.shuffle(&self.randomness_source);
+            }
+
+            let locks_out = self.max_attempts.is_some_and(|max| self.failures >= max);
+            if locks_out {
+                self.failures = 0;
+                self.lockout_until = Some(SystemTime::now() + self.lockout);
+            }
+            if self.shake_on_failure {
+                self.shake = Some(Shake {
+                    started: Instant::now(),
+                    len,
+                    holds_input: locks_out,
+                });
+            }
+        }
+
+        reason
+    }
+}
+
+/// Time until the next frame of an animation of `duration` that started at
+/// `started`, `None` once it is over.
+fn next_frame(started: Instant, duration: Duration) -> Option<Duration> {
+    duration
+        .checked_sub(started.elapsed())
+        .filter(|remaining| !remaining.is_zero())
+        .map(|remaining| remaining.min(FRAME))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::{auth::Pin, input::InputAction};
+
+    /// Submit the PIN and wait for it to be checked.
+    fn submit(state: &mut LockState, auth: &Authenticator) -> Option<UnlockReason> {
+        state.on_submit(auth);
+        state.wait_verified().flatten()
+    }
+
+    fn submit_pattern(state: &mut LockState, auth: &Authenticator) -> Option<UnlockReason> {
+        state.on_pattern_submit(auth);
+        state.wait_verified().flatten()
+    }
+
+    fn digits_only() -> LockState {
+        let config = Config {
+            input_filter: InputFilter::DigitsOnly,
+            ..Config::default()
+        };
+        LockState::new(&config, &Persisted::default())
+    }
+
+    fn type_keysym(state: &mut LockState, keysym: u32) {
+        if let InputAction::Char(c) = InputAction::from_keysym(keysym) {
+            state.on_char(c);
+        }
+    }
+
+    #[test]
+    fn digits_only_drops_letters() {
+        let mut state = digits_only();
+        for c in ['a', 'Z', 'é'] {
+            state.on_char(c);
+        }
+        assert_eq!(state.input_len(), 0);
+    }
+
+    #[test]
+    fn digits_only_drops_symbols() {
+        let mut state = digits_only();
+        for c in ['!', '#', ' ', '½', '٣'] {
+            state.on_char(c);
+        }
+        assert_eq!(state.input_len(), 0);
+    }
+
+    #[test]
+    fn digits_only_accepts_main_and_keypad_digits() {
+        let mut state = digits_only();
+        // 1 on the main row, KP_2 and KP_9 on the keypad
+        for keysym in [0x31, 0xffb2, 0xffb9] {
+            type_keysym(&mut state, keysym);
+        }
+        assert_eq!(state.input_len(), 3);
+        assert_eq!(*state.buffer, "129");
+    }
+
+    #[test]
+    fn modifiers_leave_the_input_alone() {
+        let mut state = LockState::new(&Config::default(), &Persisted::default());
+        state.on_char('1');
+        // Shift_L, Control_L and Super_L
+        for keysym in [0xffe1, 0xffe3, 0xffeb] {
+            type_keysym(&mut state, keysym);
+        }
+        assert_eq!(*state.buffer, "1");
+    }
+
+    #[test]
+    fn a_hung_auth_command_fails_and_gives_input_back() {
+        let auth =
+            Authenticator::new(&[], Some("sleep 10")).with_timeout(Duration::from_millis(200));
+        let mut state = LockState::new(&Config::default(), &Persisted::default());
+        state.on_char('1');
+
+        let started = Instant::now();
+        state.on_submit(&auth);
+        assert!(started.elapsed() < Duration::from_millis(100));
+        assert!(state.is_verifying());
+        assert_eq!(state.take_verified(), None);
+
+        // Typing goes on while the command hangs, submitting again doesn't
+        state.on_char('2');
+        state.on_submit(&auth);
+        assert_eq!(state.input_len(), 1);
+
+        while state.take_verified().is_none() {
+            assert!(started.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!state.is_verifying());
+        assert_eq!(state.failed_attempts(), 1);
+        assert_eq!(state.message(), Some(Message::IncorrectPin));
+        assert_eq!(state.input_len(), 1);
+    }
+
+    #[test]
+    fn typing_stays_in_the_reserved_buffer() {
+        let config = Config {
+            max_pin_length: Some(4),
+            ..Config::default()
+        };
+        let mut state = LockState::new(&config, &Persisted::default());
+        let reserved = state.buffer.as_ptr();
+        for _ in 0..4 {
+            state.on_char('🔒');
+        }
+        state.on_backspace();
+        assert_eq!(state.buffer.as_ptr(), reserved);
+        assert_eq!(*state.buffer, "🔒🔒🔒");
+
+        state.take_input();
+        assert!(state.buffer.capacity() >= 16);
+
+        // Without a maximum, long input moves to a bigger buffer
+        let mut state = LockState::new(&Config::default(), &Persisted::default());
+        for _ in 0..100 {
+            state.on_char('1');
+        }
+        assert_eq!(state.input_len(), 100);
+    }
+
+    #[test]
+    fn backspace_takes_back_a_whole_character() {
+        let pins = [Pin::new("pé🔒".into())];
+        let auth = Authenticator::new(&pins, None);
+        let config = Config {
+            max_pin_length: Some(3),
+            ..Config::default()
+        };
+        let mut state = LockState::new(&config, &Persisted::default());
+        // é and 🔒 as the keysyms typing them
+        for keysym in [u32::from(b'p'), 0xe9, 0x0101_f512] {
+            type_keysym(&mut state, keysym);
+        }
+        assert_eq!(state.input_len(), 3);
+        assert!(state.is_full());
+
+        state.on_backspace();
+        assert_eq!(state.input_len(), 2);
+        type_keysym(&mut state, 0x0101_f512);
+        assert_eq!(submit(&mut state, &auth), Some(UnlockReason::Authenticated));
+    }
+
+    #[test]
+    fn unlocks_and_failures_are_timed_for_the_state_file() {
+        let pins = [Pin::new("12".into())];
+        let auth = Authenticator::new(&pins, None);
+        let mut state = LockState::new(&Config::default(), &Persisted::default());
+
+        state.on_char('9');
+        submit(&mut state, &auth);
+        let persisted = state.persisted();
+        assert!(persisted.last_failed.is_some());
+        assert_eq!(persisted.last_unlocked, None);
+
+        state.on_char('1');
+        state.on_char('2');
+        submit(&mut state, &auth);
+        assert!(state.persisted().last_unlocked.is_some());
+    }
+
+    #[test]
+    fn releasing_the_submit_key_early_submits_nothing() {
+        let config = Config {
+            hold_to_submit_ms: 60_000,
+            ..Config::default()
+        };
+        let mut state = LockState::new(&config, &Persisted::default());
+        const ENTER: u8 = 36;
+
+        assert!(!state.on_submit_key(ENTER));
+        assert_eq!(state.submit_hold().map(|held| held < 1.0), Some(true));
+        assert!(!state.hold_submits());
+        state.on_key_release(ENTER);
+        assert_eq!(state.submit_hold(), None);
+
+        let mut instant = LockState::new(&Config::default(), &Persisted::default());
+        assert!(instant.on_submit_key(ENTER));
+        assert_eq!(instant.submit_hold(), None);
+    }
+
+    #[test]
+    fn the_wrong_pin_message_times_out() {
+        let config = Config {
+            incorrect_pin_timeout_ms: 60_000,
+            ..Config::default()
+        };
+        let pins = [Pin::new("12".into())];
+        let auth = Authenticator::new(&pins, None);
+        let mut state = LockState::new(&config, &Persisted::default());
+
+        state.on_char('9');
+        submit(&mut state, &auth);
+        assert!(state.message_remaining().is_some());
+        assert!(!state.expire_message());
+        assert_eq!(state.message(), Some(Message::IncorrectPin));
+
+        state.message_until = Some(Instant::now());
+        assert!(state.expire_message());
+        assert_eq!(state.message(), None);
+        assert_eq!(state.message_remaining(), None);
+
+        // Without a timeout it stays until typing
+        let mut state = LockState::new(&Config::default(), &Persisted::default());
+        submit(&mut state, &auth);
+        assert_eq!(state.message_remaining(), None);
+        assert!(!state.expire_message());
+    }
+
+    #[test]
+    fn tapping_the_last_dot_again_submits_the_pattern() {
+        let pattern = "157".to_owned().into();
+        let auth = Authenticator::new(&[], None).with_pattern(Some(&pattern));
+        let mut state = LockState::new(&Config::default(), &Persisted::default());
+
+        for dot in [0, 4, 0, 7] {
+            assert!(!state.on_pattern_tap(dot));
+        }
+        assert_eq!(state.pattern().collect::<Vec<_>>(), [0, 4, 7]);
+        assert!(state.on_pattern_tap(7));
+        assert_eq!(submit_pattern(&mut state, &auth), None);
+        assert_eq!(state.failed_attempts(), 1);
+        assert_eq!(state.pattern().count(), 0);
+
+        for dot in [0, 4, 6] {
+            state.on_pattern_tap(dot);
+        }
+        assert!(state.on_pattern_tap(6));
+        assert_eq!(
+            submit_pattern(&mut state, &auth),
+            Some(UnlockReason::Authenticated)
+        );
+    }
+
+    #[test]
+    fn any_char_accepts_letters() {
+        let mut state = LockState::new(&Config::default(), &Persisted::default());
+        state.on_char('a');
+        assert_eq!(state.input_len(), 1);
+    }
+
+    #[test]
+    fn release_between_attempts_refuses_submitting_while_a_key_is_held() {
+        let config = Config {
+            release_between_attempts: true,
+            ..Config::default()
+        };
+        let pins = [Pin::new("12".into())];
+        let auth = Authenticator::new(&pins, None);
+        let mut state = LockState::new(&config, &Persisted::default());
+        const ENTER: u8 = 36;
+
+        state.on_char('9');
+        state.on_key_press(ENTER);
+        assert_eq!(submit(&mut state, &auth), None);
+        assert_eq!(state.failed_attempts(), 1);
+
+        // Enter is still held after the wrong PIN
+        state.on_char('1');
+        state.on_char('2');
+        assert_eq!(submit(&mut state, &auth), None);
+        assert_eq!(state.failed_attempts(), 1);
+
+        state.on_key_release(ENTER);
+        state.on_key_press(ENTER);
+        assert_eq!(submit(&mut state, &auth), Some(UnlockReason::Authenticated));
+    }
+
+    #[test]
+    fn typing_ends_the_shake_unless_it_leads_to_a_lockout() {
+        let pins = [Pin::new("12".into())];
+        let auth = Authenticator::new(&pins, None);
+        let config = Config {
+            shake_on_failure: true,
+            ..Config::default()
+        };
+        let mut state = LockState::new(&config, &Persisted::default());
+        state.on_char('9');
+        submit(&mut state, &auth);
+        assert_eq!(state.shake().map(|(_, len)| len), Some(1));
+        state.on_char('1');
+        assert!(state.shake().is_none());
+        assert_eq!(state.input_len(), 1);
+
+        // A lockout too short to outlast the shake
+        let config = Config {
+            max_attempts: Some(1),
+            lockout_secs: 0,
+            ..config
+        };
+        let mut state = LockState::new(&config, &Persisted::default());
+        state.on_char('9');
+        submit(&mut state, &auth);
+        state.on_char('1');
+        assert!(state.shake().is_some());
+        assert_eq!(state.input_len(), 0);
+    }
+
+    #[test]
+    fn the_remaining_attempts_count_down_to_the_lockout() {
+        let pins = [Pin::new("12".into())];
+        let auth = Authenticator::new(&pins, None);
+        let mut state = LockState::new(&Config::default(), &Persisted::default());
+        state.on_char('9');
+        submit(&mut state, &auth);
+        assert_eq!(state.attempts_remaining(), None);
+
+        let config = Config {
+            max_attempts: Some(3),
+            ..Config::default()
+        };
+        let mut state = LockState::new(&config, &Persisted::default());
+        assert_eq!(state.attempts_remaining(), Some(3));
+        for remaining in [2, 1] {
+            state.on_char('9');
+            submit(&mut state, &auth);
+            assert_eq!(state.attempts_remaining(), Some(remaining));
+        }
+        state.on_char('9');
+        submit(&mut state, &auth);
+        assert_eq!(state.attempts_remaining(), None);
+    }
+
+    #[test]
+    fn lock_indicators_follow_the_leds_but_caps_lock_the_modifiers() {
+        let mut state = LockState::new(&Config::default(), &Persisted::default());
+        state.set_leds(Led::Num.mask() | Led::Caps.mask());
+        assert!(state.indicates(Led::Num));
+        assert!(!state.indicates(Led::Scroll));
+        assert!(!state.indicates(Led::Caps));
+
+        state.set_modifiers(KeyButMask::LOCK);
+        assert!(state.indicates(Led::Caps));
+    }
+
+    #[test]
+    fn the_dots_grow_in_on_the_first_character_only_with_animations() {
+        let mut state = LockState::new(&Config::default(), &Persisted::default());
+        assert!(state.entrance().is_none());
+        state.on_char('1');
+        assert!(state.entrance().is_some());
+        state.on_backspace();
+        assert!(state.entrance().is_none());
+
+        let config = Config {
+            animations: false,
+            ..Config::default()
+        };
+        let mut state = LockState::new(&config, &Persisted::default());
+        state.on_char('1');
+        assert!(state.entrance().is_none());
+    }
+}
@@ -0,0 +1,90 @@
+//! Facts about the machine for the `sysinfo` widget.
+//!
+//! Uptime and load come from `/proc`, which only Linux has. Where it's
+//! missing, their lines are left out.
+
+use std::{ffi::CStr, fs, time::Duration};
+
+use serde::Deserialize;
+
+/// A line of the `sysinfo` widget, as listed in `info_lines`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InfoLine {
+    Hostname,
+    /// Time since booting, e.g. `up 3 days, 4:05`.
+    Uptime,
+    /// Load averages over 1, 5 and 15 minutes.
+    Load,
+}
+
+impl InfoLine {
+    /// The current text of the line, `None` if it can't be told.
+    pub fn text(self) -> Option<String> {
+        match self {
+            Self::Hostname => hostname(),
+            Self::Uptime => {
+                let uptime = parse_uptime(&fs::read_to_string("/proc/uptime").ok()?)?;
+                Some(format_uptime(uptime))
+            }
+            Self::Load => parse_load(&fs::read_to_string("/proc/loadavg").ok()?),
+        }
+    }
+}
+
+/// The name of the machine, `None` if it can't be read.
+pub fn hostname() -> Option<String> {
+    let mut buffer = [0u8; 256];
+    // SAFETY: the buffer outlives the call and its length is passed along
+    if unsafe { libc::gethostname(buffer.as_mut_ptr().cast(), buffer.len()) } != 0 {
+        return None;
+    }
+    // Not terminated if it was truncated
+    *buffer.last_mut()? = 0;
+    let name = CStr::from_bytes_until_nul(&buffer).ok()?;
+    Some(name.to_string_lossy().into_owned())
+}
+
+/// The first field of `/proc/uptime`, seconds since booting.
+fn parse_uptime(contents: &str) -> Option<Duration> {
+    let seconds: f64 = contents.split_whitespace().next()?.parse().ok()?;
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+fn format_uptime(uptime: Duration) -> String {
+    let minutes = uptime.as_secs() / 60;
+    let (days, hours, minutes) = (minutes / (24 * 60), minutes / 60 % 24, minutes % 60);
+    match days {
+        0 => format!("up {hours}:{minutes:02}"),
+        1 => format!("up 1 day, {hours}:{minutes:02}"),
+        days => format!("up {days} days, {hours}:{minutes:02}"),
+    }
+}
+
+/// The first three fields of `/proc/loadavg`.
+fn parse_load(contents: &str) -> Option<String> {
+    let averages: Vec<&str> = contents.split_whitespace().take(3).collect();
+    (averages.len() == 3).then(|| format!("load {}", averages.join(" ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uptime_is_shown_in_days_hours_and_minutes() {
+        let uptime = parse_uptime("273900.42 1031940.16\n").unwrap();
+        assert_eq!(format_uptime(uptime), "up 3 days, 4:05");
+        assert_eq!(format_uptime(Duration::from_secs(90_000)), "up 1 day, 1:00");
+        assert_eq!(format_uptime(Duration::from_secs(59)), "up 0:00");
+    }
+
+    #[test]
+    fn load_takes_the_three_averages() {
+        assert_eq!(
+            parse_load("0.52 0.58 0.59 1/467 12345\n").as_deref(),
+            Some("load 0.52 0.58 0.59")
+        );
+        assert_eq!(parse_load(""), None);
+    }
+}
@@ -0,0 +1,282 @@
+use std::{collections::HashMap, fmt};
+
+use anyhow::{Context, Result};
+use serde::{de, Deserialize, Deserializer};
+
+/// A 24-bit RGB color, written as `"#rrggbb"` in the configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color(pub u32);
+
+impl Color {
+    /// The pixel value on a 24-bit TrueColor visual.
+    pub fn pixel(self) -> u32 {
+        self.0
+    }
+
+    /// This color moved `amount` of the way towards `other`, from 0 for
+    /// this color to 1 for `other`.
+    pub fn blend(self, other: Color, amount: f64) -> Color {
+        let channel = |color: Color, shift: u32| f64::from((color.0 >> shift) & 0xff);
+        Color([16, 8, 0].into_iter().fold(0, |blended, shift| {
+            let from = channel(self, shift);
+            let value = from + (channel(other, shift) - from) * amount.clamp(0.0, 1.0);
+            blended | (value.round() as u32) << shift
+        }))
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ColorVisitor;
+
+        impl de::Visitor<'_> for ColorVisitor {
+            type Value = Color;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a color like \"#rrggbb\"")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Color, E> {
+                value
+                    .strip_prefix('#')
+                    .filter(|hex| hex.len() == 6)
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .map(Color)
+                    .ok_or_else(|| E::invalid_value(de::Unexpected::Str(value), &self))
+            }
+        }
+
+        deserializer.deserialize_str(ColorVisitor)
+    }
+}
+
+/// Colors of the lock screen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Theme {
+    pub background: Color,
+    /// Text and PIN dots.
+    pub foreground: Color,
+    /// PIN dots once `max_pin_length` characters have been entered.
+    pub ready: Color,
+    /// The filled part of `indicator_style = "bar"`, the foreground unless
+    /// set.
+    pub bar: Option<Color>,
+    /// The `show_hostname` label, the foreground unless set.
+    pub hostname: Option<Color>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            background: Color(0x00001f),
+            foreground: Color(0xffffff),
+            ready: Color(0x4caf50),
+            bar: None,
+            hostname: None,
+        }
+    }
+}
+
+/// Names of the built-in presets.
+pub const PRESETS: &[&str] = &["dark", "light", "nord", "dracula", "high_contrast"];
+
+impl Theme {
+    /// White on black, with yellow for `ready`, for the `accessibility` mode.
+    pub fn high_contrast() -> Self {
+        Self {
+            background: Color(0x000000),
+            foreground: Color(0xffffff),
+            ready: Color(0xffff00),
+            bar: None,
+            hostname: None,
+        }
+    }
+
+    /// The built-in preset of one of the [`PRESETS`] names. `dark` is the
+    /// default theme.
+    pub fn preset(name: &str) -> Option<Self> {
+        let (background, foreground, ready) = match name {
+            "dark" => return Some(Self::default()),
+            "high_contrast" => return Some(Self::high_contrast()),
+            "light" => (0xfafafa, 0x212121, 0x388e3c),
+            "nord" => (0x2e3440, 0xeceff4, 0xa3be8c),
+            "dracula" => (0x282a36, 0xf8f8f2, 0x50fa7b),
+            _ => return None,
+        };
+        Some(Self {
+            background: Color(background),
+            foreground: Color(foreground),
+            ready: Color(ready),
+            bar: None,
+            hostname: None,
+        })
+    }
+}
+
+/// A theme as configured: `theme = "nord"` selects a preset, or a `[theme]`
+/// table sets colors, optionally on top of a `preset`. Colors left out are
+/// taken from the preset, or from the base theme without one.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(remote = "Self", default, deny_unknown_fields)]
+pub struct ThemeConfig {
+    /// A built-in preset, or one of the custom `[themes.<name>]`.
+    pub preset: Option<String>,
+    pub background: Option<Color>,
+    pub foreground: Option<Color>,
+    pub ready: Option<Color>,
+    pub bar: Option<Color>,
+    pub hostname: Option<Color>,
+}
+
+impl ThemeConfig {
+    /// The colors to use, those set here on top of the selected preset or
+    /// `base`. Presets are looked up among the custom `themes` first, whose
+    /// own `preset` can only name a built-in one.
+    pub fn resolve(&self, themes: &HashMap<String, ThemeConfig>, base: Theme) -> Result<Theme> {
+        let preset = match self.preset.as_deref() {
+            None => base,
+            Some(name) => match themes.get(name) {
+                Some(custom) => {
+                    let custom_base = match custom.preset.as_deref() {
+                        Some(name) => built_in(name)?,
+                        None => Theme::default(),
+                    };
+                    custom.apply(custom_base)
+                }
+                None => built_in(name)?,
+            },
+        };
+        Ok(self.apply(preset))
+    }
+
+    /// `base` with the colors set in the section replacing its own.
+    fn apply(&self, base: Theme) -> Theme {
+        Theme {
+            background: self.background.unwrap_or(base.background),
+            foreground: self.foreground.unwrap_or(base.foreground),
+            ready: self.ready.unwrap_or(base.ready),
+            bar: self.bar.or(base.bar),
+            hostname: self.hostname.or(base.hostname),
+        }
+    }
+}
+
+fn built_in(name: &str) -> Result<Theme> {
+    Theme::preset(name).with_context(|| {
+        format!(
+            "Unknown theme `{name}`, the built-in ones are {}",
+            PRESETS.join(", ")
+        )
+    })
+}
+
+/// `theme = "nord"` is shorthand for `[theme] preset = "nord"`.
+impl<'de> Deserialize<'de> for ThemeConfig {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ThemeVisitor;
+
+        impl<'de> de::Visitor<'de> for ThemeVisitor {
+            type Value = ThemeConfig;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("the name of a theme or a table of colors")
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<ThemeConfig, E> {
+                Ok(ThemeConfig {
+                    preset: Some(value.to_owned()),
+                    ..ThemeConfig::default()
+                })
+            }
+
+            fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<ThemeConfig, A::Error> {
+                ThemeConfig::deserialize(de::value::MapAccessDeserializer::new(map))
+            }
+        }
+
+        deserializer.deserialize_any(ThemeVisitor)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Themes {
+        theme: ThemeConfig,
+        #[serde(default)]
+        themes: HashMap<String, ThemeConfig>,
+    }
+
+    fn resolve(toml: &str) -> Result<Theme> {
+        let themes: Themes = toml::from_str(toml).unwrap();
+        themes.theme.resolve(&themes.themes, Theme::high_contrast())
+    }
+
+    #[test]
+    fn colors_set_in_the_section_override_the_base_theme() {
+        let theme = resolve("[theme]\nready = \"#00ff00\"").unwrap();
+
+        assert_eq!(theme.ready, Color(0x00ff00));
+        assert_eq!(theme.background, Theme::high_contrast().background);
+        assert_eq!(theme.foreground, Theme::high_contrast().foreground);
+    }
+
+    #[test]
+    fn the_bar_is_only_colored_apart_when_set() {
+        assert_eq!(resolve(r#"theme = "nord""#).unwrap().bar, None);
+        let theme = resolve("[theme]\npreset = \"nord\"\nbar = \"#4488ff\"").unwrap();
+        assert_eq!(theme.bar, Some(Color(0x4488ff)));
+        assert_eq!(theme.hostname, None);
+    }
+
+    #[test]
+    fn a_name_selects_a_built_in_preset() {
+        assert_eq!(
+            resolve(r#"theme = "nord""#).unwrap(),
+            Theme::preset("nord").unwrap()
+        );
+    }
+
+    #[test]
+    fn colors_set_in_the_section_override_the_preset() {
+        let theme = resolve("[theme]\npreset = \"nord\"\nready = \"#00ff00\"").unwrap();
+
+        assert_eq!(theme.ready, Color(0x00ff00));
+        assert_eq!(theme.background, Theme::preset("nord").unwrap().background);
+    }
+
+    #[test]
+    fn custom_themes_apply_to_their_own_preset() {
+        let theme = resolve(
+            r##"
+            theme = "mine"
+
+            [themes.mine]
+            preset = "dracula"
+            foreground = "#ff0000"
+            "##,
+        )
+        .unwrap();
+
+        assert_eq!(theme.foreground, Color(0xff0000));
+        assert_eq!(
+            theme.background,
+            Theme::preset("dracula").unwrap().background
+        );
+    }
+
+    #[test]
+    fn unknown_names_are_rejected() {
+        assert!(resolve(r#"theme = "solarized""#).is_err());
+        assert!(resolve("theme = \"mine\"\n[themes.mine]\npreset = \"solarized\"").is_err());
+    }
+
+    #[test]
+    fn blending_moves_each_channel_towards_the_other_color() {
+        let white = Color(0xffffff);
+        assert_eq!(white.blend(Color(0x000000), 0.0), white);
+        assert_eq!(white.blend(Color(0x000000), 1.0), Color(0x000000));
+        assert_eq!(Color(0xff0080).blend(Color(0x00ff80), 0.5), Color(0x808080));
+    }
+}
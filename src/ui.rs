@@ -0,0 +1,1553 @@
+use std::{cell::Cell, time::Duration};
+#[cfg(feature = "metrics")]
+use std::{cell::RefCell, time::Instant};
+
+use anyhow::Result;
+use serde::Deserialize;
+use x11rb::{
+    protocol::xproto::{Arc, ConnectionExt, Point, Rectangle},
+    rust_connection::RustConnection,
+};
+
+#[cfg(feature = "metrics")]
+use crate::metrics::Histogram;
+use crate::{
+    backend::{Backend, XBackend},
+    config::{Config, Strings},
+    image::{self, Encoded},
+    indicator::{BarPosition, Frame, Indicator, IndicatorBar, Orientation},
+    keypad::Keypad,
+    led::Led,
+    screensaver::Idle,
+    state::{LockState, Message},
+    theme::{Color, Theme},
+    widget::{Rect, Region, Widget},
+    window::Window,
+};
+
+/// What a frame is made of, drawn into the back buffer in the order of
+/// [`LAYERS`] after covering it with the background, so that a layer covers
+/// those before it where they overlap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Layer {
+    /// The widgets in the corners of the screen
+    Widgets,
+    /// The card behind the PIN field, with `card`
+    Card,
+    /// The `banner_text`, at `banner_position`
+    Banner,
+    /// The PIN field, its indicators and messages, see
+    /// [`Ui::draw_pin_field`]
+    PinField,
+    /// The on-screen buttons or keypad
+    Buttons,
+    /// The dots of the unlock pattern
+    Pattern,
+}
+
+/// Layers from the bottom up. A new layer goes where it is to cover the
+/// ones before it and be covered by the ones after it.
+const LAYERS: [Layer; 6] = [
+    Layer::Widgets,
+    Layer::Card,
+    Layer::Banner,
+    Layer::PinField,
+    Layer::Buttons,
+    Layer::Pattern,
+];
+
+const DOT_RADIUS: i16 = 8;
+const DOT_SPACING: i16 = 24;
+
+/// The DPI at which UI elements have their nominal size.
+const BASE_DPI: f64 = 96.0;
+const SCALE_RANGE: (f64, f64) = (0.5, 4.0);
+
+/// Extra scale of the `accessibility` mode, on top of the detected one.
+const ACCESSIBLE_ENLARGEMENT: f64 = 1.5;
+
+/// Where the banner is placed on the screen.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BannerPosition {
+    Top,
+    /// Above the PIN field.
+    Center,
+    #[default]
+    Bottom,
+}
+
+/// Where the PIN field and what is grouped with it are placed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UiAnchor {
+    /// The center of the screen.
+    #[default]
+    Center,
+    /// Where the pointer was when locking, as far as it fits on the screen.
+    Pointer,
+}
+
+/// Which monitors the UI is shown on, the others only showing the
+/// background.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UiOn {
+    /// The primary monitor, or the first one without a primary.
+    #[default]
+    Primary,
+    /// Every monitor, each with its own PIN field, clock and widgets.
+    All,
+    /// The monitor the pointer was on when locking.
+    Focused,
+}
+
+/// How the entered characters are represented on screen.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IndicatorStyle {
+    /// A dot per character.
+    #[default]
+    Dots,
+    /// The number of characters, easier to read for low-vision users.
+    Count,
+    /// A bar filling up towards `max_pin_length`, for long PINs of a fixed
+    /// length.
+    Bar,
+    /// Nothing at all, not even revealing the length.
+    None,
+}
+
+/// On-screen buttons, shown below the PIN field with the `buttons` or
+/// `keypad` option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Button {
+    Submit,
+    Clear,
+    /// A key of the keypad.
+    Digit(char),
+}
+
+/// The panel behind the center of the lock screen, with the `card` option.
+struct Card {
+    color: Color,
+    radius: i16,
+    padding: i16,
+}
+
+/// What the lock screen shows that can change without input, to tell when
+/// redrawing only part of it would leave something stale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Layout {
+    dimmed: bool,
+    grace: bool,
+    lockout_secs: Option<u64>,
+    /// Changes outside of the PIN field after a wrong PIN
+    keypad: Keypad,
+    /// Dots of the unlock pattern tapped, cleared along with the PIN
+    pattern: usize,
+}
+
+impl Layout {
+    fn of(state: &LockState, dimmed: bool) -> Self {
+        Self {
+            dimmed,
+            grace: state.in_grace(),
+            lockout_secs: state
+                .lockout_remaining()
+                .map(|remaining| remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0)),
+            keypad: *state.keypad(),
+            pattern: state.pattern().count(),
+        }
+    }
+}
+
+/// Draws the lock screen contents with a [`Backend`], usually into the
+/// lock window.
+///
+/// Each frame is composed and then shown in one go, so partially drawn
+/// frames are never visible.
+pub struct Ui<B> {
+    backend: B,
+    width: u16,
+    height: u16,
+    strings: Strings,
+    theme: Theme,
+    /// Inactivity after which the lock screen dims, and how much
+    dim: Option<(Duration, f64)>,
+    /// Whether the last frame was drawn in the dimmed foreground
+    dimmed: Cell<bool>,
+    ready_indicator: bool,
+    show_remaining_attempts: bool,
+    indicator_bar: IndicatorBar,
+    indicator_style: IndicatorStyle,
+    max_pin_length: Option<usize>,
+    /// Height of the bar of `IndicatorStyle::Bar`
+    progress_bar_height: i16,
+    buttons: bool,
+    keypad: bool,
+    /// Whether the grid of the unlock pattern is shown
+    pattern: bool,
+    banner: Vec<String>,
+    banner_position: BannerPosition,
+    /// Distance between the baselines of two lines of text
+    line_height: i16,
+    dot_radius: i16,
+    dot_spacing: i16,
+    scale: f64,
+    card: Option<Card>,
+    anchor: UiAnchor,
+    /// Where the pointer was when locking
+    pointer: Option<(i16, i16)>,
+    ui_on: UiOn,
+    /// The monitors the UI is shown on
+    screens: Vec<Rect>,
+    /// Each of the screens with the point the PIN field is centered on
+    places: Vec<(Rect, (i16, i16))>,
+    /// The screen being drawn or looked at, and its center
+    area: Cell<Rect>,
+    center: Cell<(i16, i16)>,
+    /// The layout of the last full frame, unset when something else was
+    /// drawn since or the look changed
+    drawn_layout: Cell<Option<Layout>>,
+    /// When the frame being drawn was started
+    #[cfg(feature = "metrics")]
+    frame_started: Cell<Option<Instant>>,
+    /// How long frames took, from clearing to presenting them
+    #[cfg(feature = "metrics")]
+    frame_times: RefCell<Histogram>,
+}
+
+impl<'connection> Ui<XBackend<'connection>> {
+    /// Draw on `window`, over `background_image` if there is one.
+    pub fn new(
+        connection: &'connection RustConnection,
+        window: &Window,
+        config: &Config,
+        background_image: Option<Encoded>,
+    ) -> Result<Self> {
+        let scale = scale(config, || detect_scale(connection, window));
+        let backend = XBackend::new(connection, window, config, background_image, scale)?;
+
+        // Only where it was at the start, the UI doesn't follow it around
+        let pointer = if config.ui_anchor == UiAnchor::Pointer || config.ui_on == UiOn::Focused {
+            let reply = connection.query_pointer(window.id)?.reply()?;
+            reply.same_screen.then_some((reply.win_x, reply.win_y))
+        } else {
+            None
+        };
+        let screens = screens(connection, window, config.ui_on, pointer);
+
+        Self::with_backend(
+            backend,
+            (window.width, window.height),
+            screens,
+            scale,
+            pointer,
+            config,
+        )
+    }
+
+    /// Adapt to a new size of the window, recreating the back buffer and
+    /// background to match. The layout follows on the next draw.
+    pub fn resize(&mut self, window: &Window, config: &Config) -> Result<()> {
+        if (window.width, window.height) == (self.width, self.height) {
+            return Ok(());
+        }
+
+        self.backend.resize(window, config)?;
+        (self.width, self.height) = (window.width, window.height);
+        self.screens = screens(self.backend.connection(), window, self.ui_on, self.pointer);
+        self.place();
+        self.drawn_layout.set(None);
+        Ok(())
+    }
+
+    /// Show the background image on the window before anything is drawn.
+    pub fn show_background(&self) -> Result<()> {
+        self.backend.show_background()
+    }
+
+    /// How long to wait before checking on the background image again,
+    /// while it is being prepared.
+    pub fn background_poll(&self) -> Option<Duration> {
+        self.backend.background_poll()
+    }
+
+    /// Swap in the background image if it is ready. Returns whether it was,
+    /// so that the caller redraws.
+    pub fn update_background(&mut self) -> Result<bool> {
+        let updated = self.backend.update_background()?;
+        if updated {
+            self.drawn_layout.set(None);
+        }
+        Ok(updated)
+    }
+}
+
+impl<B: Backend> Ui<B> {
+    /// Draw with `backend` on an area of `width` by `height`, showing the UI
+    /// on each of `screens`, with UI elements at `scale`. `pointer` is where
+    /// it was when locking.
+    pub fn with_backend(
+        backend: B,
+        (width, height): (u16, u16),
+        screens: Vec<Rect>,
+        scale: f64,
+        pointer: Option<(i16, i16)>,
+        config: &Config,
+    ) -> Result<Self> {
+        let scaled = |size: i16| (f64::from(size) * scale).round() as i16;
+        let line_height = backend.line_height();
+
+        let mut ui = Self {
+            backend,
+            width,
+            height,
+            strings: Strings::default(),
+            theme: Theme::default(),
+            dim: None,
+            dimmed: Cell::new(false),
+            ready_indicator: false,
+            show_remaining_attempts: false,
+            indicator_bar: IndicatorBar::new(
+                BarPosition::default(),
+                Orientation::default(),
+                Vec::new(),
+            ),
+            indicator_style: IndicatorStyle::default(),
+            max_pin_length: None,
+            progress_bar_height: 0,
+            buttons: false,
+            keypad: false,
+            pattern: false,
+            banner: Vec::new(),
+            banner_position: BannerPosition::default(),
+            line_height,
+            dot_radius: scaled(DOT_RADIUS),
+            dot_spacing: scaled(DOT_SPACING),
+            scale,
+            card: None,
+            anchor: config.ui_anchor,
+            pointer,
+            ui_on: config.ui_on,
+            screens,
+            places: Vec::new(),
+            area: Cell::new(Rect {
+                x: 0,
+                y: 0,
+                width,
+                height,
+            }),
+            center: Cell::new(((width / 2) as i16, (height / 2) as i16)),
+            drawn_layout: Cell::new(None),
+            #[cfg(feature = "metrics")]
+            frame_started: Cell::new(None),
+            #[cfg(feature = "metrics")]
+            frame_times: RefCell::default(),
+        };
+        ui.apply_config(config)?;
+
+        Ok(ui)
+    }
+
+    /// What the lock screen is drawn with.
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Take over the texts and colors of a new configuration.
+    pub fn apply_config(&mut self, config: &Config) -> Result<()> {
+        self.drawn_layout.set(None);
+        self.strings = config.strings.clone();
+        self.theme = config.theme.clone();
+        self.dim = config.dim().map(|delay| (delay, config.dim_level));
+        self.dimmed.set(false);
+        self.ready_indicator = config.ready_indicator;
+        self.show_remaining_attempts = config.show_remaining_attempts;
+        self.indicator_bar = IndicatorBar::new(
+            config.lock_indicators_position,
+            config.lock_indicators_orientation,
+            config
+                .lock_indicators
+                .iter()
+                .map(|&led| {
+                    let text = match led {
+                        Led::Caps => self.strings.caps_lock.clone(),
+                        Led::Num => self.strings.num_lock.clone(),
+                        Led::Scroll => self.strings.scroll_lock.clone(),
+                    };
+                    let color = self.theme.foreground;
+                    Box::new(move |state: &LockState| {
+                        state.indicates(led).then(|| (text.clone(), color))
+                    }) as Indicator
+                })
+                .collect(),
+        );
+        self.indicator_style = config.indicator_style;
+        self.buttons = config.buttons;
+        self.keypad = config.keypad;
+        self.pattern = config.pattern.is_some();
+        self.banner = config.banner_text.lines().map(str::to_owned).collect();
+        self.banner_position = config.banner_position;
+        let scaled = |size: u16| (f64::from(size) * self.scale).round() as i16;
+        self.card = config.card.then(|| Card {
+            color: self.theme.background.blend(
+                config.card_color.unwrap_or(self.theme.foreground),
+                config.card_opacity,
+            ),
+            radius: scaled(config.card_radius),
+            padding: scaled(config.card_padding),
+        });
+        self.max_pin_length = config.max_pin_length;
+        self.progress_bar_height = config
+            .progress_bar_height
+            .map_or(2 * self.dot_radius, scaled)
+            // Within the band of the PIN field, which typing redraws
+            .clamp(1, 6 * self.dot_radius);
+        self.anchor = config.ui_anchor;
+        self.place();
+        Ok(())
+    }
+
+    /// Repaint the whole window to reflect the current state.
+    pub fn draw(&self, state: &LockState, widgets: &[(Region, Box<dyn Widget>)]) -> Result<()> {
+        self.dimmed.set(self.dims(state));
+        self.drawn_layout
+            .set(Some(Layout::of(state, self.dimmed.get())));
+        self.clear()?;
+        for result in self.at_each_place(|| self.draw_place(state, widgets)) {
+            result?;
+        }
+        self.present()
+    }
+
+    /// Draw the UI on the current screen, a layer after the other in the
+    /// order of [`LAYERS`].
+    fn draw_place(&self, state: &LockState, widgets: &[(Region, Box<dyn Widget>)]) -> Result<()> {
+        for layer in LAYERS {
+            self.draw_layer(layer, state, widgets)?;
+        }
+        Ok(())
+    }
+
+    fn draw_layer(
+        &self,
+        layer: Layer,
+        state: &LockState,
+        widgets: &[(Region, Box<dyn Widget>)],
+    ) -> Result<()> {
+        let center_y = self.center.get().1;
+        match layer {
+            Layer::Widgets => self.draw_widgets(widgets),
+            Layer::Card => match &self.card {
+                Some(card) => self.draw_card(card),
+                None => Ok(()),
+            },
+            Layer::Banner => self.draw_banner(center_y),
+            Layer::PinField => self.draw_pin_field(state, center_y),
+            Layer::Buttons if self.shows_buttons(state) => self.draw_buttons(state),
+            Layer::Pattern if self.shows_pattern(state) => self.draw_pattern(state),
+            Layer::Buttons | Layer::Pattern => Ok(()),
+        }
+    }
+
+    /// The PIN field with what goes along with it: the lock key warnings
+    /// above, the dots, the hold bar and the message below, or whatever
+    /// takes their place while blanked, in grace, shaking or locked out.
+    fn draw_pin_field(&self, state: &LockState, center_y: i16) -> Result<()> {
+        if state.is_blank() {
+            self.draw_text(&self.strings.blanked, center_y)?;
+        } else if state.in_grace() {
+            self.draw_text(&self.strings.press_any_key, center_y)?;
+        } else if let Some((progress, len)) = state.shake() {
+            // The wrong PIN, before a lockout replaces it
+            let offset = shake_offset(progress, self.dot_spacing);
+            self.draw_indicator(len, false, center_y, offset, 1.0)?;
+            self.draw_text(&self.failure_text(state), center_y + 4 * self.dot_radius)?;
+        } else if let Some(remaining) = state.lockout_remaining() {
+            let seconds = remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0);
+            let text = self
+                .strings
+                .locked_out
+                .replace("{seconds}", &seconds.to_string());
+            self.draw_text(&text, center_y)?;
+        } else {
+            self.draw_indicator_bar(state, center_y)?;
+
+            let ready = self.ready_indicator && state.is_full();
+            let shown = state.entrance().map_or(1.0, ease_out);
+            self.draw_indicator(state.input_len(), ready, center_y, 0, shown)?;
+            if let Some(held) = state.submit_hold() {
+                self.draw_hold_bar(held, center_y)?;
+            }
+
+            if state.is_verifying() {
+                self.draw_text(&self.strings.verifying, center_y + 4 * self.dot_radius)?;
+            } else if let Some(message) = state.message() {
+                let text = match message {
+                    Message::IncorrectPin => self.failure_text(state),
+                };
+                self.draw_text(&text, center_y + 4 * self.dot_radius)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// What a wrong PIN is met with, telling the attempts left with
+    /// `show_remaining_attempts`.
+    fn failure_text(&self, state: &LockState) -> String {
+        match state
+            .attempts_remaining()
+            .filter(|_| self.show_remaining_attempts)
+        {
+            Some(remaining) => self
+                .strings
+                .remaining_attempts
+                .replace("{remaining}", &remaining.to_string()),
+            None => self.strings.incorrect_pin.clone(),
+        }
+    }
+
+    /// Whether the last frame no longer shows `state`, apart from the input
+    /// and the widgets, as the time that passed changed what it shows.
+    pub fn is_stale(&self, state: &LockState) -> bool {
+        self.drawn_layout.get() != Some(Layout::of(state, self.dims(state)))
+    }
+
+    /// Time until the lock screen dims, unless it is dimmed already.
+    pub fn dim_remaining(&self, state: &LockState) -> Option<Duration> {
+        let (delay, _) = self.dim?;
+        delay
+            .checked_sub(state.inactive_for())
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    /// Whether the last frame was drawn dimmed.
+    pub fn is_dimmed(&self) -> bool {
+        self.dimmed.get()
+    }
+
+    fn dims(&self, state: &LockState) -> bool {
+        self.dim.is_some() && self.dim_remaining(state).is_none()
+    }
+
+    /// The foreground and ready colors, faded while dimmed.
+    fn colors(&self) -> (Color, Color) {
+        (
+            self.faded(self.theme.foreground),
+            self.faded(self.theme.ready),
+        )
+    }
+
+    /// `color` faded towards the background while dimmed.
+    fn faded(&self, color: Color) -> Color {
+        match self.dim {
+            Some((_, level)) if self.dimmed.get() => color.blend(self.theme.background, level),
+            _ => color,
+        }
+    }
+
+    /// Repaint only `areas` of the window, for changes known to stay within
+    /// them. Drawing is clipped to the areas, sparing the server from
+    /// clearing and copying the rest of the frame. Falls back to a full
+    /// repaint when something outside of them may have changed as well.
+    pub fn draw_areas(
+        &self,
+        state: &LockState,
+        widgets: &[(Region, Box<dyn Widget>)],
+        areas: &[Rect],
+    ) -> Result<()> {
+        if self.is_stale(state) {
+            return self.draw(state, widgets);
+        }
+
+        let clip: Vec<Rectangle> = areas.iter().copied().map(Rectangle::from).collect();
+        self.backend.clip(&clip)?;
+        let result = self.draw(state, widgets);
+        self.backend.unclip()?;
+        result
+    }
+
+    /// The band of the PIN field, from the Caps Lock warning above it to
+    /// the message below, which is what typing changes.
+    pub fn pin_area(&self) -> Rect {
+        let center_y = self.center.get().1;
+        let top = center_y - 3 * self.dot_radius - self.line_height;
+        let bottom = center_y + 4 * self.dot_radius + self.line_height;
+        let area = self.area.get();
+        Rect {
+            x: area.x,
+            y: top,
+            width: area.width,
+            height: (bottom - top) as u16,
+        }
+    }
+
+    /// The band of the lock key indicators, all of which may show up at
+    /// once. Within the PIN field's band unless they are elsewhere.
+    pub fn indicator_area(&self) -> Rect {
+        let places = self.indicator_places(
+            &vec![0; self.indicator_bar.most_shown().max(1)],
+            self.center.get().1,
+        );
+        let area = self.area.get();
+        let line_height = i32::from(self.line_height);
+        let first = places.first().map_or(0, |&(_, y)| y);
+        let last = places.last().map_or(0, |&(_, y)| y);
+        let top = (first - line_height).max(area.y.into());
+        let bottom = (last + line_height).min(i32::from(area.y) + i32::from(area.height));
+        Rect {
+            x: area.x,
+            y: top as i16,
+            width: area.width,
+            height: (bottom - top).max(0) as u16,
+        }
+    }
+
+    /// Where the indicators `widths` wide go on the current screen.
+    fn indicator_places(&self, widths: &[i32], center_y: i16) -> Vec<(i32, i32)> {
+        let area = self.area.get();
+        let (x, y) = (i32::from(area.x), i32::from(area.y));
+        let frame = Frame {
+            width: area.width.into(),
+            height: area.height.into(),
+            line_height: self.line_height.into(),
+            pin_x: i32::from(self.center.get().0) - x,
+            above_pin: i32::from(center_y - 3 * self.dot_radius) - y,
+        };
+        self.indicator_bar
+            .layout(widths, frame)
+            .into_iter()
+            .map(|(dx, dy)| (x + dx, y + dy))
+            .collect()
+    }
+
+    /// The input areas of every screen the UI is on, which is what typing
+    /// changes.
+    pub fn input_areas(&self) -> Vec<Rect> {
+        self.at_each_place(|| [self.pin_area(), self.indicator_area()])
+            .concat()
+    }
+
+    /// The areas of the widgets on every screen the UI is on, which is what
+    /// their ticks change.
+    pub fn widget_areas(&self, widgets: &[(Region, Box<dyn Widget>)]) -> Vec<Rect> {
+        self.at_each_place(|| self.place_widget_areas(widgets))
+            .concat()
+    }
+
+    /// The areas of the widgets on the current screen.
+    fn place_widget_areas(&self, widgets: &[(Region, Box<dyn Widget>)]) -> Vec<Rect> {
+        let mut stacked = [0; 4];
+        widgets
+            .iter()
+            .map(|(region, widget)| {
+                let offset = &mut stacked[*region as usize];
+                let rect = self.widget_rect(*region, *offset, widget.lines());
+                *offset += rect.height as i16;
+                rect
+            })
+            .collect()
+    }
+
+    /// The button at a point of the window, if buttons are shown.
+    pub fn button_at(&self, state: &LockState, x: i16, y: i16) -> Option<Button> {
+        if !self.shows_buttons(state) {
+            return None;
+        }
+        self.at_each_place(|| self.button_rects(state))
+            .concat()
+            .into_iter()
+            .find(|(_, rect)| rect.contains(x, y))
+            .map(|(button, _)| button)
+    }
+
+    /// The dot of the unlock pattern at a point of the window, from 0 to 8,
+    /// if the pattern is shown.
+    pub fn pattern_dot_at(&self, state: &LockState, x: i16, y: i16) -> Option<usize> {
+        if !self.shows_pattern(state) {
+            return None;
+        }
+        self.at_each_place(|| {
+            let (dots, reach) = self.pattern_dots();
+            dots.iter().position(|&(dot_x, dot_y)| {
+                (x - dot_x).abs() <= reach && (y - dot_y).abs() <= reach
+            })
+        })
+        .into_iter()
+        .flatten()
+        .next()
+    }
+
+    /// Like the buttons, only while a PIN can be entered.
+    fn shows_pattern(&self, state: &LockState) -> bool {
+        self.pattern && !state.in_grace() && !state.is_locked_out()
+    }
+
+    /// Centers of the dots of the unlock pattern, in three rows of three
+    /// below the PIN field, and how far from them a tap still counts.
+    fn pattern_dots(&self) -> ([(i16, i16); 9], i16) {
+        let spacing = 4 * self.line_height;
+        let (center_x, center_y) = self.center.get();
+        let top = center_y + 7 * self.dot_radius + spacing / 2;
+        let dots = std::array::from_fn(|i| {
+            let (column, row) = (i as i16 % 3, i as i16 / 3);
+            (center_x + (column - 1) * spacing, top + row * spacing)
+        });
+        (dots, spacing / 2)
+    }
+
+    /// The dots of the unlock pattern, those tapped so far filled in and
+    /// joined by lines in the order they were tapped.
+    fn draw_pattern(&self, state: &LockState) -> Result<()> {
+        let (dots, _) = self.pattern_dots();
+        let (foreground, ready) = self.colors();
+        let radius = self.dot_radius;
+        let arc = |(x, y): (i16, i16)| Arc {
+            x: x - radius,
+            y: y - radius,
+            width: 2 * radius as u16,
+            height: 2 * radius as u16,
+            angle1: 0,
+            angle2: 360 * 64,
+        };
+
+        let tapped: Vec<(i16, i16)> = state.pattern().map(|dot| dots[dot]).collect();
+        if tapped.len() > 1 {
+            let points: Vec<Point> = tapped.iter().map(|&(x, y)| Point { x, y }).collect();
+            self.backend
+                .draw_lines(ready, (radius / 2).max(1) as u16, &points)?;
+        }
+        self.backend.draw_arcs(foreground, 0, &dots.map(arc))?;
+        let filled: Vec<Arc> = tapped.into_iter().map(arc).collect();
+        self.backend.fill_arcs(ready, &filled)
+    }
+
+    /// Buttons are only there while a PIN can be entered.
+    fn shows_buttons(&self, state: &LockState) -> bool {
+        (self.buttons || self.keypad) && !state.in_grace() && !state.is_locked_out()
+    }
+
+    /// Clear and Submit side by side below the PIN field, each six lines of
+    /// text wide and two high. The keypad puts them in its bottom row
+    /// instead, around the last digit, with three rows of three digits above.
+    fn button_rects(&self, state: &LockState) -> Vec<(Button, Rect)> {
+        self.button_rects_for(state.keypad())
+    }
+
+    fn button_rects_for(&self, keypad: &Keypad) -> Vec<(Button, Rect)> {
+        let top = self.center.get().1 + 7 * self.dot_radius;
+        let center_x = self.center.get().0;
+
+        if !self.keypad {
+            let (width, height) = (6 * self.line_height as u16, 2 * self.line_height as u16);
+            let gap = self.line_height;
+            let rect = |x| Rect {
+                x,
+                y: top,
+                width,
+                height,
+            };
+            return vec![
+                (Button::Clear, rect(center_x - gap / 2 - width as i16)),
+                (Button::Submit, rect(center_x + gap / 2)),
+            ];
+        }
+
+        let (width, height) = (5 * self.line_height as u16, 2 * self.line_height as u16);
+        let gap = self.line_height / 2;
+        let left = center_x - width as i16 * 3 / 2 - gap;
+        let digits = keypad.digits();
+        let bottom_row = [Button::Clear, Button::Digit(digits[9]), Button::Submit];
+
+        digits[..9]
+            .iter()
+            .map(|&digit| Button::Digit(digit))
+            .chain(bottom_row)
+            .enumerate()
+            .map(|(i, button)| {
+                let (column, row) = ((i % 3) as i16, (i / 3) as i16);
+                let rect = Rect {
+                    x: left + column * (width as i16 + gap),
+                    y: top + row * (height as i16 + gap),
+                    width,
+                    height,
+                };
+                (button, rect)
+            })
+            .collect()
+    }
+
+    /// Draw the outline of each button around its centered label.
+    fn draw_buttons(&self, state: &LockState) -> Result<()> {
+        let canvas = self.canvas();
+        let mut digit = [0; 4];
+        for (button, rect) in self.button_rects(state) {
+            self.backend
+                .draw_rectangles(self.colors().0, &[rect.into()])?;
+
+            let label = match button {
+                Button::Submit => &self.strings.submit,
+                Button::Clear => &self.strings.clear,
+                Button::Digit(c) => &*c.encode_utf8(&mut digit),
+            };
+            let x = i32::from(rect.x) + (i32::from(rect.width) - canvas.text_width(label)?) / 2;
+            let baseline = rect.y + rect.height as i16 / 2 + self.line_height / 3;
+            canvas.draw_text(label, x as i16, baseline)?;
+        }
+        Ok(())
+    }
+
+    /// Replace the whole window with the next frame of the screensaver.
+    pub fn draw_screensaver(&self, idle: &mut Idle) -> Result<()> {
+        self.drawn_layout.set(None);
+        self.dimmed.set(false);
+        self.clear()?;
+        idle.draw(&self.canvas())?;
+        self.present()
+    }
+
+    /// Draw a ring with a check mark in the theme's `ready` color, to
+    /// confirm an unlock.
+    pub fn draw_unlocked(&self) -> Result<()> {
+        self.drawn_layout.set(None);
+        self.dimmed.set(false);
+        self.clear()?;
+        for result in self.at_each_place(|| self.draw_check_mark()) {
+            result?;
+        }
+        self.present()
+    }
+
+    /// The bar below the PIN filling up while Enter is held, `held` from 0
+    /// to 1, with `hold_to_submit_ms`.
+    fn draw_hold_bar(&self, held: f64, center_y: i16) -> Result<()> {
+        let (x, _) = self.center.get();
+        let full = 6 * self.dot_spacing;
+        let width = (f64::from(full) * held).round() as u16;
+        if width == 0 {
+            return Ok(());
+        }
+        self.backend.fill_rectangles(
+            self.theme.ready,
+            &[Rectangle {
+                x: x - full / 2,
+                y: center_y + 2 * self.dot_radius,
+                width,
+                height: (self.dot_radius / 3).max(1) as u16,
+            }],
+        )
+    }
+
+    fn draw_check_mark(&self) -> Result<()> {
+        let (x, y) = self.center.get();
+        let radius = 4 * self.dot_radius;
+        let line_width = (self.dot_radius / 2).max(1) as u16;
+        self.backend.draw_arcs(
+            self.theme.ready,
+            line_width,
+            &[Arc {
+                x: x - radius,
+                y: y - radius,
+                width: 2 * radius as u16,
+                height: 2 * radius as u16,
+                angle1: 0,
+                angle2: 360 * 64,
+            }],
+        )?;
+        let unit = radius / 4;
+        self.backend.draw_lines(
+            self.theme.ready,
+            line_width,
+            &[
+                Point { x: x - 2 * unit, y },
+                Point {
+                    x: x - unit / 2,
+                    y: y + 3 * unit / 2,
+                },
+                Point {
+                    x: x + 2 * unit,
+                    y: y - 3 * unit / 2,
+                },
+            ],
+        )
+    }
+
+    fn canvas(&self) -> Canvas<'_> {
+        Canvas {
+            backend: &self.backend,
+            color: self.colors().0,
+            width: self.width,
+            height: self.height,
+            line_height: self.line_height,
+        }
+    }
+
+    fn draw_indicator_bar(&self, state: &LockState, center_y: i16) -> Result<()> {
+        let shown = self.indicator_bar.shown(state);
+        if shown.is_empty() {
+            return Ok(());
+        }
+
+        let canvas = self.canvas();
+        let widths = shown
+            .iter()
+            .map(|(text, _)| canvas.text_width(text))
+            .collect::<Result<Vec<_>>>()?;
+        let places = self.indicator_places(&widths, center_y);
+        for ((text, color), (x, y)) in shown.iter().zip(places) {
+            self.backend
+                .draw_text(self.faded(*color), text, x as i16, y as i16)?;
+        }
+        Ok(())
+    }
+
+    fn draw_widgets(&self, widgets: &[(Region, Box<dyn Widget>)]) -> Result<()> {
+        let mut canvas = self.canvas();
+        for ((_, widget), rect) in widgets.iter().zip(self.place_widget_areas(widgets)) {
+            widget.draw(&mut canvas, rect)?;
+        }
+        Ok(())
+    }
+
+    /// The area of a widget of `lines` in `region`, `offset` pixels further
+    /// from the corner than the widgets before it.
+    fn widget_rect(&self, region: Region, offset: i16, lines: usize) -> Rect {
+        let area = self.area.get();
+        let margin = self.line_height;
+        let width = area.width / 4;
+        let height = (lines as u16 + 1) * self.line_height as u16;
+        let x = match region {
+            Region::TopLeft | Region::BottomLeft => area.x + margin,
+            Region::TopRight | Region::BottomRight => {
+                area.x + area.width as i16 - margin - width as i16
+            }
+        };
+        let y = match region {
+            Region::TopLeft | Region::TopRight => area.y + margin + offset,
+            Region::BottomLeft | Region::BottomRight => {
+                area.y + area.height as i16 - margin - offset - height as i16
+            }
+        };
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Start a frame by covering the last one.
+    fn clear(&self) -> Result<()> {
+        #[cfg(feature = "metrics")]
+        self.frame_started.set(Some(Instant::now()));
+        self.backend.clear(self.theme.background)
+    }
+
+    /// Show the finished frame, copying it to the window.
+    fn present(&self) -> Result<()> {
+        let result = self.backend.present();
+        #[cfg(feature = "metrics")]
+        if let Some(started) = self.frame_started.take() {
+            self.frame_times.borrow_mut().record(started.elapsed());
+        }
+        result
+    }
+
+    /// How long the frames drawn so far took.
+    #[cfg(feature = "metrics")]
+    pub fn frame_times(&self) -> Histogram {
+        self.frame_times.borrow().clone()
+    }
+
+    /// Draw the static banner, one centered line of text per line.
+    fn draw_banner(&self, center_y: i16) -> Result<()> {
+        let lines = self.banner.len() as i16;
+        if lines == 0 {
+            return Ok(());
+        }
+
+        let area = self.area.get();
+        let margin = 2 * self.line_height;
+        let first_baseline = match self.banner_position {
+            BannerPosition::Top => area.y + margin,
+            BannerPosition::Center => {
+                center_y - 6 * self.dot_radius - (lines - 1) * self.line_height
+            }
+            BannerPosition::Bottom => {
+                area.y + area.height as i16 - margin - (lines - 1) * self.line_height
+            }
+        };
+
+        // Away from the center, centered on the screen
+        let offset = match self.banner_position {
+            BannerPosition::Center => 0,
+            BannerPosition::Top | BannerPosition::Bottom => {
+                area.x + (area.width / 2) as i16 - self.center.get().0
+            }
+        };
+        for (i, line) in self.banner.iter().enumerate() {
+            let y = first_baseline + i as i16 * self.line_height;
+            self.draw_text_at(line, offset, y, self.colors().0)?;
+        }
+        Ok(())
+    }
+
+    /// How far what is grouped around the center reaches from it, to the
+    /// left, right, top and bottom: the PIN field, the banner when at the
+    /// center and the buttons or the pattern grid, even while they are hidden,
+    /// so that the group stays put.
+    fn center_reach(&self) -> [i16; 4] {
+        let (center_x, center_y) = self.center.get();
+        let pin = self.pin_area();
+        let (mut left, mut right) = (8 * self.dot_spacing, 8 * self.dot_spacing);
+        let (mut top, mut bottom) = (center_y - pin.y, pin.y + pin.height as i16 - center_y);
+
+        let banner_lines = self.banner.len() as i16;
+        if self.banner_position == BannerPosition::Center && banner_lines > 0 {
+            top = top.max(6 * self.dot_radius + banner_lines * self.line_height);
+        }
+        if self.buttons || self.keypad {
+            for (_, rect) in self.button_rects_for(&Keypad::new(None)) {
+                left = left.max(center_x - rect.x);
+                right = right.max(rect.x + rect.width as i16 - center_x);
+                bottom = bottom.max(rect.y + rect.height as i16 - center_y);
+            }
+        }
+        if self.pattern {
+            let (dots, reach) = self.pattern_dots();
+            let (last_x, last_y) = dots[8];
+            left = left.max(center_x - dots[0].0 + reach);
+            right = right.max(last_x + reach - center_x);
+            bottom = bottom.max(last_y + reach - center_y);
+        }
+        [left, right, top, bottom]
+    }
+
+    /// The center of the current screen, or the pointer if it is on it,
+    /// moved away from the edges until everything around it fits.
+    fn anchored_center(&self) -> (i16, i16) {
+        let area = self.area.get();
+        let middle = (
+            area.x + (area.width / 2) as i16,
+            area.y + (area.height / 2) as i16,
+        );
+        let Some((x, y)) = self
+            .pointer
+            .filter(|&(x, y)| self.anchor == UiAnchor::Pointer && area.contains(x, y))
+        else {
+            return middle;
+        };
+        let [left, right, top, bottom] = self.center_reach();
+        (
+            clamp_within(
+                x,
+                area.x + left,
+                area.x + area.width as i16 - right,
+                middle.0,
+            ),
+            clamp_within(
+                y,
+                area.y + top,
+                area.y + area.height as i16 - bottom,
+                middle.1,
+            ),
+        )
+    }
+
+    /// Work out where the PIN field goes on each of the screens, and make
+    /// the first of them current.
+    fn place(&mut self) {
+        let whole = Rect {
+            x: 0,
+            y: 0,
+            width: self.width,
+            height: self.height,
+        };
+        let screens = if self.screens.is_empty() {
+            vec![whole]
+        } else {
+            self.screens.clone()
+        };
+        self.places = screens
+            .into_iter()
+            .map(|screen| {
+                self.area.set(screen);
+                (screen, self.anchored_center())
+            })
+            .collect();
+        self.enter(self.places[0]);
+    }
+
+    fn enter(&self, (area, center): (Rect, (i16, i16))) {
+        self.area.set(area);
+        self.center.set(center);
+    }
+
+    /// Run `f` on each of the screens the UI is on, made current in turn.
+    fn at_each_place<T>(&self, mut f: impl FnMut() -> T) -> Vec<T> {
+        let results = self
+            .places
+            .iter()
+            .map(|&place| {
+                self.enter(place);
+                f()
+            })
+            .collect();
+        self.enter(self.places[0]);
+        results
+    }
+
+    /// The area of the card, around everything at the center.
+    fn card_rect(&self, card: &Card) -> Rect {
+        let (center_x, center_y) = self.center.get();
+        let [left, right, top, bottom] = self.center_reach();
+        let (left, right, top, bottom) = (
+            center_x - left,
+            center_x + right,
+            center_y - top,
+            center_y + bottom,
+        );
+
+        let area = self.area.get();
+        let (x, y) = (
+            (left - card.padding).max(area.x),
+            (top - card.padding).max(area.y),
+        );
+        let right = (right + card.padding).min(area.x + area.width as i16);
+        let bottom = (bottom + card.padding).min(area.y + area.height as i16);
+        Rect {
+            x,
+            y,
+            width: (right - x) as u16,
+            height: (bottom - y) as u16,
+        }
+    }
+
+    /// Fill the card, a rectangle with rounded corners.
+    fn draw_card(&self, card: &Card) -> Result<()> {
+        let Rect {
+            x,
+            y,
+            width,
+            height,
+        } = self.card_rect(card);
+        let radius = card
+            .radius
+            .min(width as i16 / 2)
+            .min(height as i16 / 2)
+            .max(0);
+        let diameter = 2 * radius as u16;
+
+        let color = match self.dim {
+            Some((_, level)) if self.dimmed.get() => card.color.blend(self.theme.background, level),
+            _ => card.color,
+        };
+        let (right, bottom) = (
+            x + width as i16 - diameter as i16,
+            y + height as i16 - diameter as i16,
+        );
+        let corners: Vec<Arc> = [(x, y), (right, y), (x, bottom), (right, bottom)]
+            .into_iter()
+            .map(|(x, y)| Arc {
+                x,
+                y,
+                width: diameter,
+                height: diameter,
+                angle1: 0,
+                angle2: 360 * 64,
+            })
+            .collect();
+        self.backend.fill_arcs(color, &corners)?;
+        self.backend.fill_rectangles(
+            color,
+            &[
+                Rectangle {
+                    x: x + radius,
+                    y,
+                    width: width - diameter,
+                    height,
+                },
+                Rectangle {
+                    x,
+                    y: y + radius,
+                    width,
+                    height: height - diameter,
+                },
+            ],
+        )
+    }
+
+    /// Represent the entered characters in the configured style, centered
+    /// around `y` and moved right by `offset`. A ready indicator signals that
+    /// the PIN is complete and can be submitted.
+    fn draw_indicator(
+        &self,
+        count: usize,
+        ready: bool,
+        y: i16,
+        offset: i16,
+        shown: f64,
+    ) -> Result<()> {
+        if let (IndicatorStyle::Bar, Some(max)) = (self.indicator_style, self.max_pin_length) {
+            return self.draw_progress_bar(count, max, ready, y, offset, shown);
+        }
+        if count == 0 || self.indicator_style == IndicatorStyle::None {
+            return Ok(());
+        }
+
+        let (foreground, ready_color) = self.colors();
+        let color = if ready { ready_color } else { foreground };
+        // Fading in from the background as they grow, see `animations`
+        let faded_in = color.blend(self.theme.background, 1.0 - shown);
+        match self.indicator_style {
+            IndicatorStyle::Dots => {
+                let radius = (f64::from(self.dot_radius) * (0.5 + 0.5 * shown)).round() as i16;
+                self.draw_dots(count, y, offset, radius, faded_in)
+            }
+            IndicatorStyle::Count => {
+                let baseline = y + self.line_height / 2;
+                self.draw_text_at(&count.to_string(), offset, baseline, faded_in)
+            }
+            IndicatorStyle::Bar | IndicatorStyle::None => Ok(()),
+        }
+    }
+
+    /// Draw the outline of a bar with round ends, as wide as `max` dots,
+    /// filled for `count` of them. Centered around `y` and moved right by
+    /// `offset`.
+    fn draw_progress_bar(
+        &self,
+        count: usize,
+        max: usize,
+        ready: bool,
+        y: i16,
+        offset: i16,
+        shown: f64,
+    ) -> Result<()> {
+        let (foreground, ready_color) = self.colors();
+        let height = self.progress_bar_height;
+        let most = i32::from(self.area.get().width) - 2 * i32::from(self.line_height);
+        let width =
+            (max as i32 * i32::from(self.dot_spacing)).clamp(2 * i32::from(height), most) as i16;
+        let (left, top) = (self.center.get().0 - width / 2 + offset, y - height / 2);
+        let radius = height / 2;
+
+        let end = |x: i16, angle1: i16| Arc {
+            x,
+            y: top,
+            width: height as u16,
+            height: height as u16,
+            angle1,
+            angle2: 180 * 64,
+        };
+        self.backend.draw_arcs(
+            foreground,
+            0,
+            &[end(left, 90 * 64), end(left + width - height, -90 * 64)],
+        )?;
+        let (start, stop) = (left + radius, left + width - radius);
+        for line_y in [top, top + height] {
+            self.backend.draw_lines(
+                foreground,
+                0,
+                &[
+                    Point {
+                        x: start,
+                        y: line_y,
+                    },
+                    Point { x: stop, y: line_y },
+                ],
+            )?;
+        }
+
+        // Inside the outline, leaving a gap
+        let inset = (height / 4).max(1);
+        let inner = width - 2 * inset;
+        let filled = (i32::from(inner) * count.min(max) as i32 / max.max(1) as i32) as u16;
+        if filled == 0 || height <= 2 * inset {
+            return Ok(());
+        }
+        let color = if ready {
+            ready_color
+        } else {
+            self.faded(self.theme.bar.unwrap_or(self.theme.foreground))
+        };
+        let faded_in = color.blend(self.theme.background, 1.0 - shown);
+        self.backend.fill_rectangles(
+            faded_in,
+            &[Rectangle {
+                x: left + inset,
+                y: top + inset,
+                width: filled,
+                height: (height - 2 * inset) as u16,
+            }],
+        )
+    }
+
+    /// Draw one filled dot of `radius` per entered character, centered
+    /// around `y` and moved right by `offset`.
+    fn draw_dots(
+        &self,
+        count: usize,
+        y: i16,
+        offset: i16,
+        radius: i16,
+        color: Color,
+    ) -> Result<()> {
+        let count =
+            count.min(usize::from(self.area.get().width) / self.dot_spacing as usize) as i16;
+        let row_width = (count - 1) * self.dot_spacing;
+        let start_x = self.center.get().0 - row_width / 2 + offset;
+
+        let arcs: Vec<Arc> = (0..count)
+            .map(|i| Arc {
+                x: start_x + i * self.dot_spacing - radius,
+                y: y - radius,
+                width: 2 * radius as u16,
+                height: 2 * radius as u16,
+                angle1: 0,
+                angle2: 360 * 64,
+            })
+            .collect();
+
+        self.backend.fill_arcs(color, &arcs)
+    }
+
+    /// Draw a line of text horizontally centered, with its baseline at `y`.
+    fn draw_text(&self, text: &str, y: i16) -> Result<()> {
+        self.draw_text_at(text, 0, y, self.colors().0)
+    }
+
+    /// Draw a line of text in `color`, moved right from the center by
+    /// `offset`, with its baseline at `y`.
+    fn draw_text_at(&self, text: &str, offset: i16, y: i16, color: Color) -> Result<()> {
+        let width = self.backend.text_width(text)?;
+        let x = i32::from(self.center.get().0) - width / 2 + i32::from(offset);
+
+        self.backend.draw_text(color, text, x as i16, y)
+    }
+}
+
+/// `value` within `low` and `high`, or `otherwise` when they leave no room.
+fn clamp_within(value: i16, low: i16, high: i16, otherwise: i16) -> i16 {
+    if low > high {
+        otherwise
+    } else {
+        value.clamp(low, high)
+    }
+}
+
+/// Swings the shake makes back and forth
+const SHAKE_SWINGS: f64 = 3.0;
+
+/// Horizontal offset of the shake after a wrong PIN, `progress` through it
+/// from 0 to 1. Swings up to `amplitude` to either side, dying down to rest
+/// centered at the end.
+fn shake_offset(progress: f64, amplitude: i16) -> i16 {
+    let damping = (1.0 - progress).powi(2);
+    let swing = (progress * SHAKE_SWINGS * std::f64::consts::TAU).sin();
+    (f64::from(amplitude) * damping * swing).round() as i16
+}
+
+/// Easing for entrances, fast at first and settling gently, for `progress`
+/// from 0 to 1.
+fn ease_out(progress: f64) -> f64 {
+    1.0 - (1.0 - progress.clamp(0.0, 1.0)).powi(3)
+}
+
+/// The configured scale of the UI elements, or the `detect`ed one,
+/// enlarged in the `accessibility` mode.
+pub fn scale(config: &Config, detect: impl FnOnce() -> f64) -> f64 {
+    config
+        .scale
+        .unwrap_or_else(|| {
+            let enlarge = if config.accessibility {
+                ACCESSIBLE_ENLARGEMENT
+            } else {
+                1.0
+            };
+            detect() * enlarge
+        })
+        .clamp(SCALE_RANGE.0, SCALE_RANGE.1)
+}
+
+/// Derive the scale from the DPI of the primary monitor, as RandR reports
+/// its physical size.
+fn detect_scale(connection: &RustConnection, window: &Window) -> f64 {
+    let monitors = image::active_monitors(connection, window);
+
+    // Projectors and some virtual outputs report no physical size
+    monitors
+        .iter()
+        .find(|monitor| monitor.primary)
+        .or_else(|| monitors.first())
+        .filter(|monitor| monitor.width_in_millimeters > 0)
+        .map_or(1.0, |monitor| {
+            let dpi = f64::from(monitor.width) * 25.4 / f64::from(monitor.width_in_millimeters);
+            dpi / BASE_DPI
+        })
+}
+
+/// Where to show the UI: the monitors of the window picked by `ui_on`, or
+/// none if RandR can't tell.
+fn screens(
+    connection: &RustConnection,
+    window: &Window,
+    ui_on: UiOn,
+    pointer: Option<(i16, i16)>,
+) -> Vec<Rect> {
+    let monitors: Vec<(bool, Rect)> = image::active_monitors(connection, window)
+        .iter()
+        .map(|monitor| {
+            let rect = Rect {
+                x: monitor.x,
+                y: monitor.y,
+                width: monitor.width,
+                height: monitor.height,
+            };
+            (monitor.primary, rect)
+        })
+        .collect();
+    choose_screens(&monitors, ui_on, pointer)
+}
+
+/// The `monitors`, each with whether it is the primary one, that `ui_on`
+/// picks. Without a primary monitor the first one stands in for it, as it
+/// does for the focused one when the pointer is on none.
+fn choose_screens(
+    monitors: &[(bool, Rect)],
+    ui_on: UiOn,
+    pointer: Option<(i16, i16)>,
+) -> Vec<Rect> {
+    let primary = || {
+        monitors
+            .iter()
+            .find(|(primary, _)| *primary)
+            .or_else(|| monitors.first())
+            .map(|&(_, rect)| rect)
+    };
+    match ui_on {
+        UiOn::All => monitors.iter().map(|&(_, rect)| rect).collect(),
+        UiOn::Primary => primary().into_iter().collect(),
+        UiOn::Focused => pointer
+            .and_then(|(x, y)| {
+                monitors
+                    .iter()
+                    .find(|(_, rect)| rect.contains(x, y))
+                    .map(|&(_, rect)| rect)
+            })
+            .or_else(primary)
+            .into_iter()
+            .collect(),
+    }
+}
+
+/// The lock screen as handed to widgets and screensavers, drawing in the
+/// foreground color.
+pub struct Canvas<'ui> {
+    backend: &'ui dyn Backend,
+    color: Color,
+    width: u16,
+    height: u16,
+    line_height: i16,
+}
+
+impl Canvas<'_> {
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    pub fn line_height(&self) -> i16 {
+        self.line_height
+    }
+
+    /// The same canvas, drawing in `color` instead.
+    pub fn in_color(&self, color: Color) -> Canvas<'_> {
+        Canvas {
+            backend: self.backend,
+            color,
+            width: self.width,
+            height: self.height,
+            line_height: self.line_height,
+        }
+    }
+
+    pub fn fill_rectangles(&self, rectangles: &[Rectangle]) -> Result<()> {
+        self.backend.fill_rectangles(self.color, rectangles)
+    }
+
+    /// Width of `text` in pixels.
+    pub fn text_width(&self, text: &str) -> Result<i32> {
+        self.backend.text_width(text)
+    }
+
+    /// Draw a line of text starting at `x`, with its baseline at `y`.
+    pub fn draw_text(&self, text: &str, x: i16, y: i16) -> Result<()> {
+        self.backend.draw_text(self.color, text, x, y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_shake_swings_both_ways_and_settles_centered() {
+        let offsets: Vec<i16> = (0..=30)
+            .map(|i| shake_offset(i as f64 / 30.0, 20))
+            .collect();
+        assert_eq!(offsets.first(), Some(&0));
+        assert_eq!(offsets.last(), Some(&0));
+        assert!(offsets.iter().any(|&offset| offset > 0));
+        assert!(offsets.iter().any(|&offset| offset < 0));
+        assert!(offsets.iter().all(|offset| offset.abs() <= 20));
+    }
+
+    #[test]
+    fn the_pointer_anchor_keeps_clear_of_the_edges() {
+        assert_eq!(clamp_within(5, 100, 900, 500), 100);
+        assert_eq!(clamp_within(640, 100, 900, 500), 640);
+        assert_eq!(clamp_within(640, 600, 400, 500), 500);
+    }
+
+    #[test]
+    fn the_ui_goes_on_the_chosen_monitors() {
+        let monitor = |x| Rect {
+            x,
+            y: 0,
+            width: 1920,
+            height: 1080,
+        };
+        let monitors = [(false, monitor(0)), (true, monitor(1920))];
+        assert_eq!(
+            choose_screens(&monitors, UiOn::Primary, None),
+            [monitor(1920)]
+        );
+        assert_eq!(
+            choose_screens(&monitors, UiOn::All, None),
+            [monitor(0), monitor(1920)]
+        );
+        assert_eq!(
+            choose_screens(&monitors, UiOn::Focused, Some((100, 500))),
+            [monitor(0)]
+        );
+        assert_eq!(
+            choose_screens(&monitors, UiOn::Focused, None),
+            [monitor(1920)]
+        );
+        assert!(choose_screens(&[], UiOn::Primary, None).is_empty());
+    }
+
+    #[test]
+    fn entrances_ease_out_from_nothing_to_everything() {
+        assert_eq!(ease_out(0.0), 0.0);
+        assert_eq!(ease_out(1.0), 1.0);
+        assert!(ease_out(0.5) > 0.5);
+    }
+}
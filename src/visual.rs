@@ -0,0 +1,94 @@
+use anyhow::{Context, Result};
+use x11rb::protocol::xproto::{Screen, Visualid};
+
+/// The depth and visual every drawable of the lock screen is created with.
+///
+/// Windows, pixmaps and images copied between each other must agree on their
+/// depth, or the server answers with `BadMatch`. Servers may offer several
+/// depths (typically 24 and 32 bit), so rather than mixing the parent's depth
+/// with some visual, everything derives from this one choice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VisualInfo {
+    pub depth: u8,
+    pub visual: Visualid,
+}
+
+impl VisualInfo {
+    /// Use the root visual, at the depth the server lists it under.
+    pub fn for_screen(screen: &Screen) -> Result<Self> {
+        screen
+            .allowed_depths
+            .iter()
+            .find(|depth| {
+                depth
+                    .visuals
+                    .iter()
+                    .any(|visual| visual.visual_id == screen.root_visual)
+            })
+            .map(|depth| Self {
+                depth: depth.depth,
+                visual: screen.root_visual,
+            })
+            .context("Root visual is not listed among the screen's depths")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use x11rb::protocol::xproto::{Depth, VisualClass, Visualtype};
+
+    use super::*;
+
+    fn visual(visual_id: Visualid) -> Visualtype {
+        Visualtype {
+            visual_id,
+            class: VisualClass::TRUE_COLOR,
+            bits_per_rgb_value: 8,
+            colormap_entries: 256,
+            red_mask: 0xff0000,
+            green_mask: 0xff00,
+            blue_mask: 0xff,
+        }
+    }
+
+    #[test]
+    fn picks_the_depth_of_the_root_visual_on_multi_depth_screens() {
+        let screen = Screen {
+            root_depth: 24,
+            root_visual: 0x21,
+            allowed_depths: vec![
+                Depth {
+                    depth: 32,
+                    visuals: vec![visual(0x60)],
+                },
+                Depth {
+                    depth: 24,
+                    visuals: vec![visual(0x20), visual(0x21)],
+                },
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            VisualInfo::for_screen(&screen).unwrap(),
+            VisualInfo {
+                depth: 24,
+                visual: 0x21
+            }
+        );
+    }
+
+    #[test]
+    fn fails_when_the_root_visual_is_missing() {
+        let screen = Screen {
+            root_visual: 0x21,
+            allowed_depths: vec![Depth {
+                depth: 32,
+                visuals: vec![visual(0x60)],
+            }],
+            ..Default::default()
+        };
+
+        assert!(VisualInfo::for_screen(&screen).is_err());
+    }
+}
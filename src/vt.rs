@@ -0,0 +1,52 @@
+//! Keeping the virtual terminal from being switched, for the `vtlock`
+//! feature.
+//!
+//! Otherwise Ctrl+Alt+F1 and the like lead to another VT past the lock, such
+//! as a text console where a user may still be logged in. The switch lock is
+//! system wide and outlives pinlock if it gets killed, after which `chvt`
+//! or restarting pinlock brings switching back.
+
+use std::{
+    fs::{File, OpenOptions},
+    os::fd::AsRawFd,
+};
+
+use anyhow::{Context, Result};
+use log::warn;
+use nix::ioctl_none_bad;
+
+/// The console, which controls the VTs
+const CONSOLE: &str = "/dev/tty0";
+
+ioctl_none_bad!(vt_lockswitch, 0x560b);
+ioctl_none_bad!(vt_unlockswitch, 0x560c);
+
+/// Refuses VT switches until dropped.
+pub struct VtLock {
+    console: File,
+}
+
+impl VtLock {
+    /// Lock VT switching. The kernel only allows this with the
+    /// `CAP_SYS_TTY_CONFIG` capability, on top of write access to
+    /// `/dev/tty0`.
+    pub fn acquire() -> Result<Self> {
+        let console = OpenOptions::new()
+            .write(true)
+            .open(CONSOLE)
+            .with_context(|| format!("Failed to open {CONSOLE}"))?;
+        // SAFETY: the descriptor is valid for as long as `console` lives
+        unsafe { vt_lockswitch(console.as_raw_fd()) }
+            .context("Failed to lock VT switching, which needs CAP_SYS_TTY_CONFIG")?;
+        Ok(Self { console })
+    }
+}
+
+impl Drop for VtLock {
+    fn drop(&mut self) {
+        // SAFETY: as above
+        if let Err(err) = unsafe { vt_unlockswitch(self.console.as_raw_fd()) } {
+            warn!("Failed to unlock VT switching: {err}");
+        }
+    }
+}
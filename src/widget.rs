@@ -0,0 +1,358 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use serde::Deserialize;
+use x11rb::protocol::xproto::Rectangle;
+
+use crate::{
+    config::{Config, Strings},
+    persist::{self, Persisted},
+    sysinfo::{self, InfoLine},
+    theme::Color,
+    ui::Canvas,
+};
+
+/// How the times of the `access` widget are shown.
+const ACCESS_TIME_FORMAT: &str = "%Y-%m-%d %H:%M";
+
+/// How often widgets are ticked. The lock screen is redrawn after each tick.
+pub const TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// An area of the lock window, in pixels from its top left corner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i16,
+    pub y: i16,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Rect {
+    /// Whether the point lies within the area.
+    pub fn contains(&self, x: i16, y: i16) -> bool {
+        let (x, y) = (i32::from(x), i32::from(y));
+        (i32::from(self.x)..i32::from(self.x) + i32::from(self.width)).contains(&x)
+            && (i32::from(self.y)..i32::from(self.y) + i32::from(self.height)).contains(&y)
+    }
+}
+
+impl From<Rect> for Rectangle {
+    fn from(rect: Rect) -> Self {
+        Rectangle {
+            x: rect.x,
+            y: rect.y,
+            width: rect.width,
+            height: rect.height,
+        }
+    }
+}
+
+/// Extra content drawn on the lock screen next to the PIN field.
+///
+/// Coordinates of the canvas are those of the lock window, starting at its
+/// top left corner with y growing downwards. Text is positioned by its
+/// baseline. Drawing isn't clipped to `rect`, staying within it is up to the
+/// widget.
+pub trait Widget {
+    /// Draw onto the canvas within `rect`, the area of the widget's region.
+    fn draw(&self, canvas: &mut Canvas, rect: Rect) -> Result<()>;
+
+    /// Update the widget's content, called every [`TICK_INTERVAL`].
+    /// Returns whether it changed, which is then redrawn.
+    fn tick(&mut self) -> bool {
+        false
+    }
+
+    /// Lines of text the widget shows, which its area makes room for.
+    fn lines(&self) -> usize {
+        1
+    }
+}
+
+/// Corners of the screen that hold widgets. Widgets in the same corner are
+/// stacked in the order they are configured, towards the screen's center.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Region {
+    #[default]
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// The built-in widgets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WidgetKind {
+    /// The current time, formatted by `clock_format`.
+    Clock,
+    /// Facts about the machine, one per line, chosen by `info_lines`.
+    Sysinfo,
+    /// When the screen was last unlocked, and when a wrong PIN was last
+    /// entered.
+    Access,
+}
+
+/// A `[[widgets]]` entry of the configuration.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WidgetConfig {
+    pub kind: WidgetKind,
+    pub region: Region,
+}
+
+/// Create the configured widgets along with their regions, the hostname
+/// last.
+pub fn from_config(config: &Config) -> Vec<(Region, Box<dyn Widget>)> {
+    let hostname = config
+        .show_hostname
+        .then(sysinfo::hostname)
+        .flatten()
+        .map(|name| {
+            let region = config.hostname_position;
+            let hostname = Hostname::new(name, region, config.theme.hostname);
+            (region, Box::new(hostname) as Box<dyn Widget>)
+        });
+    config
+        .widgets
+        .iter()
+        .map(|widget| {
+            let created: Box<dyn Widget> = match widget.kind {
+                WidgetKind::Clock => Box::new(Clock::new(config.clock_format.clone())),
+                WidgetKind::Sysinfo => Box::new(Sysinfo::new(config.info_lines.clone())),
+                WidgetKind::Access => Box::new(Access::new(config.strings.clone())),
+            };
+            (widget.region, created)
+        })
+        .chain(hostname)
+        .collect()
+}
+
+/// The hostname for `show_hostname`, read once as it doesn't change. Lined
+/// up with the edge of its corner rather than centered.
+struct Hostname {
+    name: String,
+    region: Region,
+    color: Option<Color>,
+}
+
+impl Hostname {
+    fn new(name: String, region: Region, color: Option<Color>) -> Self {
+        Self {
+            name,
+            region,
+            color,
+        }
+    }
+}
+
+impl Widget for Hostname {
+    fn draw(&self, canvas: &mut Canvas, rect: Rect) -> Result<()> {
+        let colored;
+        let canvas = match self.color {
+            Some(color) => {
+                colored = canvas.in_color(color);
+                &colored
+            }
+            None => &*canvas,
+        };
+        let x = match self.region {
+            Region::TopLeft | Region::BottomLeft => i32::from(rect.x),
+            Region::TopRight | Region::BottomRight => {
+                i32::from(rect.x) + i32::from(rect.width) - canvas.text_width(&self.name)?
+            }
+        };
+        canvas.draw_text(&self.name, x as i16, rect.y + canvas.line_height())
+    }
+}
+
+struct Clock {
+    format: String,
+    text: String,
+}
+
+impl Clock {
+    fn new(format: String) -> Self {
+        let mut clock = Self {
+            format,
+            text: String::new(),
+        };
+        clock.tick();
+        clock
+    }
+}
+
+impl Widget for Clock {
+    fn draw(&self, canvas: &mut Canvas, rect: Rect) -> Result<()> {
+        let width = canvas.text_width(&self.text)?;
+        let x = i32::from(rect.x) + (i32::from(rect.width) - width) / 2;
+        canvas.draw_text(&self.text, x as i16, rect.y + canvas.line_height())
+    }
+
+    fn tick(&mut self) -> bool {
+        let text = Local::now().format(&self.format).to_string();
+        let changed = text != self.text;
+        self.text = text;
+        changed
+    }
+}
+
+struct Sysinfo {
+    lines: Vec<InfoLine>,
+    texts: Vec<String>,
+}
+
+impl Sysinfo {
+    fn new(lines: Vec<InfoLine>) -> Self {
+        let mut sysinfo = Self {
+            texts: Vec::with_capacity(lines.len()),
+            lines,
+        };
+        sysinfo.tick();
+        sysinfo
+    }
+}
+
+impl Widget for Sysinfo {
+    fn draw(&self, canvas: &mut Canvas, rect: Rect) -> Result<()> {
+        let mut y = rect.y;
+        for text in &self.texts {
+            y += canvas.line_height();
+            let width = canvas.text_width(text)?;
+            let x = i32::from(rect.x) + (i32::from(rect.width) - width) / 2;
+            canvas.draw_text(text, x as i16, y)?;
+        }
+        Ok(())
+    }
+
+    fn tick(&mut self) -> bool {
+        let texts: Vec<String> = self.lines.iter().filter_map(|line| line.text()).collect();
+        let changed = texts != self.texts;
+        self.texts = texts;
+        changed
+    }
+
+    fn lines(&self) -> usize {
+        self.lines.len()
+    }
+}
+
+/// Follows the state file, where unlocks and wrong PINs are timed, so that
+/// wrong PINs show up as they are entered.
+struct Access {
+    strings: Strings,
+    texts: [String; 2],
+}
+
+impl Access {
+    fn new(strings: Strings) -> Self {
+        let mut access = Self {
+            strings,
+            texts: Default::default(),
+        };
+        access.tick();
+        access
+    }
+
+    fn texts(&self, persisted: &Persisted) -> [String; 2] {
+        let time = |unix: Option<u64>| match unix {
+            Some(unix) => DateTime::<Local>::from(persist::from_unix(unix))
+                .format(ACCESS_TIME_FORMAT)
+                .to_string(),
+            None => self.strings.never.clone(),
+        };
+        [
+            self.strings
+                .last_unlocked
+                .replace("{time}", &time(persisted.last_unlocked)),
+            self.strings
+                .last_failed
+                .replace("{time}", &time(persisted.last_failed)),
+        ]
+    }
+}
+
+impl Widget for Access {
+    fn draw(&self, canvas: &mut Canvas, rect: Rect) -> Result<()> {
+        let mut y = rect.y;
+        for text in &self.texts {
+            y += canvas.line_height();
+            let width = canvas.text_width(text)?;
+            let x = i32::from(rect.x) + (i32::from(rect.width) - width) / 2;
+            canvas.draw_text(text, x as i16, y)?;
+        }
+        Ok(())
+    }
+
+    fn tick(&mut self) -> bool {
+        let texts = self.texts(&Persisted::load());
+        let changed = texts != self.texts;
+        self.texts = texts;
+        changed
+    }
+
+    fn lines(&self) -> usize {
+        self.texts.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_clock_only_changes_with_its_text() {
+        let mut clock = Clock::new("pinlock".to_owned());
+        assert!(!clock.tick());
+        clock.text.clear();
+        assert!(clock.tick());
+    }
+
+    #[test]
+    fn the_hostname_comes_after_the_widgets_of_its_corner() {
+        let mut config = Config {
+            widgets: vec![WidgetConfig {
+                kind: WidgetKind::Clock,
+                region: Region::TopLeft,
+            }],
+            ..Config::default()
+        };
+        assert_eq!(from_config(&config).len(), 1);
+        config.show_hostname = true;
+        config.hostname_position = Region::BottomRight;
+        let widgets = from_config(&config);
+        let regions: Vec<Region> = widgets.iter().map(|(region, _)| *region).collect();
+        // Left out where the hostname can't be read
+        if sysinfo::hostname().is_some() {
+            assert_eq!(regions, [Region::TopLeft, Region::BottomRight]);
+        }
+    }
+
+    #[test]
+    fn access_without_a_state_file_was_never() {
+        let access = Access::new(Strings::default());
+        let texts = access.texts(&Persisted {
+            last_failed: Some(0),
+            ..Persisted::default()
+        });
+        assert_eq!(texts[0], "Last unlocked: never");
+        assert!(texts[1].starts_with("Last failed attempt: 19"));
+    }
+
+    #[test]
+    fn rect_contains_its_edges_but_not_beyond() {
+        let rect = Rect {
+            x: 10,
+            y: 20,
+            width: 30,
+            height: 40,
+        };
+        assert!(rect.contains(10, 20));
+        assert!(rect.contains(39, 59));
+        assert!(!rect.contains(40, 30));
+        assert!(!rect.contains(20, 60));
+        assert!(!rect.contains(9, 30));
+    }
+}
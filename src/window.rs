@@ -0,0 +1,1135 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use log::{debug, warn};
+use serde::Deserialize;
+use x11rb::{
+    atom_manager,
+    connection::{Connection, RequestConnection},
+    cookie::VoidCookie,
+    errors::{ConnectionError, ReplyError},
+    protocol::{
+        shape::{self, ConnectionExt as _, SK, SO},
+        xproto::{
+            Allow, AtomEnum, ChangeWindowAttributesAux, ClipOrdering, ConfigureWindowAux,
+            ConnectionExt, CreateWindowAux, Cursor, EventMask, GetInputFocusReply, GrabMode,
+            GrabStatus, InputFocus, Keycode, MapState, ModMask, PropMode, Rectangle, Screen,
+            StackMode, WindowClass,
+        },
+        Event,
+    },
+    rust_connection::RustConnection,
+    wrapper::ConnectionExt as _,
+    CURRENT_TIME, NONE,
+};
+
+use crate::{
+    buffer::MAX_PIXMAP_SIDE,
+    config::Config,
+    cursor,
+    error::{GrabError, NotLocked},
+    image::ScreenRegion,
+    input::{KeyCombo, Keymap},
+    visual::VisualInfo,
+};
+
+/// How long to wait for the window manager to map a managed lock window.
+const MAP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How often the lock is checked before giving up, and the time between
+/// the checks, for a window manager to settle the window.
+const VERIFY_ATTEMPTS: u32 = 10;
+const VERIFY_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How long a grab held by another client is waited for when locking, and
+/// the time between the attempts. Locking from a window manager's hotkey
+/// races the keyboard grab the window manager holds while the key is down.
+const GRAB_TIMEOUT: Duration = Duration::from_secs(1);
+const GRAB_INTERVAL: Duration = Duration::from_millis(20);
+
+atom_manager! {
+    Atoms: AtomsCookie {
+        _NET_WM_STATE,
+        _NET_WM_STATE_FULLSCREEN,
+        _NET_WM_STATE_ABOVE,
+        _NET_WM_WINDOW_TYPE,
+        _NET_WM_WINDOW_TYPE_NORMAL,
+        _NET_WM_BYPASS_COMPOSITOR,
+    }
+}
+
+/// Wait for a clean up request to be processed, logging its failure.
+fn checked(what: &str, request: Result<VoidCookie<'_, RustConnection>, ConnectionError>) {
+    if let Err(err) = request
+        .map_err(ReplyError::from)
+        .and_then(VoidCookie::check)
+    {
+        warn!("Failed to {what}: {err}");
+    }
+}
+
+/// The window the keyboard and pointer grabs are taken on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GrabTarget {
+    #[default]
+    Window,
+    Root,
+}
+
+/// How the keyboard is grabbed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyboardGrabMode {
+    /// Key events are reported as they happen.
+    #[default]
+    Async,
+    /// The keyboard freezes after each reported key event, further events
+    /// being queued by the server until pinlock processed it and released
+    /// the keyboard with [`Window::thaw_keyboard`].
+    Sync,
+}
+
+impl KeyboardGrabMode {
+    fn grab_mode(self) -> GrabMode {
+        match self {
+            Self::Async => GrabMode::ASYNC,
+            Self::Sync => GrabMode::SYNC,
+        }
+    }
+
+    /// Whether the keyboard is frozen after `event` was reported.
+    pub fn freezes_after(self, event: &Event) -> bool {
+        self == Self::Sync && matches!(event, Event::KeyPress(_) | Event::KeyRelease(_))
+    }
+}
+
+/// The lock window, holding the keyboard and pointer grabs while it lives.
+pub struct Window<'connection> {
+    pub id: u32,
+    pub visual: VisualInfo,
+    /// Invisible window spanning the whole root that the pointer is confined to
+    confine: Option<u32>,
+    /// The window the grabs are taken on
+    grab_window: u32,
+    root: u32,
+    /// Passive grabs of `block_keys` on the root
+    key_grabs: Vec<(Keycode, ModMask)>,
+    grab_pointer: bool,
+    pub keyboard_mode: KeyboardGrabMode,
+    /// Additional master devices grabbed through XInput2
+    #[cfg(feature = "xinput2")]
+    device_grabs: Vec<u16>,
+    pub width: u16,
+    pub height: u16,
+    /// The focus before locking, given back on unlock with `restore_focus`
+    previous_focus: Option<(InputFocus, u32)>,
+    cleaned_up: bool,
+    conn: &'connection RustConnection,
+}
+
+impl<'connection> Window<'connection> {
+    /// Create and map the lock window and grab the input. `keymap` finds
+    /// the keys of `block_keys`.
+    pub fn create(
+        connection: &'connection RustConnection,
+        screen: &Screen,
+        config: &Config,
+        keymap: &Keymap,
+    ) -> Result<Self> {
+        // Before the lock window could take it
+        let previous_focus = if config.restore_focus {
+            focus_to_restore(&connection.get_input_focus()?.reply()?)
+        } else {
+            None
+        };
+
+        let confine = if config.grab_pointer && config.confine_pointer {
+            Some(Self::create_confine_window(connection, screen)?)
+        } else {
+            None
+        };
+
+        let win = connection.generate_id()?;
+        let visual = VisualInfo::for_screen(screen)?;
+        let (width, height) = (screen.width_in_pixels, screen.height_in_pixels);
+
+        let settings = CreateWindowAux::default()
+            .override_redirect(u32::from(!config.use_fullscreen_hint))
+            .background_pixel(config.theme.background.pixel())
+            .event_mask(
+                EventMask::EXPOSURE
+                    | EventMask::BUTTON_PRESS
+                    | EventMask::BUTTON_RELEASE
+                    | EventMask::POINTER_MOTION
+                    | EventMask::ENTER_WINDOW
+                    | EventMask::LEAVE_WINDOW
+                    | EventMask::KEY_PRESS
+                    | EventMask::KEY_RELEASE
+                    // KeymapNotify after EnterNotify and FocusIn
+                    | EventMask::KEYMAP_STATE
+                    | EventMask::FOCUS_CHANGE
+                    // ConfigureNotify when a window manager resizes us
+                    | EventMask::STRUCTURE_NOTIFY,
+            );
+
+        // Create the window
+        connection.create_window(
+            visual.depth,              // depth
+            win,                       // window Id
+            screen.root,               // parent window
+            0,                         // x
+            0,                         // y
+            width,                     // width
+            height,                    // height
+            0,                         // border width
+            WindowClass::INPUT_OUTPUT, // class
+            visual.visual,             // visual
+            &settings,
+        )?; // masks, not used yet
+
+        if config.use_fullscreen_hint || config.bypass_compositor {
+            let atoms = Atoms::new(connection)?.reply()?;
+            if config.use_fullscreen_hint {
+                Self::set_fullscreen_hints(connection, win, &atoms)?;
+            }
+            if config.bypass_compositor {
+                // 1 asks for compositing to be turned off
+                connection.change_property32(
+                    PropMode::REPLACE,
+                    win,
+                    atoms._NET_WM_BYPASS_COMPOSITOR,
+                    AtomEnum::CARDINAL,
+                    &[1],
+                )?;
+            }
+        }
+
+        if !config.passthrough_regions.is_empty() {
+            pass_through(connection, win, &config.passthrough_regions, width, height)?;
+        }
+
+        // Map the window on the screen
+        connection.map_window(win)?;
+        // Paint the background color right away rather than on the first
+        // Expose, so that the window never shows stale contents
+        connection.clear_area(false, win, 0, 0, 0, 0)?;
+
+        connection.flush()?;
+
+        if config.use_fullscreen_hint {
+            Self::wait_until_viewable(connection, win)?;
+        }
+
+        let grab_window = match config.grab_on {
+            GrabTarget::Window => win,
+            GrabTarget::Root => screen.root,
+        };
+
+        // From here on, dropping the window releases whatever was grabbed
+        #[cfg_attr(not(feature = "xinput2"), allow(unused_mut))]
+        let mut window = Self {
+            id: win,
+            visual,
+            confine,
+            grab_window,
+            root: screen.root,
+            key_grabs: Vec::new(),
+            grab_pointer: config.grab_pointer,
+            keyboard_mode: config.keyboard_grab_mode,
+            #[cfg(feature = "xinput2")]
+            device_grabs: Vec::new(),
+            width,
+            height,
+            previous_focus,
+            cleaned_up: false,
+            conn: connection,
+        };
+
+        window.grab_keys(&config.block_keys, keymap);
+
+        let cursor = cursor::create(connection, win, &config.cursor)?;
+        if cursor != NONE {
+            connection
+                .change_window_attributes(win, &ChangeWindowAttributesAux::new().cursor(cursor))?;
+        }
+
+        connection.set_input_focus(InputFocus::PARENT, win, CURRENT_TIME)?;
+        grab_input(&window, cursor, PartialGrab::Release, GRAB_TIMEOUT)?;
+
+        #[cfg(feature = "xinput2")]
+        window.grab_other_masters()?;
+
+        if cursor != NONE {
+            connection.free_cursor(cursor)?;
+        }
+
+        connection.flush()?;
+        window.verify()?;
+
+        Ok(window)
+    }
+
+    /// Make sure the lock is in effect, failing with [`NotLocked`] if not:
+    /// the window is viewable, holds the input focus and nothing visible is
+    /// stacked above it. The grabs are confirmed as they are taken. The
+    /// window is raised and focused again between the attempts.
+    fn verify(&self) -> Result<()> {
+        let mut attempt = 1;
+        loop {
+            let Some(check) = self.failed_check()? else {
+                return Ok(());
+            };
+            if attempt == VERIFY_ATTEMPTS {
+                return Err(NotLocked { check }.into());
+            }
+            debug!("Lock check failed, {check}, trying again");
+            self.conn.configure_window(
+                self.id,
+                &ConfigureWindowAux::new().stack_mode(StackMode::ABOVE),
+            )?;
+            self.conn
+                .set_input_focus(InputFocus::PARENT, self.id, CURRENT_TIME)?;
+            self.conn.flush()?;
+            thread::sleep(VERIFY_INTERVAL);
+            attempt += 1;
+        }
+    }
+
+    /// The first check of [`Self::verify`] that fails, if any.
+    fn failed_check(&self) -> Result<Option<&'static str>> {
+        let conn = self.conn;
+        if conn.get_window_attributes(self.id)?.reply()?.map_state != MapState::VIEWABLE {
+            return Ok(Some("the lock window isn't viewable"));
+        }
+        if conn.get_input_focus()?.reply()?.focus != self.id {
+            return Ok(Some("the lock window doesn't have the input focus"));
+        }
+
+        // Under a window manager, its frame is what is stacked
+        let mut top_level = self.id;
+        loop {
+            let parent = conn.query_tree(top_level)?.reply()?.parent;
+            if parent == self.root || parent == NONE {
+                break;
+            }
+            top_level = parent;
+        }
+        let children = conn.query_tree(self.root)?.reply()?.children;
+        if !children.contains(&top_level) {
+            return Ok(Some("the lock window isn't on the screen"));
+        }
+        // From the top down to the lock window, only windows that show count
+        for &above in children
+            .iter()
+            .rev()
+            .take_while(|&&child| child != top_level)
+        {
+            // Windows destroyed meanwhile don't cover anything either
+            let Ok(attributes) = conn.get_window_attributes(above)?.reply() else {
+                continue;
+            };
+            if attributes.map_state == MapState::VIEWABLE
+                && attributes.class == WindowClass::INPUT_OUTPUT
+            {
+                return Ok(Some("another window is stacked above the lock window"));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Make sure the lock is still in effect. The window is raised and
+    /// focused again should a check of [`Self::verify`] fail, and the grabs
+    /// are taken again either way: there is no asking whether they are still
+    /// held, but grabbing what the lock window holds already succeeds. Each
+    /// grab is tried once, one that another client holds is left to the next
+    /// check, so that the lock screen doesn't stall meanwhile.
+    pub fn check_grabs(&self) -> Result<()> {
+        if let Some(check) = self.failed_check()? {
+            warn!("Lock check failed, {check}, raising the lock window again");
+            self.conn.configure_window(
+                self.id,
+                &ConfigureWindowAux::new().stack_mode(StackMode::ABOVE),
+            )?;
+            self.conn
+                .set_input_focus(InputFocus::PARENT, self.id, CURRENT_TIME)?;
+        }
+        // The cursor of the grab window is shown. Whatever is still held
+        // stays held, half the input is better than none.
+        grab_input(self, NONE, PartialGrab::Keep, Duration::ZERO)?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    /// Ask for the grabs of [`grab_input`] once. Grabbing what the
+    /// lock window holds already succeeds again.
+    fn try_grab_input(&self, cursor: Cursor) -> Result<Vec<(InputDevice, GrabStatus)>> {
+        let keyboard = self.conn.grab_keyboard(
+            true,
+            self.grab_window,
+            CURRENT_TIME,
+            GrabMode::ASYNC,
+            self.keyboard_mode.grab_mode(),
+        )?;
+        let pointer = if self.grab_pointer {
+            Some(self.conn.grab_pointer(
+                true,
+                self.grab_window,
+                EventMask::NO_EVENT,
+                GrabMode::ASYNC,
+                GrabMode::ASYNC,
+                self.confine.unwrap_or(NONE),
+                cursor,
+                CURRENT_TIME,
+            )?)
+        } else {
+            None
+        };
+
+        let mut statuses = vec![(InputDevice::Keyboard, keyboard.reply()?.status)];
+        if let Some(pointer) = pointer {
+            statuses.push((InputDevice::Pointer, pointer.reply()?.status));
+        }
+        Ok(statuses)
+    }
+
+    /// Passively grab the key combos on the root, with and without the
+    /// locking modifiers. While the keyboard is grabbed, no passive grab
+    /// fires, so these only come into play before the keyboard grab is taken
+    /// and while it is lost. A combo that another client, usually the window
+    /// manager, grabbed already can't be grabbed and is only logged.
+    fn grab_keys(&mut self, combos: &[KeyCombo], keymap: &Keymap) {
+        for combo in combos {
+            let keycodes = keymap.keycodes(combo.keysym);
+            if keycodes.is_empty() {
+                warn!("No key for {combo:?} on this keyboard");
+            }
+            for keycode in keycodes {
+                let none = ModMask::from(0u16);
+                for locking in [
+                    none,
+                    ModMask::LOCK,
+                    ModMask::M2,
+                    ModMask::LOCK | ModMask::M2,
+                ] {
+                    let modifiers = combo.modifiers | locking;
+                    let grab = self.conn.grab_key(
+                        true,
+                        self.root,
+                        modifiers,
+                        keycode,
+                        GrabMode::ASYNC,
+                        GrabMode::ASYNC,
+                    );
+                    match grab.map_err(ReplyError::from).and_then(VoidCookie::check) {
+                        Ok(()) => self.key_grabs.push((keycode, modifiers)),
+                        Err(err) => warn!("Failed to grab {combo:?} on keycode {keycode}: {err}"),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Release the grabs and take the windows down, in that order. Every
+    /// step is attempted even if an earlier one failed, which is only
+    /// logged. Does nothing when called again.
+    pub fn cleanup(&mut self) {
+        if self.cleaned_up {
+            return;
+        }
+        self.cleaned_up = true;
+
+        let devices: &[_] = if self.grab_pointer {
+            &[InputDevice::Pointer, InputDevice::Keyboard]
+        } else {
+            &[InputDevice::Keyboard]
+        };
+        clean_up(self, devices, self.previous_focus);
+    }
+
+    /// Let the server report the next key event of a synchronously grabbed
+    /// keyboard, after which it freezes again.
+    pub fn thaw_keyboard(&self) -> Result<()> {
+        self.conn.allow_events(Allow::SYNC_KEYBOARD, CURRENT_TIME)?;
+        self.conn.flush()?;
+        Ok(())
+    }
+
+    /// The core grabs only cover the master devices of the core pointer and
+    /// keyboard. Grab every other master device too, so that keyboards and
+    /// pointers of further seats can't reach the desktop. Without XInput2,
+    /// the core grabs are all there is.
+    #[cfg(feature = "xinput2")]
+    fn grab_other_masters(&mut self) -> Result<()> {
+        use x11rb::{
+            connection::RequestConnection,
+            protocol::xinput::{self, ConnectionExt as _, DeviceType, GrabOwner},
+        };
+
+        let conn = self.conn;
+        if conn
+            .extension_information(xinput::X11_EXTENSION_NAME)?
+            .is_none()
+            || conn.xinput_xi_query_version(2, 0)?.reply()?.major_version < 2
+        {
+            warn!("XInput2 is unavailable, only the core devices are grabbed");
+            return Ok(());
+        }
+
+        let core_pointer = conn.xinput_xi_get_client_pointer(NONE)?.reply()?.deviceid;
+        let masters = conn
+            .xinput_xi_query_device(xinput::Device::ALL_MASTER)?
+            .reply()?
+            .infos;
+        let core_keyboard = masters
+            .iter()
+            .find(|device| device.deviceid == core_pointer)
+            .map(|device| device.attachment);
+
+        for device in &masters {
+            if device.deviceid == core_pointer || Some(device.deviceid) == core_keyboard {
+                continue;
+            }
+            let pointer = device.type_ == DeviceType::MASTER_POINTER;
+            if pointer && !self.grab_pointer {
+                continue;
+            }
+
+            let status = conn
+                .xinput_xi_grab_device(
+                    self.grab_window,
+                    CURRENT_TIME,
+                    NONE,
+                    device.deviceid,
+                    GrabMode::ASYNC,
+                    GrabMode::ASYNC,
+                    GrabOwner::OWNER,
+                    &[],
+                )?
+                .reply()?
+                .status;
+            if status != GrabStatus::SUCCESS {
+                return Err(GrabError {
+                    device: if pointer { "pointer" } else { "keyboard" },
+                    status,
+                }
+                .into());
+            }
+            self.device_grabs.push(device.deviceid);
+        }
+        Ok(())
+    }
+
+    /// Ask a cooperative window manager to show the window fullscreen and on
+    /// top, instead of bypassing it with override-redirect.
+    fn set_fullscreen_hints(connection: &RustConnection, win: u32, atoms: &Atoms) -> Result<()> {
+        connection.change_property32(
+            PropMode::REPLACE,
+            win,
+            atoms._NET_WM_STATE,
+            AtomEnum::ATOM,
+            &[atoms._NET_WM_STATE_FULLSCREEN, atoms._NET_WM_STATE_ABOVE],
+        )?;
+        connection.change_property32(
+            PropMode::REPLACE,
+            win,
+            atoms._NET_WM_WINDOW_TYPE,
+            AtomEnum::ATOM,
+            &[atoms._NET_WM_WINDOW_TYPE_NORMAL],
+        )?;
+        Ok(())
+    }
+
+    /// A managed window only becomes viewable once the window manager has
+    /// processed the map request, and grabbing before that fails.
+    fn wait_until_viewable(connection: &RustConnection, win: u32) -> Result<()> {
+        let start = Instant::now();
+        while connection.get_window_attributes(win)?.reply()?.map_state != MapState::VIEWABLE {
+            if start.elapsed() > MAP_TIMEOUT {
+                bail!("The window manager did not map the lock window");
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        Ok(())
+    }
+
+    /// Create and map an input-only window covering the entire root, so the
+    /// pointer stays confined to the screen area spanned by all outputs. It
+    /// is mapped first, so that the lock window stacks on top of it.
+    fn create_confine_window(connection: &RustConnection, screen: &Screen) -> Result<u32> {
+        let win = connection.generate_id()?;
+
+        connection.create_window(
+            0,                       // depth, must be 0 for input only windows
+            win,                     // window Id
+            screen.root,             // parent window
+            0,                       // x
+            0,                       // y
+            screen.width_in_pixels,  // width
+            screen.height_in_pixels, // height
+            0,                       // border width
+            WindowClass::INPUT_ONLY, // class
+            0,                       // visual, copied from parent
+            &CreateWindowAux::default().override_redirect(1),
+        )?;
+        connection.map_window(win)?;
+
+        Ok(win)
+    }
+}
+
+impl<'connection> Drop for Window<'connection> {
+    fn drop(&mut self) {
+        self.cleanup();
+    }
+}
+
+/// The focus to give back on unlock, from `GetInputFocus` before locking:
+/// a window or PointerRoot. Without any focus, there is nothing to give
+/// back.
+fn focus_to_restore(reply: &GetInputFocusReply) -> Option<(InputFocus, u32)> {
+    (reply.focus != NONE).then_some((reply.revert_to, reply.focus))
+}
+
+/// The requests [`grab_input`] and [`clean_up`] make, apart from the
+/// connection so that tests can record them.
+trait InputRequests {
+    /// Ask for every grab once, with the status of each
+    fn try_grab(&self, cursor: Cursor) -> Result<Vec<(InputDevice, GrabStatus)>>;
+    fn ungrab(&self, device: InputDevice) -> Result<(), ConnectionError>;
+    fn thaw_keyboard(&self) -> Result<()>;
+    fn keyboard_mode(&self) -> KeyboardGrabMode;
+    fn flush(&self) -> Result<(), ConnectionError>;
+    /// Release the passive grabs of keys and the XInput2 device grabs
+    fn release_other_grabs(&self);
+    /// Unmap and destroy the windows
+    fn take_down(&self);
+    fn restore_focus(&self, revert_to: InputFocus, focus: u32);
+}
+
+impl InputRequests for Window<'_> {
+    fn try_grab(&self, cursor: Cursor) -> Result<Vec<(InputDevice, GrabStatus)>> {
+        self.try_grab_input(cursor)
+    }
+
+    fn ungrab(&self, device: InputDevice) -> Result<(), ConnectionError> {
+        device.ungrab(self.conn)
+    }
+
+    fn thaw_keyboard(&self) -> Result<()> {
+        Window::thaw_keyboard(self)
+    }
+
+    fn keyboard_mode(&self) -> KeyboardGrabMode {
+        self.keyboard_mode
+    }
+
+    fn flush(&self) -> Result<(), ConnectionError> {
+        self.conn.flush()
+    }
+
+    fn release_other_grabs(&self) {
+        for &(keycode, modifiers) in &self.key_grabs {
+            checked(
+                "ungrab a key",
+                self.conn.ungrab_key(keycode, self.root, modifiers),
+            );
+        }
+        #[cfg(feature = "xinput2")]
+        for &device in &self.device_grabs {
+            use x11rb::protocol::xinput::ConnectionExt as _;
+
+            checked(
+                "ungrab an XInput2 device",
+                self.conn.xinput_xi_ungrab_device(CURRENT_TIME, device),
+            );
+        }
+    }
+
+    fn take_down(&self) {
+        checked("unmap the lock window", self.conn.unmap_window(self.id));
+        checked("destroy the lock window", self.conn.destroy_window(self.id));
+        if let Some(confine) = self.confine {
+            checked(
+                "destroy the confine window",
+                self.conn.destroy_window(confine),
+            );
+        }
+    }
+
+    /// Give the focus back to `focus`, and check with a round trip that it
+    /// is there, past the grabs released before.
+    fn restore_focus(&self, revert_to: InputFocus, focus: u32) {
+        // Fails if the window was unmapped or destroyed meanwhile, leaving
+        // the focus with the root
+        let restored = self
+            .conn
+            .set_input_focus(revert_to, focus, CURRENT_TIME)
+            .map_err(ReplyError::from)
+            .and_then(VoidCookie::check);
+        if let Err(err) = restored {
+            debug!("Failed to give the input focus back to {focus:#x}: {err}");
+            return;
+        }
+        match self.conn.get_input_focus() {
+            Ok(cookie) => match cookie.reply() {
+                Ok(reply) if reply.focus == focus => {}
+                Ok(reply) => debug!(
+                    "The input focus went to {:#x} rather than back to {focus:#x}",
+                    reply.focus
+                ),
+                Err(err) => warn!("Failed to check the input focus: {err}"),
+            },
+            Err(err) => warn!("Failed to check the input focus: {err}"),
+        }
+    }
+}
+
+/// Grab the keyboard and, with `grab_pointer`, the pointer, showing
+/// `cursor`. Grabs another client holds or froze are tried again for up
+/// to `patience`. Should either grab still be refused, `partial` decides
+/// what happens to the other before failing. Without the pointer grab,
+/// clicks still land on the lock window as it covers the screen, but
+/// other clients may grab the pointer meanwhile.
+fn grab_input(
+    input: &impl InputRequests,
+    cursor: Cursor,
+    partial: PartialGrab,
+    patience: Duration,
+) -> Result<()> {
+    let deadline = Instant::now() + patience;
+    let statuses = loop {
+        let statuses = input.try_grab(cursor)?;
+        let retry = statuses.iter().any(|&(_, status)| worth_retrying(status));
+        if !retry || Instant::now() >= deadline {
+            break statuses;
+        }
+        thread::sleep(GRAB_INTERVAL);
+    };
+
+    if let Err((err, grabbed)) = all_grabbed(&statuses) {
+        match partial {
+            PartialGrab::Release => {
+                for device in grabbed {
+                    input.ungrab(device)?;
+                }
+                input.flush()?;
+            }
+            // A kept synchronous grab still mustn't stay frozen
+            PartialGrab::Keep => {
+                if input.keyboard_mode() == KeyboardGrabMode::Sync
+                    && grabbed.contains(&InputDevice::Keyboard)
+                {
+                    input.thaw_keyboard()?;
+                }
+            }
+        }
+        return Err(err.into());
+    }
+
+    // A synchronous grab starts out frozen
+    if input.keyboard_mode() == KeyboardGrabMode::Sync {
+        input.thaw_keyboard()?;
+    }
+    Ok(())
+}
+
+/// Release the grabs of `devices` and the others, take the windows down
+/// and give `previous_focus` back, in that order, for [`Window::cleanup`].
+fn clean_up(
+    lock: &impl InputRequests,
+    devices: &[InputDevice],
+    previous_focus: Option<(InputFocus, u32)>,
+) {
+    for &device in devices {
+        if let Err(err) = lock.ungrab(device) {
+            warn!("Failed to ungrab the {}: {err}", device.name());
+        }
+    }
+    lock.release_other_grabs();
+    lock.take_down();
+    if let Some((revert_to, focus)) = previous_focus {
+        lock.restore_focus(revert_to, focus);
+    }
+    if let Err(err) = lock.flush() {
+        warn!("Failed to send the clean up requests: {err}");
+    }
+}
+
+/// A core input device grabbed by the lock window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InputDevice {
+    Keyboard,
+    Pointer,
+}
+
+impl InputDevice {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Keyboard => "keyboard",
+            Self::Pointer => "pointer",
+        }
+    }
+
+    fn ungrab(self, connection: &RustConnection) -> Result<(), ConnectionError> {
+        match self {
+            Self::Keyboard => connection.ungrab_keyboard(CURRENT_TIME)?,
+            Self::Pointer => connection.ungrab_pointer(CURRENT_TIME)?,
+        };
+        Ok(())
+    }
+}
+
+/// What [`grab_input`] does with the grabs it got when another was
+/// refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PartialGrab {
+    /// Give them up, so that locking fails cleanly instead of leaving half
+    /// the input grabbed while the other half still reaches the desktop.
+    Release,
+    /// Keep them, for grabbing again while locked.
+    Keep,
+}
+
+/// Whether a grab refused with `status` may succeed later on: another
+/// client holds the device or froze it, and may let go.
+fn worth_retrying(status: GrabStatus) -> bool {
+    status == GrabStatus::ALREADY_GRABBED || status == GrabStatus::FROZEN
+}
+
+/// Whether every device of `statuses` was grabbed. If not, the error for
+/// the first that wasn't, along with those that were.
+fn all_grabbed(
+    statuses: &[(InputDevice, GrabStatus)],
+) -> Result<(), (GrabError, Vec<InputDevice>)> {
+    let Some(&(device, status)) = statuses
+        .iter()
+        .find(|(_, status)| *status != GrabStatus::SUCCESS)
+    else {
+        return Ok(());
+    };
+    let grabbed = statuses
+        .iter()
+        .filter(|(_, status)| *status == GrabStatus::SUCCESS)
+        .map(|&(device, _)| device)
+        .collect();
+    Err((
+        GrabError {
+            device: device.name(),
+            status,
+        },
+        grabbed,
+    ))
+}
+
+/// Holds off every other client while it lives, for `grab_server`.
+pub struct ServerGrab<'connection> {
+    conn: &'connection RustConnection,
+    started: Instant,
+}
+
+impl<'connection> ServerGrab<'connection> {
+    pub fn new(connection: &'connection RustConnection) -> Result<Self> {
+        connection.grab_server()?;
+        Ok(Self {
+            conn: connection,
+            started: Instant::now(),
+        })
+    }
+
+    /// Let the other clients go on. Returns how long they were held off.
+    pub fn release(self) -> Duration {
+        self.started.elapsed()
+    }
+}
+
+impl Drop for ServerGrab<'_> {
+    fn drop(&mut self) {
+        let _ = self.conn.ungrab_server();
+        let _ = self.conn.flush();
+        debug!("Held the server for {:?}", self.started.elapsed());
+    }
+}
+
+/// Leave `regions` of a `width` by `height` screen out of the input region
+/// of `window`, so that clicks there reach the windows below. Without the
+/// SHAPE extension, the window goes on taking every click.
+fn pass_through(
+    conn: &RustConnection,
+    window: u32,
+    regions: &[ScreenRegion],
+    width: u16,
+    height: u16,
+) -> Result<()> {
+    if conn
+        .extension_information(shape::X11_EXTENSION_NAME)?
+        .is_none()
+    {
+        warn!("Ignoring passthrough_regions, the X server has no SHAPE extension");
+        return Ok(());
+    }
+    // Larger than any screen, so that the window takes input wherever it
+    // grows to but the holes
+    let everywhere = Rectangle {
+        x: 0,
+        y: 0,
+        width: MAX_PIXMAP_SIDE,
+        height: MAX_PIXMAP_SIDE,
+    };
+    let holes = holes(regions, width, height);
+    conn.shape_rectangles(
+        SO::SET,
+        SK::INPUT,
+        ClipOrdering::UNSORTED,
+        window,
+        0,
+        0,
+        &[everywhere],
+    )?;
+    conn.shape_rectangles(
+        SO::SUBTRACT,
+        SK::INPUT,
+        ClipOrdering::UNSORTED,
+        window,
+        0,
+        0,
+        &holes,
+    )?;
+    Ok(())
+}
+
+/// The parts of `regions` on a `width` by `height` screen.
+fn holes(regions: &[ScreenRegion], width: u16, height: u16) -> Vec<Rectangle> {
+    regions
+        .iter()
+        .filter_map(|&ScreenRegion(region)| {
+            let (x, y) = (i32::from(region.x), i32::from(region.y));
+            let right = (x + i32::from(region.width)).min(width.into());
+            let bottom = (y + i32::from(region.height)).min(height.into());
+            let described = format!(
+                "{},{},{},{}",
+                region.x, region.y, region.width, region.height
+            );
+            if right <= x || bottom <= y {
+                warn!("Ignoring passthrough region {described}, it is off the {width}x{height} screen");
+                return None;
+            }
+            let hole = Rectangle {
+                width: (right - x) as u16,
+                height: (bottom - y) as u16,
+                ..region
+            };
+            if hole != region {
+                warn!(
+                    "Passing through only part of {described}, it reaches past the {width}x{height} screen"
+                );
+            }
+            Some(hole)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use x11rb::protocol::xproto::{KeyPressEvent, MotionNotifyEvent};
+
+    use super::*;
+
+    #[test]
+    fn passthrough_holes_stay_on_the_screen() {
+        let region = |region: &str| ScreenRegion::try_from(region.to_owned()).unwrap();
+        let hole = |x, y, width, height| Rectangle {
+            x,
+            y,
+            width,
+            height,
+        };
+        assert_eq!(
+            holes(
+                &[
+                    region("1800,0,120,40"),
+                    region("1900,1000,100,100"),
+                    region("2000,0,10,10"),
+                ],
+                1920,
+                1080
+            ),
+            [hole(1800, 0, 120, 40), hole(1900, 1000, 20, 80)]
+        );
+    }
+
+    #[test]
+    fn sync_mode_releases_the_keyboard_after_each_key() {
+        let mode = KeyboardGrabMode::Sync;
+        assert!(mode.freezes_after(&Event::KeyPress(KeyPressEvent::default())));
+        assert!(mode.freezes_after(&Event::KeyRelease(KeyPressEvent::default())));
+        assert!(!mode.freezes_after(&Event::MotionNotify(MotionNotifyEvent::default())));
+    }
+
+    #[test]
+    fn async_mode_never_freezes() {
+        let mode = KeyboardGrabMode::Async;
+        assert!(!mode.freezes_after(&Event::KeyPress(KeyPressEvent::default())));
+        assert!(!mode.freezes_after(&Event::KeyRelease(KeyPressEvent::default())));
+    }
+
+    /// Requests a [`grab_input`] or [`clean_up`] made, in order
+    #[derive(Debug, PartialEq)]
+    enum Request {
+        Grab,
+        Ungrab(InputDevice),
+        Thaw,
+        Flush,
+        ReleaseOtherGrabs,
+        TakeDown,
+        Focus(InputFocus, u32),
+    }
+
+    /// Answers every grab with `statuses`
+    struct Recorder {
+        statuses: Vec<(InputDevice, GrabStatus)>,
+        keyboard_mode: KeyboardGrabMode,
+        requests: RefCell<Vec<Request>>,
+    }
+
+    impl Recorder {
+        fn new(keyboard: GrabStatus, pointer: GrabStatus) -> Self {
+            Self {
+                statuses: vec![
+                    (InputDevice::Keyboard, keyboard),
+                    (InputDevice::Pointer, pointer),
+                ],
+                keyboard_mode: KeyboardGrabMode::Async,
+                requests: RefCell::default(),
+            }
+        }
+
+        fn record(&self, request: Request) {
+            self.requests.borrow_mut().push(request);
+        }
+    }
+
+    impl InputRequests for Recorder {
+        fn try_grab(&self, _: Cursor) -> Result<Vec<(InputDevice, GrabStatus)>> {
+            self.record(Request::Grab);
+            Ok(self.statuses.clone())
+        }
+
+        fn ungrab(&self, device: InputDevice) -> Result<(), ConnectionError> {
+            self.record(Request::Ungrab(device));
+            Ok(())
+        }
+
+        fn thaw_keyboard(&self) -> Result<()> {
+            self.record(Request::Thaw);
+            Ok(())
+        }
+
+        fn keyboard_mode(&self) -> KeyboardGrabMode {
+            self.keyboard_mode
+        }
+
+        fn flush(&self) -> Result<(), ConnectionError> {
+            self.record(Request::Flush);
+            Ok(())
+        }
+
+        fn release_other_grabs(&self) {
+            self.record(Request::ReleaseOtherGrabs);
+        }
+
+        fn take_down(&self) {
+            self.record(Request::TakeDown);
+        }
+
+        fn restore_focus(&self, revert_to: InputFocus, focus: u32) {
+            self.record(Request::Focus(revert_to, focus));
+        }
+    }
+
+    #[test]
+    fn a_refused_keyboard_releases_the_granted_pointer() {
+        let refused = Recorder::new(GrabStatus::NOT_VIEWABLE, GrabStatus::SUCCESS);
+        let err = grab_input(&refused, NONE, PartialGrab::Release, Duration::ZERO).unwrap_err();
+        let err = err.downcast::<GrabError>().unwrap();
+        assert_eq!(err.device, "keyboard");
+        assert_eq!(err.status, GrabStatus::NOT_VIEWABLE);
+        assert_eq!(
+            *refused.requests.borrow(),
+            [
+                Request::Grab,
+                Request::Ungrab(InputDevice::Pointer),
+                Request::Flush
+            ]
+        );
+
+        // While locked, the pointer stays held
+        let refused = Recorder::new(GrabStatus::ALREADY_GRABBED, GrabStatus::SUCCESS);
+        assert!(grab_input(&refused, NONE, PartialGrab::Keep, Duration::ZERO).is_err());
+        assert_eq!(*refused.requests.borrow(), [Request::Grab]);
+    }
+
+    #[test]
+    fn only_grabs_held_by_others_are_tried_again() {
+        assert!(worth_retrying(GrabStatus::ALREADY_GRABBED));
+        assert!(worth_retrying(GrabStatus::FROZEN));
+        assert!(!worth_retrying(GrabStatus::NOT_VIEWABLE));
+        assert!(!worth_retrying(GrabStatus::INVALID_TIME));
+        assert!(!worth_retrying(GrabStatus::SUCCESS));
+    }
+
+    #[test]
+    fn the_focus_before_locking_is_given_back() {
+        let focused = GetInputFocusReply {
+            revert_to: InputFocus::PARENT,
+            focus: 0x1a0_0007,
+            ..Default::default()
+        };
+        assert_eq!(
+            focus_to_restore(&focused),
+            Some((InputFocus::PARENT, 0x1a0_0007))
+        );
+
+        let unfocused = GetInputFocusReply {
+            focus: NONE,
+            ..Default::default()
+        };
+        assert_eq!(focus_to_restore(&unfocused), None);
+
+        // Once the grabs are gone and the lock window is down
+        let lock = Recorder::new(GrabStatus::SUCCESS, GrabStatus::SUCCESS);
+        clean_up(
+            &lock,
+            &[InputDevice::Pointer, InputDevice::Keyboard],
+            focus_to_restore(&focused),
+        );
+        assert_eq!(
+            *lock.requests.borrow(),
+            [
+                Request::Ungrab(InputDevice::Pointer),
+                Request::Ungrab(InputDevice::Keyboard),
+                Request::ReleaseOtherGrabs,
+                Request::TakeDown,
+                Request::Focus(InputFocus::PARENT, 0x1a0_0007),
+                Request::Flush,
+            ]
+        );
+
+        let lock = Recorder::new(GrabStatus::SUCCESS, GrabStatus::SUCCESS);
+        clean_up(
+            &lock,
+            &[InputDevice::Keyboard],
+            focus_to_restore(&unfocused),
+        );
+        assert!(!lock
+            .requests
+            .borrow()
+            .iter()
+            .any(|request| matches!(request, Request::Focus(..))));
+    }
+}